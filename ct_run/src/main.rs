@@ -154,8 +154,9 @@ fn main() {
   //  let mut child = cmd.spawn().unwrap();
   //  let exit_status = child.wait().unwrap();
   //  println!("erl exit status: {}", exit_status);
-  erlangrt::lib_main::start_emulator(&mut erl_args);
+  let status = erlangrt::lib_main::start_emulator(&mut erl_args);
   println!("ct_run: Finished.");
+  std::process::exit(status);
 }
 
 fn add_script_start(args: &mut ErlStartArgs) {
@@ -1,5 +1,5 @@
 use erlangrt::{command_line_args::ErlStartArgs, lib_main::start_emulator};
-use std::env;
+use std::{env, process};
 
 fn main() {
   let in_args: Vec<String> = env::args().collect();
@@ -17,6 +17,7 @@ fn main() {
   ];
 
   // Get going now
-  start_emulator(&mut args);
+  let status = start_emulator(&mut args);
   println!("erlexec: Finished.");
+  process::exit(status);
 }
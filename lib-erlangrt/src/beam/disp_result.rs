@@ -5,6 +5,9 @@ pub enum YieldType {
   EndOfTheQueue,
   /// The process gives up running for infinite receive or a similar reason.
   InfiniteWait,
+  /// The process waits for a message with a timeout, the receive timer is
+  /// already started.
+  TimedWait,
 }
 
 /// Enum is used by VM dispatch handlers for opcodes to indicate whether to
@@ -8,7 +8,7 @@ fn module() -> &'static str {
 }
 
 impl LoaderState {
-  pub fn stage2_register_atoms(&mut self, code_server: &mut CodeServer) {
+  pub fn stage2_register_atoms(&mut self, code_server: &CodeServer) {
     self.vm_atoms.reserve(self.beam_file.atoms.len());
     for a in &self.beam_file.atoms {
       self.vm_atoms.push(atom::from_str(a));
@@ -23,6 +23,7 @@ use crate::{
     code::{opcode::RawOpcode, Code, CodeOffset},
     code_srv::CodeServer,
     function::FunEntry,
+    heap::literal_area,
     module::{self, Module, VersionedModuleName},
   },
   fail::RtResult,
@@ -140,16 +141,18 @@ impl LoaderState {
     {
       mem::swap(&mut self.funs, &mut newmod.funs);
       mem::swap(&mut self.code, &mut newmod.code);
-      mem::swap(&mut self.beam_file.lit_heap, &mut newmod.lit_heap);
+      mem::swap(&mut self.beam_file.lit_heap, &mut *newmod.lit_heap);
       mem::swap(&mut self.lambdas, &mut newmod.lambdas);
     }
+    // Literals are shared with the processes instead of being copied
+    literal_area::register(newmod.lit_heap.get_used_area());
 
     Ok(newmod)
   }
 
   //============================================================================
 
-  fn set_mod_id(&mut self, code_server: &mut CodeServer) {
+  fn set_mod_id(&mut self, code_server: &CodeServer) {
     assert!(!self.vm_atoms.is_empty());
     // 0-th atom in the atom table is module name
     let mod_name = self.vm_atoms[0];
@@ -212,7 +215,7 @@ fn op_badarg_panic(op: RawOpcode, args: &[Term], argi: Word) {
 }
 
 pub fn load_module(
-  code_srv: &mut CodeServer,
+  code_srv: &CodeServer,
   mod_file_path: &PathBuf,
 ) -> RtResult<Box<Module>> {
   rtdbg!("BEAM loader: from {}", mod_file_path.to_str().unwrap());
@@ -1,12 +1,9 @@
 use crate::{
   beam::disp_result::DispatchResult,
   defs::{BitSize, SizeBytes, SizeWords},
-  emulator::{heap::THeapOwner, process::Process, runtime_ctx::*, vm::VM},
+  emulator::{heap::THeapOwner, process::Process, runtime_ctx::*},
   fail::{self, RtResult},
-  term::{
-    boxed::{self, binary::*},
-    Term,
-  },
+  term::{boxed, Term},
 };
 
 // Create a binary on proc heap or binary heap with GC if required.
@@ -21,8 +18,8 @@ use crate::{
 // bs_init2 Fail Sz Words Regs Flags Dst =>   i_bs_init_fail_heap Sz Words Fail Regs Dst
 // Example  bs_init2 [], X1, 0, 2, 0, X1
 define_opcode!(
-  vm, rt_ctx, proc, name: OpcodeBsInit2, arity: 6,
-  run: { Self::bs_init2(vm, rt_ctx, proc, fail, sz, words, regs, flags, dst) },
+  _vm, rt_ctx, proc, name: OpcodeBsInit2, arity: 6,
+  run: { Self::bs_init2(rt_ctx, proc, fail, sz, words, regs, flags, dst) },
   args: cp_or_nil(fail), load_usize(sz), usize(words), usize(regs),
         usize(flags), term(dst),
 );
@@ -31,7 +28,6 @@ impl OpcodeBsInit2 {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn bs_init2(
-    vm: &mut VM,
     runtime_ctx: &mut RuntimeContext,
     proc: &mut Process,
     fail: Term,
@@ -56,14 +52,9 @@ impl OpcodeBsInit2 {
     let bit_sz = BitSize::with_bytes(sz);
 
     // Show intent to allocate memory; TODO: add GC related args, like live/regs
-    let bin = if sz <= ProcessHeapBinary::ONHEAP_THRESHOLD {
-      proc.ensure_heap(ProcessHeapBinary::storage_size(bit_sz) + extra_memory)?;
-      unsafe { boxed::Binary::create_into(bit_sz, proc.get_heap_mut())? }
-    } else {
-      vm.binary_heap
-        .ensure_heap(ReferenceToBinary::storage_size() + extra_memory)?;
-      unsafe { boxed::Binary::create_into(bit_sz, vm.binary_heap.get_heap_mut())? }
-    };
+    // Large binaries go to the binary heap, and only a reference is on our heap
+    boxed::Binary::ensure_memory_for_binary(proc, bit_sz, extra_memory)?;
+    let bin = unsafe { boxed::Binary::create_into(bit_sz, proc.get_heap_mut())? };
 
     let bin_term = unsafe { (*bin).make_term() };
     runtime_ctx.current_bin.reset(bin_term);
@@ -42,7 +42,7 @@ impl OpcodeBsPutBinary {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn bs_put_binary(
-    _vm: &mut VM,
+    _vm: &VM,
     ctx: &mut RuntimeContext,
     _proc: &mut Process,
     fail: Term,
@@ -21,7 +21,7 @@ impl OpcodeBsPutInteger {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn bs_put_integer(
-    _vm: &mut VM,
+    _vm: &VM,
     ctx: &mut RuntimeContext,
     _proc: &mut Process,
     _fail: Term,
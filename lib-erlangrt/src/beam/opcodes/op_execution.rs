@@ -109,7 +109,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeCallExtOnly {
   #[inline]
   pub fn call_ext_only(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
@@ -117,7 +117,7 @@ impl OpcodeCallExtOnly {
   ) -> RtResult<DispatchResult> {
     let args = ctx.registers_slice(0, arity);
     ctx.debug_trace_call("opcode:call_ext_only", dst, 0, arity);
-    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, false)
+    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, false, None)
   }
 }
 
@@ -134,7 +134,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeCallExt {
   #[inline]
   pub fn call_ext(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
@@ -142,7 +142,7 @@ impl OpcodeCallExt {
   ) -> RtResult<DispatchResult> {
     let args = ctx.registers_slice(0, arity);
     ctx.debug_trace_call("opcode:call_ext", dst, 0, arity);
-    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, true)
+    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, true, None)
   }
 }
 
@@ -157,31 +157,34 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeCallExtLast {
   #[inline]
   pub fn call_ext_last(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
     dst: Term,
     dealloc: usize,
   ) -> RtResult<DispatchResult> {
-    let new_cp = curr_p.get_heap_mut().stack_deallocate(dealloc);
-    ctx.set_cp(new_cp);
     let args = ctx.registers_slice(0, arity);
     ctx.debug_trace_call("opcode:call_ext_last", dst, 0, arity);
-    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, false)
+    generic_call_ext(vm, ctx, curr_p, dst, Term::nil(), args, false, Some(dealloc))
   }
 }
 
 /// Arg: dst_import: boxed::Import which will contain MFArity to call.
+/// Arg: dealloc: stack frame size to drop for a tail call. For a BIF it is
+///   dropped after the call, so that a trapping BIF can run the instruction
+///   again.
 #[inline]
+#[allow(clippy::too_many_arguments)]
 fn generic_call_ext(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   proc: &mut Process,
   dst_import: Term,
   fail_label: Term,
   args: &[Term],
   save_cp: bool,
+  dealloc: Option<usize>,
 ) -> RtResult<DispatchResult> {
   ctx.live = args.len();
 
@@ -200,9 +203,14 @@ fn generic_call_ext(
           Term::make_register_x(0),
           true,
         );
-        if save_cp {
+        if save_cp || native_dispatch_result.is_err() {
+          // Errors, traps and hibernation leave the CP and the stack for their
+          // handlers
           native_dispatch_result
         } else {
+          if let Some(words) = dealloc {
+            ctx.set_cp(proc.get_heap_mut().stack_deallocate(words));
+          }
           // Perform inline return like if it was a tail recursive call
           // Because tail call might happen on an empty stack, the return with
           // empty stack will end the process life here (no more code).
@@ -217,8 +225,10 @@ fn generic_call_ext(
         if save_cp {
           ctx.cp = ctx.ip; // Points at the next opcode after this
         }
-        let code_srv = vm.get_code_server_p();
-        let import_dst = (*import_ptr).resolve(&mut (*code_srv))?;
+        if let Some(words) = dealloc {
+          ctx.set_cp(proc.get_heap_mut().stack_deallocate(words));
+        }
+        let import_dst = (*import_ptr).resolve(&vm.code_server)?;
         ctx.jump_ptr(import_dst.get_pointer());
         Ok(DispatchResult::Normal)
       }
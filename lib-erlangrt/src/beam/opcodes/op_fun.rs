@@ -53,7 +53,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeCallFun {
   #[inline]
   pub fn call_fun(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
@@ -89,7 +89,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeApply {
   #[inline]
   pub fn apply(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
@@ -116,7 +116,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeApplyLast {
   #[inline]
   pub fn apply_last(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     arity: usize,
@@ -139,7 +139,7 @@ impl OpcodeApplyLast {
 /// Perform application of module:function/arity to args stored in registers,
 /// with optional deallocation.
 fn fixed_apply(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   curr_p: &mut Process,
   mfa: &ModFunArity,
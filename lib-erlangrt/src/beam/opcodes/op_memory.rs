@@ -21,22 +21,12 @@ fn gen_alloc(
   fill: AllocInit,
 ) -> RtResult<()> {
   ctx.live = live;
-  curr_p.ensure_heap(heap_need)?;
+  // Heap and stack share the same memory, reserve for both and the CP
+  curr_p.ensure_heap(heap_need + stack_need + SizeWords::one())?;
 
   let hp = curr_p.get_heap_mut();
-  hp.stack_alloc(stack_need, SizeWords::one(), fill);
+  hp.stack_alloc(stack_need, SizeWords::one(), fill)?;
   hp.stack_push_lterm_unchecked(ctx.cp.to_cp_term());
-
-  //  if hp.stack_check_available(stack_need + WordSize::one()) {
-  //    // Stack has enough words, we can allocate unchecked
-  //    if stack_need.words > 0 {
-  //      hp.stack_alloc_unchecked(stack_need, zero);
-  //    }
-  //    hp.stack_push_lterm_unchecked(ctx.cp.to_cp_term());
-  //  } else {
-  //    // Stack has not enough, invoke GC and possibly fail
-  //    return Err(RtErr::HeapIsFull("heap::gen_alloc/stack"));
-  //  }
   Ok(())
 }
 
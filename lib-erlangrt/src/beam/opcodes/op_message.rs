@@ -1,33 +1,49 @@
 use crate::{
   beam::disp_result::{DispatchResult, YieldType},
-  emulator::{process::Process, runtime_ctx::*, vm::VM},
+  emulator::{
+    gen_atoms,
+    heap::{owned_term::OwnedTerm, THeapOwner},
+    process::{Process, ReceiveTimer},
+    runtime_ctx::*,
+    signal::Signal,
+    vm::VM,
+  },
   fail::{self, RtResult},
   term::*,
 };
 
 // Sends to x0 value x1, x1 is moved to x0 as result of the operation.
-// If process with pid x0 does not exist, no error is raised.
+// The destination is a pid, a registered name or `{Name, Node}`.
+// If process with pid x0 does not exist, no error is raised. An unregistered
+// name is a `badarg`.
 // Structure: send()
-define_opcode!(vm, ctx, _curr_p,
+define_opcode!(vm, ctx, curr_p,
   name: OpcodeSend, arity: 0,
-  run: { Self::send(vm, ctx) },
+  run: { Self::send(vm, ctx, curr_p) },
   args:
 );
 
 impl OpcodeSend {
   #[inline]
-  pub fn send(vm: &mut VM, ctx: &mut RuntimeContext) -> RtResult<DispatchResult> {
-    // let sched = vm.get_scheduler_p();
+  pub fn send(
+    vm: &VM,
+    ctx: &mut RuntimeContext,
+    curr_p: &mut Process,
+  ) -> RtResult<DispatchResult> {
     let x1 = ctx.get_x(1);
     let x0 = ctx.get_x(0);
-    if !x0.is_pid() {
-      return fail::create::badarg();
-    }
-    let p = vm.processes.unsafe_lookup_pid_mut(x0);
-    if !p.is_null() {
-      unsafe {
-        (*p).deliver_message(&mut vm.processes, x1)?;
+    let dest = match vm.processes.resolve_send_dest(x0)? {
+      Some(pid) if pid.is_local_pid() => pid,
+      _ => {
+        ctx.set_x(0, x1);
+        return Ok(DispatchResult::Normal);
       }
+    };
+    if dest == curr_p.pid {
+      curr_p.deliver_message(x1)?;
+    } else {
+      // A process which does not exist drops the message
+      let _ = vm.send_signal(dest, Signal::Message(OwnedTerm::new(x1)?));
     }
 
     ctx.set_x(0, x1);
@@ -39,19 +55,22 @@ impl OpcodeSend {
 // If there is no next message, jumps to `fail` label which points to a `wait`
 // or `wait_timeout` instruction.
 // Structure: loop_rec(fail:cp, _source)
-define_opcode!(_vm, ctx, curr_p,
+define_opcode!(vm, ctx, curr_p,
   name: OpcodeLoopRec, arity: 2,
-  run: { Self::loop_rec(ctx, curr_p, fail) },
+  run: { Self::loop_rec(vm, ctx, curr_p, fail) },
   args: cp_or_nil(fail), IGNORE(source),
 );
 
 impl OpcodeLoopRec {
   #[inline]
   pub fn loop_rec(
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
   ) -> RtResult<DispatchResult> {
+    // Messages sent while the process was running
+    curr_p.handle_signals(vm)?;
     if let Some(msg) = curr_p.mailbox.get_current() {
       ctx.set_x(0, msg);
     } else {
@@ -83,12 +102,37 @@ impl OpcodeLoopRecEnd {
   }
 }
 
+// Saves the end of the message queue for the receive loop at `label`. The
+// compiler emits it before creating a reference which a following receive
+// will match on, so messages already in the queue can be skipped.
+// Structure: recv_mark(label:cp)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvMark, arity: 1,
+  run: {
+    curr_p.mailbox.set_mark(label);
+    Ok(DispatchResult::Normal)
+  },
+  args: cp_or_nil(label),
+);
+
+// Moves the receive pointer to the position saved by `recv_mark`, if it was
+// saved for the same receive loop. Placed right before `loop_rec`.
+// Structure: recv_set(label:cp)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvSet, arity: 1,
+  run: {
+    curr_p.mailbox.use_mark(label);
+    Ok(DispatchResult::Normal)
+  },
+  args: cp_or_nil(label),
+);
+
 // Removes the current message in the process message list and moves it to `x0`
 // Structure: remove_message()
 define_opcode!(_vm, ctx, curr_p,
   name: OpcodeRemoveMessage, arity: 0,
   run: {
-    let message = curr_p.mailbox.remove_current();
+    let message = curr_p.remove_message();
     ctx.set_x(0, message);
     Ok(DispatchResult::Normal)
   },
@@ -111,3 +155,60 @@ impl OpcodeWait {
     Ok(DispatchResult::Yield(YieldType::InfiniteWait))
   }
 }
+
+// Suspends the current process for at most `timeout` milliseconds and sets
+// the ip to the label (beginning of the receive loop). When the timer expires
+// the process continues at the next instruction, which is `timeout`.
+// Structure: wait_timeout(label:cp, timeout)
+define_opcode!(_vm, ctx, curr_p,
+  name: OpcodeWaitTimeout, arity: 2,
+  run: { Self::wait_timeout(ctx, curr_p, label, timeout) },
+  args: cp_or_nil(label), load(timeout),
+);
+
+impl OpcodeWaitTimeout {
+  #[inline]
+  pub fn wait_timeout(
+    ctx: &mut RuntimeContext,
+    curr_p: &mut Process,
+    label: Term,
+    timeout: Term,
+  ) -> RtResult<DispatchResult> {
+    if timeout == gen_atoms::INFINITY {
+      return OpcodeWait::wait(ctx, label);
+    }
+    if !timeout.is_small() || timeout.get_small_signed() < 0 {
+      return fail::create::timeout_value();
+    }
+
+    match curr_p.recv_timer {
+      // Timer has expired while the process was running, continue to the
+      // `timeout` instruction
+      ReceiveTimer::Expired => return Ok(DispatchResult::Normal),
+      ReceiveTimer::Active(_) | ReceiveTimer::Pending(..) => {}
+      ReceiveTimer::None => {
+        let timeout_ms = timeout.get_small_signed() as u64;
+        if timeout_ms == 0 {
+          return Ok(DispatchResult::Normal);
+        }
+        // ctx.ip already points to the following `timeout` instruction
+        curr_p.start_receive_timer(timeout_ms, ctx.ip);
+      }
+    }
+    ctx.jump(label);
+    Ok(DispatchResult::Yield(YieldType::TimedWait))
+  }
+}
+
+// Ends a receive which has timed out: clears the receive timer and resets the
+// receive pointer to the first message in the mailbox.
+// Structure: timeout()
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeTimeout, arity: 0,
+  run: {
+    curr_p.recv_timer = ReceiveTimer::None;
+    curr_p.mailbox.reset_read_index();
+    Ok(DispatchResult::Normal)
+  },
+  args:
+);
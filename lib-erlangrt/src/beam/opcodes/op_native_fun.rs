@@ -25,7 +25,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeBif0 {
   #[inline]
   fn bif0(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     target: Term,
@@ -57,7 +57,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeBif1 {
   #[inline]
   fn bif1(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
@@ -82,7 +82,7 @@ define_opcode!(vm, ctx, curr_p,
 impl OpcodeBif2 {
   #[inline]
   fn bif2(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
@@ -108,7 +108,7 @@ impl OpcodeGcBif1 {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn gc_bif1(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
@@ -137,7 +137,7 @@ impl OpcodeGcBif2 {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn gc_bif2(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
@@ -166,7 +166,7 @@ impl OpcodeGcBif3 {
   #[inline]
   #[allow(clippy::too_many_arguments)]
   fn gc_bif3(
-    vm: &mut VM,
+    vm: &VM,
     ctx: &mut RuntimeContext,
     curr_p: &mut Process,
     fail: Term,
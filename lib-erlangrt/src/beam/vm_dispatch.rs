@@ -10,7 +10,7 @@ use crate::{
 };
 
 #[inline]
-pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut RuntimeContext, curr_p: &mut Process) -> RtResult<DispatchResult> {
+pub fn dispatch_op_inline(vm: &VM, op: RawOpcode, ctx: &mut RuntimeContext, curr_p: &mut Process) -> RtResult<DispatchResult> {
   match op {
     OPCODE_FUNC_INFO => {
       assert_arity(OPCODE_FUNC_INFO, OpcodeFuncInfo::ARITY);
@@ -107,6 +107,11 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut RuntimeContext,
       return OpcodeRemoveMessage::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_TIMEOUT => {
+      assert_arity(OPCODE_TIMEOUT, OpcodeTimeout::ARITY);
+      return OpcodeTimeout::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_LOOP_REC => {
       assert_arity(OPCODE_LOOP_REC, OpcodeLoopRec::ARITY);
       return OpcodeLoopRec::__run(vm, ctx, curr_p);
@@ -122,6 +127,11 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut RuntimeContext,
       return OpcodeWait::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_WAIT_TIMEOUT => {
+      assert_arity(OPCODE_WAIT_TIMEOUT, OpcodeWaitTimeout::ARITY);
+      return OpcodeWaitTimeout::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_IS_LT => {
       assert_arity(OPCODE_IS_LT, OpcodeIsLt::ARITY);
       return OpcodeIsLt::__run(vm, ctx, curr_p);
@@ -357,6 +367,16 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut RuntimeContext,
       return OpcodeTrim::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_RECV_MARK => {
+      assert_arity(OPCODE_RECV_MARK, OpcodeRecvMark::ARITY);
+      return OpcodeRecvMark::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_RECV_SET => {
+      assert_arity(OPCODE_RECV_SET, OpcodeRecvSet::ARITY);
+      return OpcodeRecvSet::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_GC_BIF3 => {
       assert_arity(OPCODE_GC_BIF3, OpcodeGcBif3::ARITY);
       return OpcodeGcBif3::__run(vm, ctx, curr_p);
@@ -4,30 +4,47 @@ use crate::{
     gen_op,
     vm_dispatch::dispatch_op_inline,
   },
-  emulator::{disasm, scheduler::SliceResult, vm::VM},
+  defs::{exc_type::ExceptionType, SizeWords},
+  emulator::{disasm, gen_atoms, process::Process, scheduler::SliceResult, vm::VM},
   fail::{RtErr, RtResult},
 };
+use core::sync::atomic::Ordering;
 
 // fn module() -> &'static str { "vm_loop: " }
 
 impl VM {
-  /// Take a process from scheduler.
-  /// Fetch an opcode and execute it.
-  /// Reduce the reduction (instruction) count and once it reaches zero, return.
+  /// Take a process from the scheduler `index`, or steal one from another
+  /// scheduler if there is nothing to run, and run its time slice.
   /// Call dispatch again to schedule another process.
   ///
   /// Returns: `false` if VM found no process to run, `true` if the process has
   /// used its time slice and wants to run another.
-  pub fn dispatch(&mut self) -> RtResult<bool> {
-    // let scheduler = self.get_scheduler_p();
-    let curr_p = match self.scheduler.next_process(&mut self.processes) {
+  pub fn dispatch(&self, index: usize) -> RtResult<bool> {
+    let mut next = self.next_process(index);
+    if next.is_none() && self.steal_process(index) {
+      next = self.next_process(index);
+    }
+    let mut proc = match next {
       None => return Ok(false),
-      Some(next_pid) => {
-        let next_ptr = self.processes.unsafe_lookup_pid_mut(next_pid);
-        unsafe { &mut (*next_ptr) }
-      }
+      Some(p) => p,
     };
+    loop {
+      if let Err(e) = self.run_slice(&mut proc) {
+        // The VM stops, the process is left in its scheduler
+        self.schedulers[index].lock().unwrap().check_in(proc);
+        return Err(e);
+      }
+      match self.finalize_slice(index, proc) {
+        // The exception was caught, the process continues
+        Some(p) => proc = p,
+        None => return Ok(true),
+      }
+    }
+  }
 
+  /// Fetch an opcode and execute it.
+  /// Reduce the reduction (instruction) count and once it reaches zero, return.
+  fn run_slice(&self, curr_p: &mut Process) -> RtResult<()> {
     // Ugly borrowing the context from the process, but we guarantee that the
     // borrow will not outlive the owning process or we pay the harsh price
     // debugging SIGSEGVs.
@@ -36,7 +53,9 @@ impl VM {
     ctx.swap_in(); // tell the context, that it is active now
     // curr_p.heap.print_stack();
 
-    let cs = self.get_code_server_p();
+    // Grows with every retry of an instruction which ran out of heap, see
+    // `Process::retry_need`
+    let mut retry_need = SizeWords::zero();
 
     // Fetch some opcodes, Execute some opcodes
     //
@@ -44,12 +63,13 @@ impl VM {
       if cfg!(feature = "trace_opcode_execution") {
         print!("   ↳ ");
         unsafe {
-          disasm::disasm_op(ctx.ip.get_pointer(), &(*cs));
+          disasm::disasm_op(ctx.ip.get_pointer(), &self.code_server);
         }
         //        curr_p.heap.stack_dump();
       }
 
       // Take next opcode
+      let op_ip = ctx.ip;
       let op = ctx.fetch_opcode();
       debug_assert!(
         op <= gen_op::OPCODE_MAX,
@@ -57,15 +77,55 @@ impl VM {
         op.get()
       );
 
-      // Handle next opcode
-      let disp_result = match dispatch_op_inline(self, op, ctx, curr_p) {
+      // Handle next opcode. If it runs out of heap, collect the garbage using
+      // `ctx.live` set by the last test_heap or allocate and run it again.
+      let op_result = match dispatch_op_inline(self, op, ctx, curr_p) {
+        Err(RtErr::HeapIsFull(failed)) => {
+          retry_need = Process::retry_need(retry_need, failed);
+          ctx.ip = op_ip;
+          match curr_p.collect_garbage(retry_need, &mut []) {
+            Ok(()) => continue,
+            Err(e) => Err(e),
+          }
+        }
+        Err(RtErr::Trap) => {
+          // A native function ran out of reductions, run the same opcode again
+          // when the process is scheduled back in
+          ctx.ip = op_ip;
+          curr_p.timeslice_result = SliceResult::Yield;
+          break;
+        }
+        other => other,
+      };
+      retry_need = SizeWords::zero();
+      if curr_p.trap.is_some() {
+        // The trapped call has finished or failed
+        curr_p.trap = None;
+      }
+
+      let disp_result = match op_result {
         Err(RtErr::Exception(exc_type, exc_reason)) => {
           println!("vm: Exception type={exc_type} reason={exc_reason}");
           curr_p.set_exception(exc_type, exc_reason);
           curr_p.timeslice_result = SliceResult::Exception;
-          return Ok(true);
+          break;
+        }
+        Err(RtErr::ProcessKilled) => {
+          curr_p.set_exception(ExceptionType::Exit, gen_atoms::KILLED);
+          curr_p.timeslice_result = SliceResult::Killed;
+          break;
         }
-        other => other?,
+        Err(RtErr::Hibernate) => {
+          curr_p.timeslice_result = SliceResult::InfiniteWait;
+          break;
+        }
+        Err(e) => {
+          // The error stops the VM, the slice still counts in the statistics
+          let reductions = ctx.get_slice_reductions();
+          self.reductions_total.fetch_add(reductions, Ordering::Relaxed);
+          return Err(e);
+        }
+        Ok(r) => r,
       };
 
       match disp_result {
@@ -73,8 +133,9 @@ impl VM {
           curr_p.timeslice_result = match yt {
             YieldType::EndOfTheQueue => SliceResult::Yield,
             YieldType::InfiniteWait => SliceResult::InfiniteWait,
+            YieldType::TimedWait => SliceResult::TimedWait,
           };
-          return Ok(true);
+          break;
         }
         DispatchResult::Normal => {
           // curr_p.timeslice_result = SliceResult::None;
@@ -82,22 +143,20 @@ impl VM {
         DispatchResult::Finished => {
           // Scheduler will terminate the process with EXIT:NORMAL
           curr_p.timeslice_result = SliceResult::Finished;
-          return Ok(true);
+          break;
         }
       }
 
-      // TODO: this seems to not work?
-      if self.processes.count() == 0 {
-        println!("All processes finished, this is the end.");
-        return Ok(false);
-      }
-
       if ctx.reductions <= 0 {
         // curr_p.heap.print_stack();
         // Out of reductions, just give up and let another one run
         curr_p.timeslice_result = SliceResult::Yield;
-        return Ok(true);
+        break;
       }
     } // end loop
+
+    let reductions = ctx.get_slice_reductions();
+    self.reductions_total.fetch_add(reductions, Ordering::Relaxed);
+    Ok(())
   }
 }
@@ -27,6 +27,12 @@ pub struct ErlStartArgs {
   /// Which modules:functions to start (option -s m f arg1,...)
   pub start: Vec<Vec<String>>,
   pub search_path: Vec<String>,
+  /// Check process heaps integrity after GC, message send and native calls
+  /// (option -verify_heap)
+  pub verify_heap: bool,
+  /// Scheduler count (option +S N[:M], only N is used), the VM runs a thread
+  /// per scheduler
+  pub schedulers: usize,
 
   /// Small heap only for storing command line available globally
   arg_heap: Heap,
@@ -42,6 +48,8 @@ impl ErlStartArgs {
       node: NodeName::Short("nonode@nohost".to_string()),
       start: Vec::new(),
       search_path: vec![],
+      verify_heap: false,
+      schedulers: 1,
       arg_heap: Heap::new(Designation::ProgramArgumentsHeap),
       args_term: Term::non_value(),
     }
@@ -58,8 +66,15 @@ impl ErlStartArgs {
     where
         ITER: Iterator<Item=&'a String>,
   {
-    for s in iter {
-      self.add_arg1(s.as_ref())
+    let mut iter = iter.peekable();
+    while let Some(s) = iter.next() {
+      match (s.as_str(), iter.peek()) {
+        ("-sname", Some(_)) | ("-name", Some(_)) | ("+S", Some(_)) => {
+          let value = iter.next().unwrap();
+          self.add_arg2(s.as_ref(), value.as_ref())
+        }
+        _ => self.add_arg1(s.as_ref()),
+      }
     }
   }
 
@@ -84,6 +99,16 @@ impl ErlStartArgs {
       "-name" => {
         self.node = NodeName::Full(args[1].to_string());
       }
+      "-verify_heap" => {
+        self.verify_heap = true;
+      }
+      "+S" => {
+        let total = args.get(1).and_then(|s| s.split(':').next());
+        match total.and_then(|n| n.parse::<usize>().ok()) {
+          Some(n) if n > 0 => self.schedulers = n,
+          _ => println!("Bad value for +S, expected a positive number"),
+        }
+      }
       other => self.other_args.push(String::from(other)),
     }
   }
@@ -59,6 +59,10 @@ impl Reductions {
 
   /// Fetch is base "tax" for fetching an opcode and dispatching to its handler
   pub const FETCH_OPCODE_COST: isize = 1;
+
+  /// Work a native function does for one reduction: list elements visited,
+  /// bytes copied etc.
+  pub const NATIVE_WORK_PER_REDUCTION: usize = 16;
 }
 
 // / For n bytes calculate how many words are required to store this
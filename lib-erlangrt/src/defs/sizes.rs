@@ -3,7 +3,7 @@ use core::fmt;
 use std::ops::{Add, Sub};
 
 /// Size of something in machine words (32 or 64 bit depending on platform)
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct SizeWords {
   pub words: usize,
 }
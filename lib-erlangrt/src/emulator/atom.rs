@@ -61,8 +61,9 @@ impl Atom {
 /// A quick way to find an atom index by its string.
 type StrLookup = BTreeMap<String, usize>;
 
-/// A quick way to find an atom by its index.
-type IndexLookup = Vec<Atom>;
+/// A quick way to find an atom by its index. Atoms are boxed, so the pointers
+/// given by `lookup` stay valid when another thread adds atoms.
+type IndexLookup = Vec<Box<Atom>>;
 
 /// Lookup table for atom to atom index and back. Declared static for use by
 /// printing and atom loading facilities without having to pass the VM pointer
@@ -98,7 +99,7 @@ impl AtomStorage {
   ) -> Word {
     let index = atoms_by_index.len();
     atoms_by_str.insert(s.to_string(), index);
-    atoms_by_index.push(Atom::new(s));
+    atoms_by_index.push(Box::new(Atom::new(s)));
     index
   }
 }
@@ -166,5 +167,5 @@ pub fn lookup(a: Term) -> *const Atom {
   if index >= atoms_r.len() {
     return ptr::null();
   }
-  &atoms_r[index] as *const Atom
+  &*atoms_r[index] as *const Atom
 }
@@ -1,5 +1,8 @@
 //! Code server loads modules and stores them in memory, handles code lookups
 //! as well as dynamic reloading and partial unloading.
+//!
+//! The code server is shared by the scheduler threads. Lookups share the
+//! module table, loading a module changes it and takes it exclusively.
 
 use crate::{
   beam::loader,
@@ -14,9 +17,11 @@ use crate::{
   native_fun::{registry::NativeFunRegistry, NativeFn},
   term::*,
 };
+use core::sync::atomic::{AtomicUsize, Ordering};
 use std::{
   collections::BTreeMap,
   path::{Path, PathBuf},
+  sync::{Mutex, RwLock},
 };
 
 fn module() -> &'static str {
@@ -43,9 +48,12 @@ pub enum MFALookupResult {
 pub struct CodeServer {
   // Mapping {atom(): ModuleGenerations} where generations contains current
   // and previous mod versions
-  mods: BTreeMap<Term, ModuleGenerations>,
+  mods: RwLock<BTreeMap<Term, ModuleGenerations>>,
+  /// Held while a module is loaded, so that two threads which miss the same
+  /// module do not both load it
+  loading: Mutex<()>,
   search_path: Vec<String>,
-  mod_version: usize,
+  mod_version: AtomicUsize,
 
   pub native_functions: NativeFunRegistry,
 }
@@ -53,8 +61,9 @@ pub struct CodeServer {
 impl CodeServer {
   pub fn new(args: &mut ErlStartArgs) -> CodeServer {
     CodeServer {
-      mod_version: 1,
-      mods: BTreeMap::new(),
+      mod_version: AtomicUsize::new(1),
+      mods: RwLock::new(BTreeMap::new()),
+      loading: Mutex::new(()),
       search_path: args.search_path.clone(),
       native_functions: NativeFunRegistry::new(),
     }
@@ -64,7 +73,7 @@ impl CodeServer {
   /// something else.
   /// Arg: `allow_load` allows loading another BEAM file as needed
   pub fn lookup_mfa(
-    &mut self,
+    &self,
     mfa: &ModFunArity,
     allow_load: bool,
   ) -> RtResult<MFALookupResult> {
@@ -91,7 +100,7 @@ impl CodeServer {
     mfarity: &ModFunArity,
   ) -> RtResult<VersionedCodePtr> {
     let m = mfarity.m;
-    match self.mods.get(&m) {
+    match self.mods.read().unwrap().get(&m) {
       None => {
         let msg = format!("{}Module not found {}", module(), m);
         Err(RtErr::ModuleNotFound(msg))
@@ -108,7 +117,7 @@ impl CodeServer {
   /// Returns: Memory pointer to code, not versioned (do not store)
   pub fn lookup_beam_code(&self, mfarity: &ModFunArity) -> RtResult<CodePtr> {
     let m = mfarity.m;
-    match self.mods.get(&m) {
+    match self.mods.read().unwrap().get(&m) {
       None => {
         let msg = format!("{}Module not found {}", module(), m);
         Err(RtErr::ModuleNotFound(msg))
@@ -118,7 +127,7 @@ impl CodeServer {
   }
 
   /// Find the module file from search path and return the path or error.
-  pub fn find_module_file(&mut self, filename: &str) -> RtResult<PathBuf> {
+  pub fn find_module_file(&self, filename: &str) -> RtResult<PathBuf> {
     match first_exists_in_search_path(&self.search_path, filename) {
       Some(found_first) => Ok(found_first),
       None => Err(RtErr::BEAMFileNotFound(filename.to_string())),
@@ -127,7 +136,7 @@ impl CodeServer {
 
   /// Notify the code server about the fact that a new module is ready to be
   /// added to the codebase.
-  pub fn module_loaded(&mut self, mod_ptr: Box<Module>) {
+  pub fn module_loaded(&self, mod_ptr: Box<Module>) {
     let name = mod_ptr.versioned_name.module;
     let v = mod_ptr.versioned_name.version;
     let mg = ModuleGenerations {
@@ -136,25 +145,26 @@ impl CodeServer {
       old_modp: None,
       old_version: 0,
     };
-    self.mods.insert(name, mg);
+    self.mods.write().unwrap().insert(name, mg);
   }
 
   /// Lookup, which will attempt to load a missing module if lookup fails
   /// on the first attempt.
-  pub fn lookup_beam_code_and_load(
-    &mut self,
-    mfarity: &ModFunArity,
-  ) -> RtResult<CodePtr> {
+  pub fn lookup_beam_code_and_load(&self, mfarity: &ModFunArity) -> RtResult<CodePtr> {
     // Try lookup once, then load if not found
-    match self.lookup_beam_code(mfarity) {
-      Ok(ip) => return Ok(ip),
-      Err(_e) => {
+    if let Ok(ip) = self.lookup_beam_code(mfarity) {
+      return Ok(ip);
+    }
+    {
+      let _loading = self.loading.lock().unwrap();
+      // Another thread might have loaded it while we waited
+      if self.lookup_beam_code(mfarity).is_err() {
         let mod_name = atom::to_str(mfarity.m)?;
         let found_mod = self.find_module_file(&mod_name)?;
 
         self.try_load_module(&found_mod)?;
       }
-    };
+    }
     // Try lookup again
     match self.lookup_beam_code(mfarity) {
       Ok(ip) => Ok(ip),
@@ -169,7 +179,7 @@ impl CodeServer {
 
   /// Internal function: runs 3 stages of module loader and returns an atomic
   /// refc (Arc) module pointer or an error
-  fn try_load_module(&mut self, mod_file_path: &PathBuf) -> RtResult<()> {
+  fn try_load_module(&self, mod_file_path: &PathBuf) -> RtResult<()> {
     let mod_ptr = loader::load_module(self, mod_file_path)?;
     self.module_loaded(mod_ptr);
     Ok(())
@@ -178,7 +188,7 @@ impl CodeServer {
   /// Given a code address try find a module and function where this belongs.
   // TODO: Optimize search by giving a module name hint and using a range tree
   pub fn code_reverse_lookup(&self, ip: CodePtr) -> Option<ModFunArity> {
-    for val in self.mods.values() {
+    for val in self.mods.read().unwrap().values() {
       let modp = &val.curr_modp;
       let lresult = modp.code_reverse_lookup(ip);
       // TODO: Might be situation when ip points to old version of a module
@@ -190,10 +200,8 @@ impl CodeServer {
     None
   }
 
-  pub fn next_module_version(&mut self, _m: Term) -> usize {
-    let ver = self.mod_version;
-    self.mod_version += 1;
-    ver
+  pub fn next_module_version(&self, _m: Term) -> usize {
+    self.mod_version.fetch_add(1, Ordering::Relaxed)
   }
 }
 
@@ -0,0 +1,102 @@
+//! Timers started by `erlang:send_after` and `erlang:start_timer`. A timer is
+//! keyed by the id of the reference returned to the user, and holds its own
+//! copy of the message, which is sent to the destination when the timer
+//! expires.
+use crate::{
+  defs::Word,
+  emulator::{heap::owned_term::OwnedTerm, timer_wheel::TimerWheel},
+  fail::RtResult,
+  term::*,
+};
+use std::collections::HashMap;
+
+struct ErlTimer {
+  /// Pid or a registered name
+  dest: Term,
+  message: OwnedTerm,
+}
+
+pub struct ErlTimers {
+  wheel: TimerWheel<Word>,
+  timers: HashMap<Word, ErlTimer>,
+}
+
+impl ErlTimers {
+  pub fn new() -> Self {
+    Self {
+      wheel: TimerWheel::new(),
+      timers: HashMap::new(),
+    }
+  }
+
+  /// Monotonic clock in milliseconds, used for absolute timer times.
+  #[inline]
+  pub fn now(&self) -> u64 {
+    self.wheel.now()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.timers.is_empty()
+  }
+
+  #[inline]
+  pub fn time_to_next(&self) -> Option<u64> {
+    self.wheel.time_to_next()
+  }
+
+  /// Start a timer which sends `message` to `dest` after `timeout_ms`. The
+  /// message is copied and the caller's heap is not referenced.
+  pub fn start(
+    &mut self,
+    ref_id: Word,
+    timeout_ms: u64,
+    dest: Term,
+    message: Term,
+  ) -> RtResult<()> {
+    let timer = ErlTimer {
+      dest,
+      message: OwnedTerm::new(message)?,
+    };
+    self.timers.insert(ref_id, timer);
+    self.wheel.add(ref_id, timeout_ms);
+    Ok(())
+  }
+
+  /// Cancel a timer, returns milliseconds which were left or `None` if the
+  /// timer does not exist or has already expired.
+  pub fn cancel(&mut self, ref_id: Word) -> Option<u64> {
+    self.timers.remove(&ref_id)?;
+    self.wheel.cancel(ref_id)
+  }
+
+  pub fn time_left(&self, ref_id: Word) -> Option<u64> {
+    self.wheel.time_left(ref_id)
+  }
+
+  /// Cancel all timers which would send to the pid. Called when the process
+  /// terminates, timers which send to a registered name stay active.
+  pub fn cancel_for_pid(&mut self, pid: Term) {
+    let ids: Vec<Word> = self
+      .timers
+      .iter()
+      .filter(|(_, timer)| timer.dest == pid)
+      .map(|(id, _)| *id)
+      .collect();
+    for id in ids {
+      self.cancel(id);
+    }
+  }
+
+  /// Remove the expired timers, returns their destinations (a pid or a
+  /// registered name) and messages.
+  pub fn take_expired(&mut self) -> Vec<(Term, OwnedTerm)> {
+    let mut expired = Vec::new();
+    for ref_id in self.wheel.advance() {
+      if let Some(timer) = self.timers.remove(&ref_id) {
+        expired.push((timer.dest, timer.message));
+      }
+    }
+    expired
+  }
+}
@@ -0,0 +1,80 @@
+//! Error reports from the emulator, such as a process exceeding its
+//! `max_heap_size` or a lost message. Reports can be made from places which
+//! have no access to the VM, so they are queued and then delivered by the VM:
+//! sent as `{error_report, Text}` to the process
+//! registered as `logger`, or printed if there is none.
+use crate::{
+  defs::SizeWords,
+  emulator::{
+    gen_atoms,
+    heap::{owned_term::OwnedTerm, Heap},
+    signal::Signal,
+    vm::VM,
+  },
+  fail::{RtErr, RtResult},
+  term::{
+    boxed,
+    term_builder::{list_builder::build_erlstr_from_utf8, tuple_builder::tuple2},
+    Term,
+  },
+};
+use std::sync::Mutex;
+
+lazy_static! {
+  static ref PENDING_REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Queue an error report, it will be delivered by `flush`.
+pub fn error_report(text: String) {
+  PENDING_REPORTS.lock().unwrap().push(text);
+}
+
+/// Report a message or a signal which could not be delivered, such as a
+/// timer message, an exit signal or a `'DOWN'` message.
+pub fn lost_message(what: &str, dest: Term, err: &RtErr) {
+  error_report(format!("{} to {} was lost: {:?}", what, dest, err));
+}
+
+/// Whether some reports are waiting for `flush`.
+pub fn is_pending() -> bool {
+  !PENDING_REPORTS.lock().unwrap().is_empty()
+}
+
+/// Deliver the queued reports to the `logger` process, or print them.
+pub fn flush(vm: &VM) {
+  if !is_pending() {
+    return;
+  }
+  // Sent under the timers lock like the timer messages, then a report is
+  // either pending or delivered, see `VM::tick_scheduler`
+  let _timers = vm.timers.lock().unwrap();
+  let reports = core::mem::take(&mut *PENDING_REPORTS.lock().unwrap());
+  for text in reports {
+    match send_to_logger(vm, &text) {
+      Ok(true) => {}
+      Ok(false) => println!("error_report: {}", text),
+      Err(e) => println!("error_report: {} (sending to logger failed: {:?})", text, e),
+    }
+  }
+}
+
+/// Send the report to `logger`, returns `Ok(false)` if it is not running.
+fn send_to_logger(vm: &VM, text: &str) -> RtResult<bool> {
+  let logger_pid = match vm.processes.find_registered(gen_atoms::LOGGER) {
+    Some(pid) => pid,
+    None => return Ok(false),
+  };
+  if text.is_empty() {
+    return Ok(false);
+  }
+  // A string takes one cons cell per character
+  let size = SizeWords::new(text.chars().count() * 2) + boxed::Tuple::storage_size(2);
+  let mut fragment = Heap::new_fragment(size);
+  let message = unsafe {
+    let text_term = build_erlstr_from_utf8(text, &mut fragment)?;
+    tuple2(&mut fragment, gen_atoms::ERROR_REPORT, text_term)?
+  };
+  let signal = Signal::Message(OwnedTerm::new(message)?);
+  Ok(vm.send_signal(logger_pid, signal).is_ok())
+}
+
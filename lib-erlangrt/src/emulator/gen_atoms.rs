@@ -9,69 +9,161 @@ use crate::term::*;
 pub const SYM_PLUS: Term = Term::make_atom(0);
 pub const SYM_MINUS: Term = Term::make_atom(1);
 pub const SYM_EQ_EQ: Term = Term::make_atom(2);
-pub const ALL: Term = Term::make_atom(3);
-pub const APPLY: Term = Term::make_atom(4);
-pub const BADARG: Term = Term::make_atom(5);
-pub const BADARITH: Term = Term::make_atom(6);
-pub const BADARITY: Term = Term::make_atom(7);
-pub const BADFUN: Term = Term::make_atom(8);
-pub const BADMATCH: Term = Term::make_atom(9);
-pub const CASE_CLAUSE: Term = Term::make_atom(10);
-pub const ERLANG: Term = Term::make_atom(11);
-pub const ERROR: Term = Term::make_atom(12);
-pub const ERTS_INTERNAL: Term = Term::make_atom(13);
-pub const EXIT: Term = Term::make_atom(14);
-pub const FALSE: Term = Term::make_atom(15);
-pub const FUNCTION_CLAUSE: Term = Term::make_atom(16);
-pub const HIGH: Term = Term::make_atom(17);
-pub const IF_CLAUSE: Term = Term::make_atom(18);
-pub const INIT: Term = Term::make_atom(19);
-pub const KILL: Term = Term::make_atom(20);
-pub const KILLED: Term = Term::make_atom(21);
-pub const LOW: Term = Term::make_atom(22);
-pub const NIF_ERROR: Term = Term::make_atom(23);
-pub const NOCATCH: Term = Term::make_atom(24);
-pub const NORMAL: Term = Term::make_atom(25);
-pub const OK: Term = Term::make_atom(26);
-pub const SYSTEM_LIMIT: Term = Term::make_atom(27);
-pub const THROW: Term = Term::make_atom(28);
-pub const TRAP_EXIT: Term = Term::make_atom(29);
-pub const TRUE: Term = Term::make_atom(30);
-pub const UNDEF: Term = Term::make_atom(31);
-pub const UNDEFINED: Term = Term::make_atom(32);
+pub const DOWN_TAG: Term = Term::make_atom(3);
+pub const EXIT_TAG: Term = Term::make_atom(4);
+pub const ABS: Term = Term::make_atom(5);
+pub const ALL: Term = Term::make_atom(6);
+pub const APPLY: Term = Term::make_atom(7);
+pub const ASYNC: Term = Term::make_atom(8);
+pub const BADARG: Term = Term::make_atom(9);
+pub const BADARITH: Term = Term::make_atom(10);
+pub const BADARITY: Term = Term::make_atom(11);
+pub const BADFUN: Term = Term::make_atom(12);
+pub const BADMATCH: Term = Term::make_atom(13);
+pub const CANCEL_TIMER: Term = Term::make_atom(14);
+pub const CASE_CLAUSE: Term = Term::make_atom(15);
+pub const CURRENT_FUNCTION: Term = Term::make_atom(16);
+pub const DICTIONARY: Term = Term::make_atom(17);
+pub const ERLANG: Term = Term::make_atom(18);
+pub const ERROR: Term = Term::make_atom(19);
+pub const ERROR_LOGGER: Term = Term::make_atom(20);
+pub const ERROR_REPORT: Term = Term::make_atom(21);
+pub const ERTS_INTERNAL: Term = Term::make_atom(22);
+pub const EXIT: Term = Term::make_atom(23);
+pub const FALSE: Term = Term::make_atom(24);
+pub const FLUSH: Term = Term::make_atom(25);
+pub const FULLSWEEP_AFTER: Term = Term::make_atom(26);
+pub const FUNCTION_CLAUSE: Term = Term::make_atom(27);
+pub const HEAP_SIZE: Term = Term::make_atom(28);
+pub const HIGH: Term = Term::make_atom(29);
+pub const IF_CLAUSE: Term = Term::make_atom(30);
+pub const INFINITY: Term = Term::make_atom(31);
+pub const INFO: Term = Term::make_atom(32);
+pub const INIT: Term = Term::make_atom(33);
+pub const KILL: Term = Term::make_atom(34);
+pub const KILLED: Term = Term::make_atom(35);
+pub const LINK: Term = Term::make_atom(36);
+pub const LINKS: Term = Term::make_atom(37);
+pub const LOGGER: Term = Term::make_atom(38);
+pub const LOW: Term = Term::make_atom(39);
+pub const MAX: Term = Term::make_atom(40);
+pub const MAX_HEAP_SIZE: Term = Term::make_atom(41);
+pub const MESSAGE_QUEUE_DATA: Term = Term::make_atom(42);
+pub const MESSAGE_QUEUE_LEN: Term = Term::make_atom(43);
+pub const MESSAGES: Term = Term::make_atom(44);
+pub const MIN_BIN_VHEAP_SIZE: Term = Term::make_atom(45);
+pub const MIN_HEAP_SIZE: Term = Term::make_atom(46);
+pub const MONITOR: Term = Term::make_atom(47);
+pub const MONITORS: Term = Term::make_atom(48);
+pub const NIF_ERROR: Term = Term::make_atom(49);
+pub const NOCATCH: Term = Term::make_atom(50);
+pub const NONODE_AT_NOHOST: Term = Term::make_atom(51);
+pub const NOPROC: Term = Term::make_atom(52);
+pub const NORMAL: Term = Term::make_atom(53);
+pub const OFF_HEAP: Term = Term::make_atom(54);
+pub const OK: Term = Term::make_atom(55);
+pub const ON_HEAP: Term = Term::make_atom(56);
+pub const PRIORITY: Term = Term::make_atom(57);
+pub const PROCESS: Term = Term::make_atom(58);
+pub const READ_TIMER: Term = Term::make_atom(59);
+pub const REDUCTIONS: Term = Term::make_atom(60);
+pub const REGISTERED_NAME: Term = Term::make_atom(61);
+pub const RUNNABLE: Term = Term::make_atom(62);
+pub const RUNNING: Term = Term::make_atom(63);
+pub const SAVE_CALLS: Term = Term::make_atom(64);
+pub const SIZE: Term = Term::make_atom(65);
+pub const STACK_SIZE: Term = Term::make_atom(66);
+pub const STATUS: Term = Term::make_atom(67);
+pub const SYSTEM_LIMIT: Term = Term::make_atom(68);
+pub const TAG: Term = Term::make_atom(69);
+pub const THROW: Term = Term::make_atom(70);
+pub const TIMEOUT: Term = Term::make_atom(71);
+pub const TIMEOUT_VALUE: Term = Term::make_atom(72);
+pub const TOTAL_HEAP_SIZE: Term = Term::make_atom(73);
+pub const TRAP_EXIT: Term = Term::make_atom(74);
+pub const TRUE: Term = Term::make_atom(75);
+pub const UNDEF: Term = Term::make_atom(76);
+pub const UNDEFINED: Term = Term::make_atom(77);
+pub const WAITING: Term = Term::make_atom(78);
 
-pub static ATOM_INIT_NAMES: &[&str] = &[
+pub static ATOM_INIT_NAMES: &'static [&'static str] = &[
   "+", // id=0
   "-", // id=1
   "==", // id=2
-  "all", // id=3
-  "apply", // id=4
-  "badarg", // id=5
-  "badarith", // id=6
-  "badarity", // id=7
-  "badfun", // id=8
-  "badmatch", // id=9
-  "case_clause", // id=10
-  "erlang", // id=11
-  "error", // id=12
-  "erts_internal", // id=13
-  "exit", // id=14
-  "false", // id=15
-  "function_clause", // id=16
-  "high", // id=17
-  "if_clause", // id=18
-  "init", // id=19
-  "kill", // id=20
-  "killed", // id=21
-  "low", // id=22
-  "nif_error", // id=23
-  "nocatch", // id=24
-  "normal", // id=25
-  "ok", // id=26
-  "system_limit", // id=27
-  "throw", // id=28
-  "trap_exit", // id=29
-  "true", // id=30
-  "undef", // id=31
-  "undefined", // id=32
+  "DOWN", // id=3
+  "EXIT", // id=4
+  "abs", // id=5
+  "all", // id=6
+  "apply", // id=7
+  "async", // id=8
+  "badarg", // id=9
+  "badarith", // id=10
+  "badarity", // id=11
+  "badfun", // id=12
+  "badmatch", // id=13
+  "cancel_timer", // id=14
+  "case_clause", // id=15
+  "current_function", // id=16
+  "dictionary", // id=17
+  "erlang", // id=18
+  "error", // id=19
+  "error_logger", // id=20
+  "error_report", // id=21
+  "erts_internal", // id=22
+  "exit", // id=23
+  "false", // id=24
+  "flush", // id=25
+  "fullsweep_after", // id=26
+  "function_clause", // id=27
+  "heap_size", // id=28
+  "high", // id=29
+  "if_clause", // id=30
+  "infinity", // id=31
+  "info", // id=32
+  "init", // id=33
+  "kill", // id=34
+  "killed", // id=35
+  "link", // id=36
+  "links", // id=37
+  "logger", // id=38
+  "low", // id=39
+  "max", // id=40
+  "max_heap_size", // id=41
+  "message_queue_data", // id=42
+  "message_queue_len", // id=43
+  "messages", // id=44
+  "min_bin_vheap_size", // id=45
+  "min_heap_size", // id=46
+  "monitor", // id=47
+  "monitors", // id=48
+  "nif_error", // id=49
+  "nocatch", // id=50
+  "nonode@nohost", // id=51
+  "noproc", // id=52
+  "normal", // id=53
+  "off_heap", // id=54
+  "ok", // id=55
+  "on_heap", // id=56
+  "priority", // id=57
+  "process", // id=58
+  "read_timer", // id=59
+  "reductions", // id=60
+  "registered_name", // id=61
+  "runnable", // id=62
+  "running", // id=63
+  "save_calls", // id=64
+  "size", // id=65
+  "stack_size", // id=66
+  "status", // id=67
+  "system_limit", // id=68
+  "tag", // id=69
+  "throw", // id=70
+  "timeout", // id=71
+  "timeout_value", // id=72
+  "total_heap_size", // id=73
+  "trap_exit", // id=74
+  "true", // id=75
+  "undef", // id=76
+  "undefined", // id=77
+  "waiting", // id=78
 ];
@@ -1,85 +1,415 @@
 //! The classic BEAM design approach is to copy terms to the new owning heap
 //! when an object changes its owner process.
+//! Terms which live on a module literal heap are not copied, the copy refers
+//! to them directly (see `literal_area`).
+//! Two modes are supported: the default one copies every subterm as many
+//! times as it is referred to, while the sharing-preserving mode (like
+//! `copy_shared` in Erlang/OTP) remembers the subterms which were already
+//! copied and reuses them.
 // TODO: Smarter approach with refcounted movable objects or use shared heap or something else
 use crate::{
-  emulator::heap::THeap,
+  defs::{SizeWords, Word},
+  emulator::heap::{literal_area, AllocInit, THeap},
   fail::RtResult,
   term::{
-    boxed, cons,
-    term_builder::{ListBuilder, TupleBuilder},
+    boxed::{
+      self,
+      binary::{
+        match_state::BinaryMatchState, BinarySlice, BinaryType, ReferenceToBinary, TBinary,
+      },
+    },
     PrimaryTag, SpecialTag, Term,
   },
 };
+use core::ptr;
+use std::collections::{HashMap, HashSet};
 
 /// Copies term to another heap.
+#[allow(dead_code)]
 pub fn copy_to(term: Term, hp: &mut dyn THeap) -> RtResult<Term> {
-  match term.get_term_tag() {
-    PrimaryTag::BOX_PTR => unsafe { copy_boxed_to(term, hp) },
-    PrimaryTag::HEADER => {
-      panic!("Attempt to copy header value");
+  TermCopier::new(false).copy(term, hp)
+}
+
+/// Copies term to another heap, subterms which are referred to more than once
+/// are copied once and the copy is shared.
+pub fn copy_shared_to(term: Term, hp: &mut dyn THeap) -> RtResult<Term> {
+  TermCopier::new(true).copy(term, hp)
+}
+
+/// Calculate how much heap `copy_to` will need to copy the term, used to
+/// reserve the space before copying.
+#[allow(dead_code)]
+pub fn size_of(term: Term) -> RtResult<SizeWords> {
+  TermSizer::new(false).size_of(term)
+}
+
+/// Calculate how much heap `copy_shared_to` will need to copy the term.
+pub fn size_of_shared(term: Term) -> RtResult<SizeWords> {
+  TermSizer::new(true).size_of(term)
+}
+
+/// Pointer to the boxed object or cons cell, if the term is not immediate and
+/// must be copied.
+fn copyable_ptr(term: Term) -> Option<*const Word> {
+  let p = match term.get_term_tag() {
+    PrimaryTag::BOX_PTR => {
+      if term.is_cp() {
+        return None;
+      }
+      term.get_box_ptr::<Word>()
     }
-    PrimaryTag::CONS_PTR => unsafe { copy_cons_to(term, hp) },
+    PrimaryTag::CONS_PTR => term.get_cons_ptr() as *const Word,
+    PrimaryTag::HEADER => panic!("Attempt to copy header value"),
     PrimaryTag::SMALL_INT
     | PrimaryTag::ATOM
     | PrimaryTag::LOCAL_PID
-    | PrimaryTag::LOCAL_PORT => Ok(term),
+    | PrimaryTag::LOCAL_PORT => return None,
     PrimaryTag::SPECIAL => match term.get_special_tag() {
-      SpecialTag::CONST => Ok(term),
+      SpecialTag::CONST => return None,
       _ => panic!("Attempt to copy a special value: {}", term),
     },
     t => panic!("Not sure how to copy term with {:?}", t),
+  };
+  if literal_area::is_literal(p) {
+    return None;
+  }
+  Some(p)
+}
+
+struct TermCopier {
+  /// Source address to copied term, only used in the sharing mode
+  copied: Option<HashMap<usize, Term>>,
+}
+
+impl TermCopier {
+  fn new(shared: bool) -> Self {
+    Self {
+      copied: if shared { Some(HashMap::new()) } else { None },
+    }
+  }
+
+  fn copy(&mut self, term: Term, hp: &mut dyn THeap) -> RtResult<Term> {
+    let p = match copyable_ptr(term) {
+      Some(p) => p,
+      None => return Ok(term),
+    };
+    if let Some(copy) = self.find_copied(p) {
+      return Ok(copy);
+    }
+    if term.is_cons() {
+      unsafe { self.copy_cons(term, hp) }
+    } else {
+      unsafe { self.copy_boxed(term, hp) }
+    }
+  }
+
+  #[inline]
+  fn find_copied(&self, p: *const Word) -> Option<Term> {
+    match &self.copied {
+      Some(copied) => copied.get(&(p as usize)).cloned(),
+      None => None,
+    }
+  }
+
+  #[inline]
+  fn remember(&mut self, p: *const Word, copy: Term) {
+    if let Some(copied) = &mut self.copied {
+      copied.insert(p as usize, copy);
+    }
+  }
+
+  /// Copy the list cells one by one (without recursion, lists can be long),
+  /// each list element is copied recursively. Also copy the tail element.
+  unsafe fn copy_cons(&mut self, lst: Term, hp: &mut dyn THeap) -> RtResult<Term> {
+    let mut result = Term::nil();
+    // Previous copied cell, its tail is set when the next cell is ready
+    let mut prev: *mut boxed::Cons = ptr::null_mut();
+    let mut src = lst;
+
+    loop {
+      let tail = match copyable_ptr(src) {
+        Some(p) if src.is_cons() => match self.find_copied(p) {
+          Some(copy) => copy,
+          None => {
+            let src_p = src.get_cons_ptr();
+            let new_p = hp.alloc(SizeWords::new(2), AllocInit::Nil)? as *mut boxed::Cons;
+            let new_cell = Term::make_cons(new_p);
+            self.remember(p, new_cell);
+            if prev.is_null() {
+              result = new_cell;
+            } else {
+              (*prev).set_tl(new_cell);
+            }
+            let hd = self.copy((*src_p).hd(), hp)?;
+            (*new_p).set_hd(hd);
+            prev = new_p;
+            src = (*src_p).tl();
+            continue;
+          }
+        },
+        _ => self.copy(src, hp)?,
+      };
+      if prev.is_null() {
+        return Ok(tail);
+      }
+      (*prev).set_tl(tail);
+      return Ok(result);
+    }
+  }
+
+  /// Copy the boxed object as is, then replace the terms stored inside it with
+  /// their copies (same as the copying GC does in `scan_boxed`).
+  unsafe fn copy_boxed(&mut self, term: Term, hp: &mut dyn THeap) -> RtResult<Term> {
+    let header_p = term.get_box_ptr::<boxed::BoxHeader>();
+    let box_type = (*(*header_p).get_trait_ptr()).get_type();
+
+    if box_type == boxed::BOXTYPETAG_BINARY {
+      let bin_p = boxed::Binary::get_trait_from_term(term);
+      if let BinaryType::RefToBinaryHeap = (*bin_p).get_type() {
+        // Large binaries are not copied, only the reference is
+        let refbin_p = header_p as *const ReferenceToBinary;
+        let new_p = ReferenceToBinary::create_into((*refbin_p).pointer, hp)?;
+        let copy = (*new_p).make_term();
+        self.remember(header_p as *const Word, copy);
+        return Ok(copy);
+      }
+    }
+
+    let size = (*header_p).get_storage_size();
+    let new_p = hp.alloc(size, AllocInit::Uninitialized)?;
+    ptr::copy_nonoverlapping(header_p as *const Word, new_p, size.words);
+    let copy = Term::make_boxed(new_p);
+    self.remember(header_p as *const Word, copy);
+
+    let new_header_p = new_p as *mut boxed::BoxHeader;
+    match box_type {
+      boxed::BOXTYPETAG_TUPLE => {
+        let tuple_p = new_header_p as *mut boxed::Tuple;
+        for i in 0..(*tuple_p).get_arity() {
+          let el = self.copy((*tuple_p).get_element(i), hp)?;
+          (*tuple_p).set_element(i, el);
+        }
+      }
+      boxed::BOXTYPETAG_CLOSURE => {
+        let closure_p = new_header_p as *mut boxed::Closure;
+        for frozen in (*closure_p).get_frozen_mut().iter_mut() {
+          *frozen = self.copy(*frozen, hp)?;
+        }
+      }
+      boxed::BOXTYPETAG_MAP => {
+        for kv in boxed::Map::get_pairs_mut(new_header_p as *mut boxed::Map).iter_mut() {
+          *kv = self.copy(*kv, hp)?;
+        }
+      }
+      boxed::BOXTYPETAG_EXTERNALPID => {
+        let epid_p = new_header_p as *mut boxed::ExternalPid;
+        (*epid_p).node = self.copy((*epid_p).node, hp)?;
+      }
+      boxed::BOXTYPETAG_JUMP_TABLE => {
+        let jt_p = new_header_p as *mut boxed::JumpTable;
+        for i in 0..(*jt_p).get_count() {
+          let (val, loc) = (*jt_p).get_pair(i);
+          let val1 = self.copy(val, hp)?;
+          (*jt_p).set_pair(i, val1, loc);
+        }
+      }
+      boxed::BOXTYPETAG_BINARY => {
+        let bin_p = boxed::Binary::get_trait_mut(new_header_p as *mut boxed::Binary);
+        if let BinaryType::Slice = (*bin_p).get_type() {
+          let slice_p = new_header_p as *mut BinarySlice;
+          (*slice_p).orig = self.copy_binary_ptr((*slice_p).orig, hp)?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY_MATCH_STATE => {
+        let ms_p = new_header_p as *mut BinaryMatchState;
+        let src = self.copy_binary_ptr((*ms_p).get_src_binary(), hp)?;
+        (*ms_p).set_src_binary(src);
+      }
+      // No terms stored inside: bignum, float, import, export, external
+      // refs and ports
+      _ => {}
+    }
+    Ok(copy)
+  }
+
+  /// Binary slices and match states refer to their source binary with a raw
+  /// pointer, which has to point to the copy of the binary.
+  unsafe fn copy_binary_ptr(
+    &mut self,
+    bin_p: *const dyn TBinary,
+    hp: &mut dyn THeap,
+  ) -> RtResult<*const dyn TBinary> {
+    let new_term = self.copy((*bin_p).make_term(), hp)?;
+    Ok(boxed::Binary::get_trait_from_term(new_term))
   }
 }
 
-/// For each list element copy it to a new element in the destination heap.
-/// Also copy the tail element.
-/// Returns: `RtResult<copied_term>`
-unsafe fn copy_cons_to(lst: Term, hp: &mut dyn THeap) -> RtResult<Term> {
-  let mut lb = ListBuilder::new()?;
+struct TermSizer {
+  /// Source addresses already counted, only used in the sharing mode
+  seen: Option<HashSet<usize>>,
+}
 
-  let tail_el_result = cons::for_each(lst, |el| {
-    // Recurse into copy, for each list element
-    let copy_of_el = copy_to(el, hp)?;
-    lb.append(copy_of_el, hp)?;
-    Ok(())
-  })?;
+impl TermSizer {
+  fn new(shared: bool) -> Self {
+    Self {
+      seen: if shared { Some(HashSet::new()) } else { None },
+    }
+  }
 
-  if let Some(tail_el) = tail_el_result {
-    return Ok(lb.make_term_with_tail(tail_el));
+  /// Returns true if the object is visited for the first time (or if the
+  /// sharing is not tracked).
+  #[inline]
+  fn visit(&mut self, p: *const Word) -> bool {
+    match &mut self.seen {
+      Some(seen) => seen.insert(p as usize),
+      None => true,
+    }
   }
 
-  Ok(lb.make_term())
+  fn size_of(&mut self, term: Term) -> RtResult<SizeWords> {
+    let mut size = SizeWords::zero();
+    let mut t = term;
+    // Walk the list cells in a loop, the other terms recursively
+    loop {
+      let p = match copyable_ptr(t) {
+        Some(p) => p,
+        None => return Ok(size),
+      };
+      if !self.visit(p) {
+        return Ok(size);
+      }
+      if !t.is_cons() {
+        return Ok(size + unsafe { self.size_of_boxed(t)? });
+      }
+      let cons_p = t.get_cons_ptr();
+      size = size + SizeWords::new(2) + self.size_of(unsafe { (*cons_p).hd() })?;
+      t = unsafe { (*cons_p).tl() };
+    }
+  }
+
+  unsafe fn size_of_boxed(&mut self, term: Term) -> RtResult<SizeWords> {
+    let header_p = term.get_box_ptr::<boxed::BoxHeader>();
+    let mut size = (*header_p).get_storage_size();
+    match (*(*header_p).get_trait_ptr()).get_type() {
+      boxed::BOXTYPETAG_TUPLE => {
+        let tuple_p = header_p as *const boxed::Tuple;
+        for i in 0..(*tuple_p).get_arity() {
+          size = size + self.size_of((*tuple_p).get_element(i))?;
+        }
+      }
+      boxed::BOXTYPETAG_CLOSURE => {
+        let closure_p = header_p as *const boxed::Closure;
+        for frozen in (*closure_p).get_frozen().iter() {
+          size = size + self.size_of(*frozen)?;
+        }
+      }
+      boxed::BOXTYPETAG_MAP => {
+        for kv in boxed::Map::get_pairs_mut(header_p as *mut boxed::Map).iter() {
+          size = size + self.size_of(*kv)?;
+        }
+      }
+      boxed::BOXTYPETAG_EXTERNALPID => {
+        let epid_p = header_p as *const boxed::ExternalPid;
+        size = size + self.size_of((*epid_p).node)?;
+      }
+      boxed::BOXTYPETAG_JUMP_TABLE => {
+        let jt_p = header_p as *const boxed::JumpTable;
+        for i in 0..(*jt_p).get_count() {
+          size = size + self.size_of((*jt_p).get_pair(i).0)?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY => {
+        let bin_p = boxed::Binary::get_trait_from_term(term);
+        if let BinaryType::Slice = (*bin_p).get_type() {
+          let slice_p = header_p as *const BinarySlice;
+          size = size + self.size_of((*(*slice_p).orig).make_term())?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY_MATCH_STATE => {
+        let ms_p = header_p as *const BinaryMatchState;
+        size = size + self.size_of((*(*ms_p).get_src_binary()).make_term())?;
+      }
+      _ => {}
+    }
+    Ok(size)
+  }
 }
 
-unsafe fn copy_boxed_to(term: Term, hp: &mut dyn THeap) -> RtResult<Term> {
-  let header_ptr = term.get_box_ptr::<boxed::BoxHeader>();
-  let trait_ptr = (*header_ptr).get_trait_ptr();
-  let box_type = (*trait_ptr).get_type();
-
-  match box_type {
-    boxed::BOXTYPETAG_TUPLE => {
-      let tuple_p = header_ptr as *const boxed::Tuple;
-      let arity = (*tuple_p).get_arity();
-      let tb = TupleBuilder::with_arity(arity, hp)?;
-      for i in 0..arity {
-        let element = (*tuple_p).get_element(i);
-        let copied = copy_to(element, hp)?;
-        tb.set_element(i, copied);
-      }
-      return Ok(tb.make_term());
-    }
-    boxed::BOXTYPETAG_BIGINTEGER => {}
-    boxed::BOXTYPETAG_EXTERNALPID => {}
-    boxed::BOXTYPETAG_EXTERNALREF => {}
-    boxed::BOXTYPETAG_EXTERNALPORT => {}
-    boxed::BOXTYPETAG_CLOSURE => {}
-    boxed::BOXTYPETAG_FLOAT => {}
-    boxed::BOXTYPETAG_IMPORT => {}
-    boxed::BOXTYPETAG_EXPORT => {}
-    boxed::BOXTYPETAG_MAP => {}
-    boxed::BOXTYPETAG_BINARY => {}
-    _other => {}
-  }
-
-  panic!("Don't know how to copy {}", term);
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::term_builder::{tuple_builder::tuple2, ListBuilder},
+  };
+
+  /// Build `[a, b]` on the heap, 4 words.
+  fn make_list2(hp: &mut Heap, a: usize, b: usize) -> Term {
+    let mut lb = ListBuilder::new().unwrap();
+    unsafe {
+      lb.append(Term::make_small_unsigned(a), hp).unwrap();
+      lb.append(Term::make_small_unsigned(b), hp).unwrap();
+    }
+    lb.make_term()
+  }
+
+  unsafe fn element(t: Term, i: usize) -> Term {
+    (*t.get_tuple_ptr()).get_element(i)
+  }
+
+  #[test]
+  fn test_copy_term_sharing() {
+    let mut src = Heap::new(Designation::ProcessHeap);
+    let inner = make_list2(&mut src, 1, 2);
+    let outer = tuple2(&mut src, inner, inner).unwrap();
+
+    // Default mode copies `inner` twice, sharing mode once
+    let tuple_size = boxed::Tuple::storage_size(2).words;
+    assert_eq!(size_of(outer).unwrap().words, tuple_size + 8);
+    assert_eq!(size_of_shared(outer).unwrap().words, tuple_size + 4);
+
+    let mut dst = Heap::new(Designation::ProcessHeap);
+    let copy = copy_to(outer, &mut dst).unwrap();
+    unsafe {
+      assert_ne!(element(copy, 0), element(copy, 1));
+      assert_ne!(element(copy, 0), inner);
+      assert!(dst.belongs_to_heap(element(copy, 0).get_cons_ptr() as *const Word));
+    }
+
+    let mut dst = Heap::new(Designation::ProcessHeap);
+    let copy = copy_shared_to(outer, &mut dst).unwrap();
+    unsafe {
+      assert_eq!(element(copy, 0), element(copy, 1));
+      assert_ne!(element(copy, 0), inner);
+      let cons_p = element(copy, 0).get_cons_ptr();
+      assert!(dst.belongs_to_heap(cons_p as *const Word));
+      assert_eq!((*cons_p).hd(), Term::make_small_unsigned(1));
+    }
+    let used = dst.get_used_area();
+    assert_eq!(unsafe { used.end.offset_from(used.start) } as usize, tuple_size + 4);
+  }
+
+  #[test]
+  fn test_copy_term_skips_literals() {
+    let mut lit_heap = Heap::new(Designation::ModuleLiterals);
+    let literal = make_list2(&mut lit_heap, 1, 2);
+    literal_area::register(lit_heap.get_used_area());
+
+    let mut src = Heap::new(Designation::ProcessHeap);
+    let own = make_list2(&mut src, 3, 4);
+    let outer = tuple2(&mut src, literal, own).unwrap();
+    let tuple_size = boxed::Tuple::storage_size(2).words;
+    assert_eq!(size_of_shared(outer).unwrap().words, tuple_size + 4);
+
+    let mut dst = Heap::new(Designation::ProcessHeap);
+    let copy = copy_shared_to(outer, &mut dst).unwrap();
+    unsafe {
+      assert_eq!(element(copy, 0), literal);
+      assert_ne!(element(copy, 1), own);
+    }
+    assert!(literal_area::unregister(lit_heap.get_used_area()));
+  }
 }
@@ -1,11 +1,34 @@
 //! Copying Garbage Collector
 //! A simple implementation of half-heap copying (evicting) garbage collector
 //! somewhat similar to what is used in Erlang/OTP.
+//!
+//! Cheney algorithm: roots are evacuated first into the new heap, then the new
+//! heap is scanned linearly from the start, and every pointer found there is
+//! also evacuated, until the scan pointer meets the free pointer.
+//! Evacuated objects in the old heap are overwritten with forwarding pointers:
+//! * Boxed: the header word is replaced with a boxed pointer to the new copy.
+//! * Cons: the head is replaced with a non-value, and the tail holds the new
+//!   cons pointer.
+//!
+//! In generational mode objects which already survived a GC are promoted to
+//! the old heap instead, and the old heap tail is scanned same way.
 use crate::{
-  emulator::heap::{gc_trait::TGc, heap_trait::THeap, *},
+  defs::{SizeWords, Word},
+  emulator::heap::{
+    gc_trait::{GcArea, GcRequest, TGc},
+    heap_trait::THeap,
+    *,
+  },
   fail::RtResult,
-  term::{heap_walker::*, Term},
+  term::{
+    boxed::{
+      self,
+      binary::{match_state::BinaryMatchState, BinarySlice, BinaryType, TBinary},
+    },
+    PrimaryTag, Term,
+  },
 };
+use core::ptr;
 
 pub struct CopyingGc {}
 
@@ -14,12 +37,8 @@ impl TGc for CopyingGc {
     Self {}
   }
 
-  fn garbage_collect(
-    _heap: &dyn THeap,
-    mut walker: HeapWalker,
-    mut roots: Box<dyn TRootIterator>,
-  ) -> RtResult<()> {
-    println!("Copying GC");
+  fn garbage_collect(req: GcRequest, roots: &mut dyn TRootIterator) -> RtResult<()> {
+    let mut state = CopyingGcState::new(req);
 
     roots.roots_begin();
     loop {
@@ -27,18 +46,223 @@ impl TGc for CopyingGc {
       if r.is_null() {
         break;
       }
-      println!("root: {:?}", unsafe { *r });
+      unsafe { *r = state.evacuate(*r)? };
     }
 
+    unsafe { state.scan() }
+  }
+}
+
+/// A heap where the survivors are copied to, with Cheney scan state.
+struct GcDestination<'a> {
+  heap: &'a mut dyn THeap,
+  /// Next word in the destination to be inspected by the scan.
+  scan: *mut Word,
+  /// End of the data copied into the destination so far.
+  free: *mut Word,
+}
+
+impl<'a> GcDestination<'a> {
+  fn new(heap: &'a mut dyn THeap) -> Self {
+    Self {
+      heap,
+      scan: ptr::null_mut(),
+      free: ptr::null_mut(),
+    }
+  }
+
+  /// Allocate on the destination heap. The allocations are expected to be
+  /// contiguous so that the copied data can be scanned linearly.
+  unsafe fn alloc(&mut self, n: SizeWords) -> RtResult<*mut Word> {
+    let p = self.heap.alloc(n, AllocInit::Uninitialized)?;
+    debug_assert!(self.free.is_null() || self.free == p);
+    if self.scan.is_null() {
+      self.scan = p;
+    }
+    self.free = p.add(n.words);
+    Ok(p)
+  }
+
+  #[inline]
+  fn has_unscanned(&self) -> bool {
+    self.scan < self.free
+  }
+}
+
+/// Working state of a single garbage collection run.
+struct CopyingGcState<'a> {
+  collect: Vec<GcArea>,
+  young: GcDestination<'a>,
+  /// Area of aged objects, and the old heap where they are promoted
+  promote: Option<(GcArea, GcDestination<'a>)>,
+}
+
+impl<'a> CopyingGcState<'a> {
+  fn new(req: GcRequest<'a>) -> Self {
+    Self {
+      collect: req.collect,
+      young: GcDestination::new(req.young_dest),
+      promote: req
+        .promote
+        .map(|(area, old)| (area, GcDestination::new(old))),
+    }
+  }
+
+  #[inline]
+  fn is_collected(&self, p: *const Word) -> bool {
+    self.collect.iter().any(|area| area.contains(p))
+  }
+
+  /// Allocate space for a moved object, the destination depends on the
+  /// object's age.
+  unsafe fn alloc_for(&mut self, p: *const Word, n: SizeWords) -> RtResult<*mut Word> {
+    if let Some((aged, old)) = &mut self.promote {
+      if aged.contains(p) {
+        return old.alloc(n);
+      }
+    }
+    self.young.alloc(n)
+  }
+
+  /// Check whether `t` points into a collected area, then move the object it
+  /// points to (or follow the forwarding pointer if already moved).
+  /// Returns: updated term value.
+  unsafe fn evacuate(&mut self, t: Term) -> RtResult<Term> {
+    match t.get_term_tag() {
+      PrimaryTag::BOX_PTR => {
+        if t.is_cp() {
+          return Ok(t);
+        }
+        let p = t.get_box_ptr_unchecked_mut::<Word>();
+        if !self.is_collected(p) {
+          return Ok(t);
+        }
+        let header = Term::from_raw(*p);
+        if header.is_boxed() {
+          // Already moved, follow the forwarding pointer
+          return Ok(header);
+        }
+        let size = boxed::BoxHeader::headerword_to_storage_size(header.raw());
+        let new_p = self.alloc_for(p, size)?;
+        ptr::copy_nonoverlapping(p, new_p, size.words);
+        let result = Term::make_boxed(new_p);
+        *p = result.raw();
+        Ok(result)
+      }
+      PrimaryTag::CONS_PTR => {
+        let p = t.get_cons_ptr_mut() as *mut Word;
+        if !self.is_collected(p) {
+          return Ok(t);
+        }
+        if Term::from_raw(*p).is_non_value() {
+          // Already moved, the tail contains the new location
+          return Ok(Term::from_raw(*p.add(1)));
+        }
+        let new_p = self.alloc_for(p, SizeWords::new(2))?;
+        ptr::copy_nonoverlapping(p, new_p, 2);
+        let result = Term::make_cons(new_p);
+        *p = Term::non_value().raw();
+        *p.add(1) = result.raw();
+        Ok(result)
+      }
+      _ => Ok(t),
+    }
+  }
+
+  /// Walk the copied data from start to the end, evacuating whatever is
+  /// referred from the objects found there. Repeat for both destinations until
+  /// no new data is copied.
+  unsafe fn scan(&mut self) -> RtResult<()> {
     loop {
-      let p = walker.next();
-      if p.is_null() {
-        break;
+      if self.young.has_unscanned() {
+        self.young.scan = self.scan_one(self.young.scan)?;
+        continue;
+      }
+      let old_scan = match &self.promote {
+        Some((_, old)) if old.has_unscanned() => old.scan,
+        _ => return Ok(()),
+      };
+      let next = self.scan_one(old_scan)?;
+      if let Some((_, old)) = &mut self.promote {
+        old.scan = next;
       }
-      let pval = unsafe { Term::from_raw(*p) };
-      println!("Heapwalker: {pval}");
     }
+  }
+
+  /// Scan one object at `p`. Cons cells are scanned word by word, boxed
+  /// objects are stepped over using their header size.
+  /// Returns: pointer to the next object.
+  unsafe fn scan_one(&mut self, p: *mut Word) -> RtResult<*mut Word> {
+    let val = Term::from_raw(*p);
+    if val.is_header_word() {
+      let size = boxed::BoxHeader::headerword_to_storage_size(val.raw());
+      self.scan_boxed(p as *mut boxed::BoxHeader)?;
+      Ok(p.add(size.words))
+    } else {
+      *p = self.evacuate(val)?.raw();
+      Ok(p.add(1))
+    }
+  }
+
+  /// Update the terms stored inside a boxed object which was just moved.
+  unsafe fn scan_boxed(&mut self, header_p: *mut boxed::BoxHeader) -> RtResult<()> {
+    let trait_ptr = (*header_p).get_trait_ptr();
+    match (*trait_ptr).get_type() {
+      boxed::BOXTYPETAG_TUPLE => {
+        let tuple_p = header_p as *mut boxed::Tuple;
+        for i in 0..(*tuple_p).get_arity() {
+          let el = self.evacuate((*tuple_p).get_element(i))?;
+          (*tuple_p).set_element(i, el);
+        }
+      }
+      boxed::BOXTYPETAG_CLOSURE => {
+        let closure_p = header_p as *mut boxed::Closure;
+        for frozen in (*closure_p).get_frozen_mut().iter_mut() {
+          *frozen = self.evacuate(*frozen)?;
+        }
+      }
+      boxed::BOXTYPETAG_MAP => {
+        for kv in boxed::Map::get_pairs_mut(header_p as *mut boxed::Map).iter_mut() {
+          *kv = self.evacuate(*kv)?;
+        }
+      }
+      boxed::BOXTYPETAG_EXTERNALPID => {
+        let epid_p = header_p as *mut boxed::ExternalPid;
+        (*epid_p).node = self.evacuate((*epid_p).node)?;
+      }
+      boxed::BOXTYPETAG_JUMP_TABLE => {
+        let jt_p = header_p as *mut boxed::JumpTable;
+        for i in 0..(*jt_p).get_count() {
+          let (val, loc) = (*jt_p).get_pair(i);
+          let val1 = self.evacuate(val)?;
+          (*jt_p).set_pair(i, val1, loc);
+        }
+      }
+      boxed::BOXTYPETAG_BINARY => {
+        let bin_p = boxed::Binary::get_trait_mut(header_p as *mut boxed::Binary);
+        if let BinaryType::Slice = (*bin_p).get_type() {
+          let slice_p = header_p as *mut BinarySlice;
+          (*slice_p).orig = self.evacuate_binary_ptr((*slice_p).orig)?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY_MATCH_STATE => {
+        let ms_p = header_p as *mut BinaryMatchState;
+        let src = self.evacuate_binary_ptr((*ms_p).get_src_binary())?;
+        (*ms_p).set_src_binary(src);
+      }
+      // No terms stored inside: bignum, float, import, export
+      _ => {}
+    }
+    Ok(())
+  }
 
-    unimplemented!("Copying GC: Done")
+  /// Binary slices and match states refer to their source binary with a raw
+  /// pointer, which has to be updated if the binary was moved.
+  unsafe fn evacuate_binary_ptr(
+    &mut self,
+    bin_p: *const dyn TBinary,
+  ) -> RtResult<*const dyn TBinary> {
+    let new_term = self.evacuate((*bin_p).make_term())?;
+    Ok(boxed::Binary::get_trait_from_term(new_term))
   }
 }
@@ -1,20 +1,48 @@
 //! Trait for Garbage Collector
 //! GC can only be compatible with the heap type it is designed for.
 use crate::{
+  defs::Word,
   emulator::heap::{heap_trait::THeap, *},
   fail::RtResult,
-  term::heap_walker::*,
 };
 
+/// A memory range `[start, end)` on some heap.
+#[derive(Copy, Clone)]
+pub struct GcArea {
+  pub start: *const Word,
+  pub end: *const Word,
+}
+
+impl GcArea {
+  pub fn new(start: *const Word, end: *const Word) -> Self {
+    Self { start, end }
+  }
+
+  #[inline]
+  pub fn contains(&self, p: *const Word) -> bool {
+    p >= self.start && p < self.end
+  }
+}
+
+/// Describes a single garbage collection run: what is collected and where the
+/// survivors are moved.
+pub struct GcRequest<'a> {
+  /// Memory areas being collected. Pointers outside of them are left as is.
+  pub collect: Vec<GcArea>,
+  /// Fresh empty heap where the live data will be moved.
+  pub young_dest: &'a mut dyn THeap,
+  /// For generational collection: objects found in this area (part of
+  /// `collect`) have already survived a GC and are promoted to the old heap,
+  /// appending to the end of it.
+  pub promote: Option<(GcArea, &'a mut dyn THeap)>,
+}
+
 pub trait TGc {
   fn new() -> Self;
 
-  /// GC takes a range of mutable-term-ranges
-  fn garbage_collect(
-    heap: &dyn THeap,
-    walker: HeapWalker,
-    roots: Box<dyn TRootIterator>,
-  ) -> RtResult<()>;
+  /// Move live data as described by `req`. The roots are updated in place
+  /// with the new locations.
+  fn garbage_collect(req: GcRequest, roots: &mut dyn TRootIterator) -> RtResult<()>;
 }
 
 /// Null GC does nothing, and instead panics
@@ -26,7 +54,7 @@ impl TGc for NullGc {
     Self {}
   }
 
-  fn garbage_collect(_heap: &dyn THeap, _walker: HeapWalker,_roots: Box<dyn TRootIterator>) -> RtResult<()> {
+  fn garbage_collect(_req: GcRequest, _roots: &mut dyn TRootIterator) -> RtResult<()> {
     unimplemented!("NullGC is not designed to collect any garbage")
   }
 }
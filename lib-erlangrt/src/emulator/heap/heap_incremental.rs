@@ -3,15 +3,28 @@
 //! * Incremental allocation.
 //! * Second heap is created on GC. Live data is moved into the new heap.
 //!   The old heap is discarded.
-//!
-//! Possible improvements:
-//!
-//! * The young values get garbaged more often, introduce an age mark.
+//! * Generational: data which survived one GC is below the high water mark,
+//!   on the next minor GC it is promoted to the old heap. Every
+//!   `fullsweep_after` minor GCs, both young and old data are collected.
+//! * Heap sizes are picked from a Fibonacci-like table. After GC the heap grows
+//!   if it is more than 75% full, or shrinks (not below `min_heap_size`) if it
+//!   is less than 25% full.
+//! * Heap fragments (off-heap messages) can be attached to the heap, their
+//!   live data is moved into the heap on the next GC.
+//! * Hibernation discards the stack and compacts the heap to fit the live data.
+//! * References to binaries on the binary heap are tracked, and released when
+//!   a GC finds them dead. Their total size is the virtual binary heap, which
+//!   also can trigger a GC.
 use crate::{
-  defs::{Word, SizeWords},
-  emulator::heap::{catch::NextCatchResult, gc_trait::TGc, heap_trait::*, iter, *},
+  defs::{SizeWords, Word},
+  emulator::heap::{
+    catch::NextCatchResult,
+    gc_trait::{GcArea, GcRequest, TGc},
+    heap_trait::*,
+    iter, *,
+  },
   fail::{RtErr, RtResult},
-  term::{heap_walker::*, Term},
+  term::{boxed::binary::ReferenceToBinary, Term},
 };
 use colored::Colorize;
 use core::fmt;
@@ -19,9 +32,12 @@ use core::fmt;
 /// Default heap size for constants (literals) when loading a module.
 const DEFAULT_LIT_HEAP: usize = 8192;
 
-/// Default heap size when spawning a process. (default: 300)
-const DEFAULT_PROC_HEAP: usize = 1024;
-const BINARY_HEAP_CAPACITY: usize = 65536; // 64k*8 = 512kb
+/// First two sizes in the heap size table, the following sizes are sums of two
+/// previous sizes (same as in Erlang/OTP).
+const HEAP_SIZE_TABLE_START: (usize, usize) = (233, 377);
+
+/// After this size the heap size table grows by 20% instead of Fibonacci.
+const HEAP_SIZE_FIB_LIMIT: usize = 1_346_269;
 
 /// A heap structure which allocates incrementally forward.
 /// Stack grows backwards until they meet with the heap.
@@ -39,6 +55,33 @@ where
   stack_top: usize,
   /// Marks end of the stack and also end of the heap.
   capacity: usize,
+
+  /// Old generation, data which survived two GCs is promoted here. Only the
+  /// heap part is used, it has no stack.
+  old_heap: Option<Box<IncrementalHeap<GC>>>,
+  /// High water mark: heap data below this offset has survived a GC.
+  high_water: usize,
+  /// Minor GC count since the last fullsweep.
+  minor_gcs: usize,
+  /// Force a fullsweep after this many minor GCs, 0 disables generational GC.
+  fullsweep_after: usize,
+
+  /// The heap will not shrink below this capacity.
+  min_heap_size: usize,
+  /// Virtual binary heap size: how much off-heap binary data (in words) this
+  /// heap may refer to before a GC is wanted.
+  bin_vheap_size: usize,
+  /// The virtual binary heap will not shrink below this size.
+  min_bin_vheap_size: usize,
+  /// Off-heap binary data (in words) referred from this heap.
+  bin_vheap_used: usize,
+  /// References to binaries on the binary heap, allocated on this heap, the
+  /// old heap or the fragments.
+  refc_binaries: Vec<*mut ReferenceToBinary>,
+
+  /// Heap fragments attached to this heap, such as received off-heap messages.
+  /// They are collected together with the heap on the next GC.
+  fragments: Vec<Self>,
 }
 
 impl<GC: TGc> fmt::Debug for IncrementalHeap<GC> {
@@ -57,10 +100,10 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
     let pos = self.heap_top;
     let n_words = n.words;
 
-    // Explicitly forbid expanding without a GC, fail if capacity is exceeded
-    // This situation has to be detected before we arrive here
-    if pos + n_words >= self.stack_top {
-      panic!("Heap is full requested={}", n);
+    // Explicitly forbid expanding without a GC, fail if capacity is exceeded.
+    // The caller will run a GC and retry
+    if pos + n_words > self.stack_top {
+      return Err(RtErr::HeapIsFull(n));
     }
 
     // Assume we can grow the data without reallocating
@@ -80,10 +123,21 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
     Ok(new_chunk)
   }
 
-  #[inline]
-  fn garbage_collect(&mut self, roots: Box<dyn TRootIterator>) -> RtResult<()> {
-    let walker = HeapWalker::new(self.get_heap_start_ptr_mut(), self.get_heap_top_ptr_mut());
-    GC::garbage_collect(self, walker, roots)
+  /// Run either a minor GC (young data only) or a fullsweep (young and old).
+  /// Live data is moved to a new heap, the current heap data is discarded.
+  /// Then grow or shrink the heap if the live data does not fit it well.
+  fn garbage_collect(
+    &mut self,
+    need: SizeWords,
+    mut roots: Box<dyn TRootIterator>,
+  ) -> RtResult<()> {
+    if self.gc_wants_fullsweep() {
+      self.gc_fullsweep(roots.as_mut())?;
+    } else {
+      self.gc_minor(roots.as_mut())?;
+    }
+    self.gc_adjust_bin_vheap_size();
+    self.gc_adjust_size(need, roots.as_mut())
   }
 
   fn get_y(&self, index: Word) -> RtResult<Term> {
@@ -164,10 +218,20 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
     self.heap_top + need.words <= self.stack_top
   }
 
-  /// Allocate stack cells without checking. Call `stack_have(n)` beforehand.
-  fn stack_alloc(&mut self, need: SizeWords, _extra: SizeWords, fill: AllocInit) {
+  /// Allocate stack cells, and check that `extra` more words are available
+  /// after that. The cells are always cleared, regardless of `fill`, because
+  /// the GC scans every stack cell and must not see garbage values there.
+  fn stack_alloc(
+    &mut self,
+    need: SizeWords,
+    extra: SizeWords,
+    _fill: AllocInit,
+  ) -> RtResult<()> {
+    if !self.stack_check_available(need + extra) {
+      return Err(RtErr::HeapIsFull(need + extra));
+    }
     if need.words == 0 {
-      return;
+      return Ok(());
     }
     self.stack_top -= need.words;
 
@@ -175,13 +239,11 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
     let raw_nil = Term::nil().raw();
     unsafe {
       let p = self.get_heap_start_ptr_mut().add(self.stack_top);
-
-      if fill == AllocInit::Nil {
-        for y in 0..need.words {
-          p.add(y).write(raw_nil)
-        }
+      for y in 0..need.words {
+        p.add(y).write(raw_nil)
       }
     }
+    Ok(())
   }
 
   fn stack_depth(&self) -> usize {
@@ -246,7 +308,21 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
   }
 
   fn belongs_to_heap(&self, p: *const Word) -> bool {
-    p < self.get_heap_start_ptr() || p >= self.get_heap_top_ptr()
+    if self.get_used_area().contains(p) {
+      return true;
+    }
+    if self.fragments.iter().any(|frag| frag.belongs_to_heap(p)) {
+      return true;
+    }
+    match &self.old_heap {
+      Some(old) => old.belongs_to_heap(p),
+      None => false,
+    }
+  }
+
+  fn register_refc_binary(&mut self, refbin: *mut ReferenceToBinary) {
+    self.bin_vheap_used += unsafe { (*refbin).size.get_words_rounded_up().words };
+    self.refc_binaries.push(refbin);
   }
 
   fn stack_dump(&self) {
@@ -267,21 +343,32 @@ impl<GC: TGc> THeap for IncrementalHeap<GC> {
   }
 }
 
+impl<GC: TGc> Drop for IncrementalHeap<GC> {
+  /// Release the binaries referred from this heap.
+  fn drop(&mut self) {
+    for refbin in self.refc_binaries.drain(..) {
+      unsafe { ReferenceToBinary::on_destroy(refbin) }
+    }
+  }
+}
+
 // === === ===
 
 impl<GC: TGc> IncrementalHeap<GC> {
   fn get_size_for(d: Designation) -> usize {
     match d {
-      Designation::ProcessHeap => DEFAULT_PROC_HEAP,
+      Designation::ProcessHeap => HEAP_SIZE_TABLE_START.0,
       Designation::ModuleLiterals => DEFAULT_LIT_HEAP,
-      Designation::BinaryHeap => BINARY_HEAP_CAPACITY,
       Designation::TransientDestructible => 1,
       Designation::ProgramArgumentsHeap => 512,
     }
   }
 
   pub fn new(designation: Designation) -> Self {
-    let capacity = Self::get_size_for(designation);
+    Self::with_capacity(Self::get_size_for(designation))
+  }
+
+  fn with_capacity(capacity: usize) -> Self {
     assert!(capacity > 0);
     let mut h = Self {
       gc: GC::new(),
@@ -289,6 +376,16 @@ impl<GC: TGc> IncrementalHeap<GC> {
       heap_top: 0,
       stack_top: capacity,
       capacity,
+      old_heap: None,
+      high_water: 0,
+      minor_gcs: 0,
+      fullsweep_after: 0,
+      min_heap_size: 0,
+      bin_vheap_size: 0,
+      min_bin_vheap_size: 0,
+      bin_vheap_used: 0,
+      refc_binaries: Vec::new(),
+      fragments: Vec::new(),
     };
     unsafe { h.data.set_len(capacity) };
     h
@@ -329,12 +426,6 @@ impl<GC: TGc> IncrementalHeap<GC> {
     unsafe { self.get_heap_start_ptr().add(self.heap_top) }
   }
 
-  /// Get pointer to end of the allocated heap (below the stack top).
-  #[inline]
-  pub fn get_heap_top_ptr_mut(&mut self) -> *mut Word {
-    unsafe { self.get_heap_start_ptr_mut().add(self.heap_top) }
-  }
-
   /// Stack start is same as end of everything, pointer to the first word after
   /// the allocated memory, used as limit when iterating the stack.
   #[inline]
@@ -352,6 +443,311 @@ impl<GC: TGc> IncrementalHeap<GC> {
     self.get_heap_start_ptr().add(self.stack_top)
   }
 
+  /// Set how many minor GCs are allowed before a fullsweep, 0 disables
+  /// generational GC and every GC will be a fullsweep.
+  pub fn set_fullsweep_after(&mut self, n: usize) {
+    self.fullsweep_after = n;
+  }
+
+  /// Create a heap fragment to store `size` words of off-heap data, such as
+  /// a message. It has no stack and is never collected on its own.
+  pub fn new_fragment(size: SizeWords) -> Self {
+    Self::with_capacity(size.words.max(1))
+  }
+
+  /// Take ownership of a heap fragment. The data in it stays valid until the
+  /// next GC, which will move the live parts into this heap.
+  pub fn attach_fragment(&mut self, mut fragment: Self) {
+    self.bin_vheap_used += fragment.bin_vheap_used;
+    fragment.bin_vheap_used = 0;
+    self.refc_binaries.append(&mut fragment.refc_binaries);
+    self.fragments.push(fragment);
+  }
+
+  /// Words used in the attached heap fragments.
+  fn get_fragments_used(&self) -> usize {
+    self.fragments.iter().map(|frag| frag.heap_top).sum()
+  }
+
+  /// Size of the young heap with the stack in words.
+  #[inline]
+  pub fn get_heap_size(&self) -> usize {
+    self.capacity
+  }
+
+  /// Memory taken by the process heap in words: young heap with the stack,
+  /// the old heap, and attached heap fragments.
+  pub fn get_total_size(&self) -> usize {
+    let frags: usize = self.fragments.iter().map(|frag| frag.capacity).sum();
+    self.capacity + self.old_heap.as_ref().map_or(0, |old| old.capacity) + frags
+  }
+
+  /// Set the minimal heap size in words, rounded up to the heap size table.
+  /// The heap must be empty, it is reallocated to the new size.
+  pub fn set_min_heap_size(&mut self, n: usize) {
+    assert!(
+      self.heap_top == 0 && self.stack_depth() == 0,
+      "min_heap_size can only be set on an empty heap"
+    );
+    let capacity = Self::next_heap_size(n);
+    let mut new_heap = Self::with_capacity(capacity);
+    core::mem::swap(&mut self.data, &mut new_heap.data);
+    self.stack_top = capacity;
+    self.capacity = capacity;
+    self.min_heap_size = capacity;
+  }
+
+  /// Set the initial virtual binary heap size in words, it will not shrink
+  /// below that.
+  pub fn set_min_bin_vheap_size(&mut self, n: usize) {
+    self.bin_vheap_size = n;
+    self.min_bin_vheap_size = n;
+  }
+
+  /// Whether the heap refers to more binary heap data than the virtual binary
+  /// heap size allows, then a GC is wanted to release dead binaries.
+  pub fn is_bin_vheap_full(&self) -> bool {
+    self.bin_vheap_size != 0 && self.bin_vheap_used > self.bin_vheap_size
+  }
+
+  /// Find the smallest size in the heap size table which is not less than
+  /// `need`. The table is Fibonacci-like up to `HEAP_SIZE_FIB_LIMIT`, then
+  /// every next size is 20% larger.
+  pub fn next_heap_size(need: usize) -> usize {
+    let (mut size, mut next) = HEAP_SIZE_TABLE_START;
+    while size < need {
+      let after = if next < HEAP_SIZE_FIB_LIMIT {
+        size + next
+      } else {
+        next + next / 5
+      };
+      size = next;
+      next = after;
+    }
+    size
+  }
+
+  /// Memory range occupied by the heap data (not including the stack).
+  pub fn get_used_area(&self) -> GcArea {
+    GcArea::new(self.get_heap_start_ptr(), self.get_heap_top_ptr())
+  }
+
+  /// Memory ranges which hold the data of this heap: the heap itself, the old
+  /// heap and the attached fragments.
+  pub fn get_data_areas(&self) -> Vec<GcArea> {
+    let mut areas = vec![self.get_used_area()];
+    if let Some(old) = &self.old_heap {
+      areas.push(old.get_used_area());
+    }
+    for frag in self.fragments.iter() {
+      areas.push(frag.get_used_area());
+    }
+    areas
+  }
+
+  /// Memory range occupied by the stack.
+  pub fn get_stack_area(&self) -> GcArea {
+    unsafe { GcArea::new(self.get_stack_top_ptr(), self.get_stack_start_ptr()) }
+  }
+
+  /// Fullsweep is done when it is time to, or when the old heap might not have
+  /// enough room for everything below the high water mark.
+  fn gc_wants_fullsweep(&self) -> bool {
+    if self.fullsweep_after == 0 || self.minor_gcs >= self.fullsweep_after {
+      return true;
+    }
+    match &self.old_heap {
+      Some(old) => !old.heap_check_available(SizeWords::new(self.high_water)),
+      None => false,
+    }
+  }
+
+  /// Create an empty heap and move our stack there as is.
+  /// Returns: the new heap, and the stack cells as roots for the GC.
+  fn gc_create_new_heap(&self, capacity: usize) -> (Self, ArrayRootIterator) {
+    let mut new_heap = Self::with_capacity(capacity);
+    new_heap.fullsweep_after = self.fullsweep_after;
+
+    let depth = self.stack_depth();
+    new_heap.stack_top = new_heap.capacity - depth;
+    new_heap.data[new_heap.stack_top..].copy_from_slice(&self.data[self.stack_top..]);
+
+    let stack_roots = unsafe {
+      let stack_p = new_heap.get_heap_start_ptr_mut().add(new_heap.stack_top);
+      let stack_slice = core::slice::from_raw_parts_mut(stack_p as *mut Term, depth);
+      ArrayRootIterator::new(stack_slice)
+    };
+    (new_heap, stack_roots)
+  }
+
+  /// Take over the memory of the heap where the GC has moved live data.
+  /// Everything which is in the heap now has survived a GC.
+  fn gc_replace_with(&mut self, mut new_heap: Self) {
+    core::mem::swap(&mut self.data, &mut new_heap.data);
+    self.heap_top = new_heap.heap_top;
+    self.stack_top = new_heap.stack_top;
+    self.capacity = new_heap.capacity;
+    self.high_water = self.heap_top;
+  }
+
+  /// Collect the young data. Data below the high water mark goes to the old
+  /// heap, the rest goes to the new young heap.
+  fn gc_minor(&mut self, roots: &mut dyn TRootIterator) -> RtResult<()> {
+    // Fragment data is young, it might all survive
+    let need = self.heap_top + self.get_fragments_used() + self.stack_depth();
+    let (mut new_heap, stack_roots) = self.gc_create_new_heap(self.capacity.max(need));
+    let mut all_roots =
+      ChainRootIterator::new(vec![Box::new(stack_roots), Box::new(roots)]);
+
+    let young_area = self.get_used_area();
+    let aged_area = unsafe {
+      let start = self.get_heap_start_ptr();
+      GcArea::new(start, start.add(self.high_water))
+    };
+    if self.high_water > 0 && self.old_heap.is_none() {
+      self.old_heap = Some(Box::new(Self::with_capacity(self.capacity)));
+    }
+    let promote = match &mut self.old_heap {
+      Some(old) if self.high_water > 0 => {
+        Some((aged_area, old.as_mut() as &mut dyn THeap))
+      }
+      _ => None,
+    };
+    let mut collect = vec![young_area];
+    collect.extend(self.fragments.iter().map(|frag| frag.get_used_area()));
+    let req = GcRequest {
+      collect: collect.clone(),
+      young_dest: &mut new_heap,
+      promote,
+    };
+    GC::garbage_collect(req, &mut all_roots)?;
+    self.gc_sweep_refc_binaries(&collect);
+
+    self.gc_replace_with(new_heap);
+    self.fragments.clear();
+    self.minor_gcs += 1;
+    Ok(())
+  }
+
+  /// Collect both young and old data into a new young heap. The old heap is
+  /// discarded.
+  fn gc_fullsweep(&mut self, roots: &mut dyn TRootIterator) -> RtResult<()> {
+    // Everything might survive, ensure that it will fit
+    let old_used = self.old_heap.as_ref().map_or(0, |old| old.heap_top);
+    let need = self.heap_top + old_used + self.get_fragments_used() + self.stack_depth();
+    let (mut new_heap, stack_roots) = self.gc_create_new_heap(self.capacity.max(need));
+    let mut all_roots =
+      ChainRootIterator::new(vec![Box::new(stack_roots), Box::new(roots)]);
+
+    let mut collect = vec![self.get_used_area()];
+    if let Some(old) = &self.old_heap {
+      collect.push(old.get_used_area());
+    }
+    collect.extend(self.fragments.iter().map(|frag| frag.get_used_area()));
+    let req = GcRequest {
+      collect: collect.clone(),
+      young_dest: &mut new_heap,
+      promote: None,
+    };
+    GC::garbage_collect(req, &mut all_roots)?;
+    self.gc_sweep_refc_binaries(&collect);
+
+    self.gc_replace_with(new_heap);
+    self.fragments.clear();
+    self.old_heap = None;
+    self.minor_gcs = 0;
+    Ok(())
+  }
+
+  /// For hibernation: discard the stack, collect everything and move the live
+  /// data into a heap which fits it exactly. The heap will grow on the next GC
+  /// after the process wakes up.
+  pub fn gc_hibernate(&mut self, roots: &mut dyn TRootIterator) -> RtResult<()> {
+    self.stack_top = self.capacity;
+    self.gc_fullsweep(roots)?;
+    self.gc_adjust_bin_vheap_size();
+    self.gc_resize(self.heap_top.max(1), roots)
+  }
+
+  /// After GC: grow the heap if the live data with `need` words on top of it
+  /// takes more than 75% of the capacity, or shrink it if less than 25%.
+  fn gc_adjust_size(
+    &mut self,
+    need: SizeWords,
+    roots: &mut dyn TRootIterator,
+  ) -> RtResult<()> {
+    let wanted = self.heap_top + self.stack_depth() + need.words;
+    let new_capacity = if wanted * 4 > self.capacity * 3 {
+      Self::next_heap_size(wanted * 4 / 3 + 1)
+    } else if wanted * 4 < self.capacity && self.capacity > self.min_heap_size {
+      Self::next_heap_size(wanted * 2).max(self.min_heap_size)
+    } else {
+      return Ok(());
+    };
+    if new_capacity == self.capacity {
+      return Ok(());
+    }
+    self.gc_resize(new_capacity, roots)
+  }
+
+  /// After GC: the references to binaries which were in the collected areas
+  /// have either moved (the header is now a forwarding pointer), or they are
+  /// garbage and their binaries are released.
+  fn gc_sweep_refc_binaries(&mut self, collected: &[GcArea]) {
+    let mut released = 0;
+    self.refc_binaries.retain_mut(|refbin| unsafe {
+      let p = *refbin as *const Word;
+      if !collected.iter().any(|area| area.contains(p)) {
+        return true;
+      }
+      let header = Term::from_raw(*p);
+      if header.is_boxed() {
+        *refbin = header.get_box_ptr_mut::<ReferenceToBinary>();
+        return true;
+      }
+      released += (**refbin).size.get_words_rounded_up().words;
+      ReferenceToBinary::on_destroy(*refbin);
+      false
+    });
+    self.bin_vheap_used -= released;
+  }
+
+  /// After GC: grow the virtual binary heap if the binaries which are still
+  /// referenced take more than 75% of it, or shrink if less than 25%.
+  fn gc_adjust_bin_vheap_size(&mut self) {
+    let used = self.bin_vheap_used;
+    if used * 4 > self.bin_vheap_size * 3 {
+      self.bin_vheap_size = Self::next_heap_size(used * 4 / 3 + 1);
+    } else if used * 4 < self.bin_vheap_size {
+      self.bin_vheap_size = Self::next_heap_size(used * 2).max(self.min_bin_vheap_size);
+    }
+  }
+
+  /// Move the young heap data into a heap of a different size. Everything
+  /// in the heap has just survived a GC, so nothing is collected here, the
+  /// data is only relocated and the roots are updated.
+  fn gc_resize(
+    &mut self,
+    capacity: usize,
+    roots: &mut dyn TRootIterator,
+  ) -> RtResult<()> {
+    let (mut new_heap, stack_roots) = self.gc_create_new_heap(capacity);
+    let mut all_roots =
+      ChainRootIterator::new(vec![Box::new(stack_roots), Box::new(roots)]);
+
+    let collect = vec![self.get_used_area()];
+    let req = GcRequest {
+      collect: collect.clone(),
+      young_dest: &mut new_heap,
+      promote: None,
+    };
+    GC::garbage_collect(req, &mut all_roots)?;
+    self.gc_sweep_refc_binaries(&collect);
+
+    self.gc_replace_with(new_heap);
+    Ok(())
+  }
+
   #[allow(dead_code)]
   pub fn stack_info(&self) {
     println!("Stack (s_top {}, s_end {})", self.stack_top, self.capacity)
@@ -363,3 +759,181 @@ impl<GC: TGc> IncrementalHeap<GC> {
     self.capacity - self.stack_top >= y + 1
   }
 }
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{verify::HeapVerifier, Heap},
+    term::{
+      boxed,
+      term_builder::{ListBuilder, TupleBuilder},
+    },
+  };
+
+  const SMALL_BIN: &[u8] = b"hello";
+  const LARGE_BIN: [u8; 100] = [7u8; 100];
+
+  /// Build `{[1,2,3], <<"hello">>, <<7,7,...>>, #{1 => 2, 3 => {4,5,6}}}`,
+  /// the large binary goes to the binary heap.
+  unsafe fn make_sample(hp: &mut Heap) -> RtResult<Term> {
+    let mut lb = ListBuilder::new()?;
+    for i in 1..=3 {
+      lb.append(Term::make_small_unsigned(i), hp)?;
+    }
+    let list = lb.make_term_with_tail(Term::nil());
+    let small_bin = (*boxed::Binary::create_with_data(SMALL_BIN, hp)?).make_term();
+    let large_bin = (*boxed::Binary::create_with_data(&LARGE_BIN, hp)?).make_term();
+    let map_p = boxed::Map::create_into(hp, 2)?;
+    let inner_tb = TupleBuilder::with_arity(3, hp)?;
+    for i in 0..3 {
+      inner_tb.set_element(i, Term::make_small_unsigned(i + 4));
+    }
+    boxed::Map::add(map_p, Term::make_small_unsigned(1), Term::make_small_unsigned(2))?;
+    boxed::Map::add(map_p, Term::make_small_unsigned(3), inner_tb.make_term())?;
+
+    let tb = TupleBuilder::with_arity(4, hp)?;
+    tb.set_element(0, list);
+    tb.set_element(1, small_bin);
+    tb.set_element(2, large_bin);
+    tb.set_element(3, Term::make_boxed(map_p));
+    Ok(tb.make_term())
+  }
+
+  /// Something to collect, nothing refers to it.
+  fn make_garbage(hp: &mut Heap) {
+    let tb = TupleBuilder::with_arity(20, hp).unwrap();
+    for i in 0..20 {
+      unsafe { tb.set_element(i, Term::make_small_unsigned(i)) }
+    }
+  }
+
+  unsafe fn bin_data<'a>(t: Term) -> &'a [u8] {
+    (*boxed::Binary::get_trait_from_term(t)).get_data()
+  }
+
+  /// Check that the sample looks same as it was built, and that the heap has
+  /// no pointers outside of it.
+  unsafe fn check_sample(hp: &Heap, t: Term) {
+    let mut verifier = HeapVerifier::new();
+    verifier.add_heap(hp);
+    if let Err(e) = verifier.verify_heap(hp).and(verifier.verify_roots(&[t])) {
+      panic!("{}", e)
+    }
+    assert!(hp.belongs_to_heap(t.get_box_ptr()));
+
+    let tuple_p = t.get_tuple_ptr();
+    assert_eq!((*tuple_p).get_arity(), 4);
+
+    let mut list = (*tuple_p).get_element(0);
+    for i in 1..=3 {
+      let cons_p = list.get_cons_ptr();
+      assert_eq!((*cons_p).hd(), Term::make_small_unsigned(i));
+      list = (*cons_p).tl();
+    }
+    assert_eq!(list, Term::nil());
+
+    assert_eq!(bin_data((*tuple_p).get_element(1)), SMALL_BIN);
+    assert_eq!(bin_data((*tuple_p).get_element(2)), &LARGE_BIN[..]);
+
+    let map_p = (*tuple_p).get_element(3).get_box_ptr_mut::<boxed::Map>();
+    let pairs = boxed::Map::get_pairs_mut(map_p);
+    assert_eq!(pairs.len(), 4);
+    assert_eq!(pairs[0], Term::make_small_unsigned(1));
+    assert_eq!(pairs[1], Term::make_small_unsigned(2));
+    assert_eq!(pairs[2], Term::make_small_unsigned(3));
+    let inner_p = pairs[3].get_tuple_ptr();
+    assert_eq!((*inner_p).get_arity(), 3);
+    for i in 0..3 {
+      assert_eq!((*inner_p).get_element(i), Term::make_small_unsigned(i + 4));
+    }
+  }
+
+  /// Run a GC with a single root, the root is updated in place.
+  fn gc_with_root(hp: &mut Heap, root: &mut [Term; 1]) {
+    let roots = Box::new(ArrayRootIterator::new(root));
+    hp.garbage_collect(SizeWords::new(0), roots).unwrap();
+  }
+
+  #[test]
+  fn test_gc_survival_fullsweep() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    make_garbage(&mut hp);
+    let mut root = [unsafe { make_sample(&mut hp) }.unwrap()];
+    make_garbage(&mut hp);
+    let used_before = hp.heap_top;
+
+    // fullsweep_after is 0, every GC is a fullsweep
+    for _ in 0..3 {
+      gc_with_root(&mut hp, &mut root);
+      assert!(hp.old_heap.is_none());
+      assert!(hp.heap_top < used_before, "garbage was not collected");
+      unsafe { check_sample(&hp, root[0]) };
+    }
+    // The large binary is still referenced
+    assert_eq!(hp.refc_binaries.len(), 1);
+  }
+
+  #[test]
+  fn test_gc_survival_minor_and_promotion() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    hp.set_fullsweep_after(2);
+    let mut root = [unsafe { make_sample(&mut hp) }.unwrap()];
+    make_garbage(&mut hp);
+
+    // First minor GC: the data is young, it stays in the young heap
+    gc_with_root(&mut hp, &mut root);
+    assert!(hp.old_heap.is_none());
+    assert_eq!(hp.high_water, hp.heap_top);
+    unsafe { check_sample(&hp, root[0]) };
+
+    // Second minor GC: the data has survived once, it is promoted
+    make_garbage(&mut hp);
+    gc_with_root(&mut hp, &mut root);
+    let old_used = hp.old_heap.as_ref().map_or(0, |old| old.heap_top);
+    assert!(old_used > 0, "surviving data was not promoted");
+    assert_eq!(hp.heap_top, 0);
+    assert!(hp.old_heap.as_ref().unwrap().belongs_to_heap(root[0].get_box_ptr()));
+    unsafe { check_sample(&hp, root[0]) };
+
+    // Third GC is a fullsweep, the old heap is merged back
+    gc_with_root(&mut hp, &mut root);
+    assert!(hp.old_heap.is_none());
+    assert_eq!(hp.minor_gcs, 0);
+    unsafe { check_sample(&hp, root[0]) };
+    assert_eq!(hp.refc_binaries.len(), 1);
+  }
+
+  #[test]
+  fn test_gc_releases_dead_binary() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut root = [unsafe { make_sample(&mut hp) }.unwrap()];
+    assert_eq!(hp.refc_binaries.len(), 1);
+    root[0] = Term::nil();
+    gc_with_root(&mut hp, &mut root);
+    assert!(hp.refc_binaries.is_empty());
+    assert_eq!(hp.bin_vheap_used, 0);
+  }
+
+  #[test]
+  fn test_gc_collects_fragments() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut frag = Heap::new_fragment(SizeWords::new(64));
+    let mut root = [unsafe { make_sample(&mut frag) }.unwrap()];
+    hp.attach_fragment(frag);
+    gc_with_root(&mut hp, &mut root);
+    assert!(hp.fragments.is_empty());
+    assert!(hp.get_used_area().contains(root[0].get_box_ptr()));
+    unsafe { check_sample(&hp, root[0]) };
+  }
+
+  #[test]
+  fn test_next_heap_size() {
+    assert_eq!(Heap::next_heap_size(0), 233);
+    assert_eq!(Heap::next_heap_size(234), 377);
+    assert_eq!(Heap::next_heap_size(378), 610);
+  }
+}
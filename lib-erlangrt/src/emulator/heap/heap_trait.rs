@@ -3,7 +3,7 @@ use crate::{
   defs::{sizes::SizeWords, Word},
   emulator::heap::{catch::NextCatchResult, iter, *},
   fail::RtResult,
-  term::Term,
+  term::{boxed::binary::ReferenceToBinary, Term},
 };
 
 #[derive(Eq, PartialEq)]
@@ -15,7 +15,12 @@ pub enum AllocInit {
 /// Trait defines shared API which all heap implementations must expose
 pub trait THeap {
   fn alloc(&mut self, sz: SizeWords, fill: AllocInit) -> RtResult<*mut Word>;
-  fn garbage_collect(&mut self, _roots: Box<dyn TRootIterator>) -> RtResult<()>;
+  /// Collect garbage and ensure that `need` words are available after that.
+  fn garbage_collect(
+    &mut self,
+    need: SizeWords,
+    roots: Box<dyn TRootIterator>,
+  ) -> RtResult<()>;
 
   // Stack access
   //
@@ -38,7 +43,10 @@ pub trait THeap {
 
   fn heap_check_available(&self, need: SizeWords) -> bool;
   fn stack_check_available(&self, need: SizeWords) -> bool;
-  fn stack_alloc(&mut self, need: SizeWords, extra: SizeWords, fill: AllocInit);
+  /// Allocate `need` words on stack, `extra` more words must be available after
+  /// that. Returns `HeapIsFull` if there is not enough space.
+  fn stack_alloc(&mut self, need: SizeWords, extra: SizeWords, fill: AllocInit)
+    -> RtResult<()>;
   fn stack_depth(&self) -> usize;
 
   /// Push a Term to stack without checking. Call `stack_have(1)` beforehand.
@@ -51,6 +59,10 @@ pub trait THeap {
 
   unsafe fn heap_iter(&self) -> iter::HeapIterator;
   fn belongs_to_heap(&self, p: *const Word) -> bool;
+
+  /// Remember a reference to a binary on the binary heap, allocated on this
+  /// heap. The binary is released when the GC finds the reference dead.
+  fn register_refc_binary(&mut self, refbin: *mut ReferenceToBinary);
   //  fn get_heap_start_ptr(&self) -> *const Word;
   //  fn get_heap_top_ptr(&self) -> *const Word;
   //  fn get_heap_begin_ptr_mut(&mut self) -> *mut Word;
@@ -0,0 +1,110 @@
+//! Registry of memory areas which hold module literals. Literal heaps are
+//! never garbage collected or freed, not even with their module, so terms
+//! stored there can be referred to from process heaps without copying.
+//!
+//! `is_literal` is on the hot path of copying and heap verification, so it
+//! does not lock: the areas are kept in a table sorted by the start address,
+//! and every change publishes a new table. Replaced tables are never freed
+//! because a reader might still be looking at one, modules are loaded rarely
+//! so they do not take much memory.
+// TODO: Copy literals to the referring processes when a module is purged, then
+// its area can be unregistered and freed
+use crate::{defs::Word, emulator::heap::gc_trait::GcArea};
+use core::{
+  ptr,
+  sync::atomic::{AtomicPtr, Ordering},
+};
+use std::sync::Mutex;
+
+/// Literal areas stored as `(start, end)` addresses, sorted by `start`
+type AreaTable = Vec<(usize, usize)>;
+
+static LITERAL_AREAS: AtomicPtr<AreaTable> = AtomicPtr::new(ptr::null_mut());
+
+/// Serializes the table updates
+static TABLE_WRITER: Mutex<()> = Mutex::new(());
+
+/// Copy the current table, change it with `update_fn` and publish.
+fn update_table<T>(update_fn: impl FnOnce(&mut AreaTable) -> T) -> T {
+  let _writer = TABLE_WRITER.lock().unwrap();
+  let current = LITERAL_AREAS.load(Ordering::Acquire);
+  let mut table = if current.is_null() {
+    Vec::new()
+  } else {
+    unsafe { (*current).clone() }
+  };
+  let result = update_fn(&mut table);
+  // The replaced table is leaked, a reader might still be looking at it
+  LITERAL_AREAS.store(Box::into_raw(Box::new(table)), Ordering::Release);
+  result
+}
+
+/// Register a loaded module's literal heap area.
+pub fn register(area: GcArea) {
+  if area.start == area.end {
+    return;
+  }
+  let (start, end) = (area.start as usize, area.end as usize);
+  update_table(|table| {
+    let pos = table.partition_point(|(s, _e)| *s < start);
+    table.insert(pos, (start, end));
+  })
+}
+
+/// Forget a literal heap area which is no longer referred to.
+/// Returns: `false` if the area was not registered.
+#[allow(dead_code)]
+pub fn unregister(area: GcArea) -> bool {
+  if area.start == area.end {
+    return true;
+  }
+  let start = area.start as usize;
+  update_table(|table| match table.binary_search_by_key(&start, |(s, _e)| *s) {
+    Ok(pos) => {
+      table.remove(pos);
+      true
+    }
+    Err(_) => false,
+  })
+}
+
+/// Check whether the pointer points into some module's literal heap.
+pub fn is_literal(p: *const Word) -> bool {
+  let table = LITERAL_AREAS.load(Ordering::Acquire);
+  if table.is_null() {
+    return false;
+  }
+  let areas = unsafe { &*table };
+  let addr = p as usize;
+  // The last area which starts at or before `addr`
+  let pos = areas.partition_point(|(s, _e)| *s <= addr);
+  pos > 0 && addr < areas[pos - 1].1
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_literal_area_lookup() {
+    // Real memory, so that the test does not collide with loaded modules
+    let mem = [0 as Word; 16];
+    let area = |m: &[Word]| unsafe { GcArea::new(m.as_ptr(), m.as_ptr().add(m.len())) };
+    register(area(&mem[8..12]));
+    register(area(&mem[2..6]));
+    let expected = |i: usize| (2..6).contains(&i) || (8..12).contains(&i);
+    for (i, p) in mem.iter().enumerate() {
+      assert_eq!(is_literal(p), expected(i), "word {}", i);
+    }
+
+    assert!(unregister(area(&mem[2..6])));
+    assert!(!is_literal(&mem[2]));
+    assert!(is_literal(&mem[11]));
+    assert!(!unregister(area(&mem[2..6])));
+    assert!(unregister(area(&mem[8..12])));
+    assert!(!is_literal(&mem[8]));
+  }
+}
@@ -9,6 +9,9 @@ pub mod dump;
 pub mod gc_trait;
 pub mod heap_incremental;
 pub mod iter;
+pub mod literal_area;
+pub mod owned_term;
+pub mod verify;
 
 mod heap_owner_trait;
 pub use heap_owner_trait::*;
@@ -28,7 +31,6 @@ pub type Heap = IncrementalHeap<CopyingGc>;
 pub enum Designation {
   ProcessHeap,
   ModuleLiterals,
-  BinaryHeap,
   // Used to store command line args on startup
   ProgramArgumentsHeap,
   // Heap of smallest size to be destroyed after it is swapped with the real one
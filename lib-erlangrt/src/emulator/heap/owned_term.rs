@@ -0,0 +1,45 @@
+//! A term stored outside of any process heap, together with its own copy of
+//! the data in a heap fragment. Used to keep terms which outlive the process
+//! which created them, such as timer messages and exit reasons.
+use crate::{
+  emulator::heap::{copy_term, Heap},
+  fail::RtResult,
+  term::Term,
+};
+
+pub struct OwnedTerm {
+  term: Term,
+  /// Heap fragment with the term data, None for immediate values
+  _fragment: Option<Heap>,
+}
+
+impl OwnedTerm {
+  /// Copy the term into a new heap fragment.
+  pub fn new(term: Term) -> RtResult<Self> {
+    let size = copy_term::size_of_shared(term)?;
+    if size.words == 0 {
+      return Ok(Self {
+        term,
+        _fragment: None,
+      });
+    }
+    let mut fragment = Heap::new_fragment(size);
+    let term = copy_term::copy_shared_to(term, &mut fragment)?;
+    Ok(Self {
+      term,
+      _fragment: Some(fragment),
+    })
+  }
+
+  /// The term, valid for as long as this object lives.
+  #[inline]
+  pub fn get(&self) -> Term {
+    self.term
+  }
+
+  /// The term and the heap fragment which holds its data, to be attached to a
+  /// process heap.
+  pub fn into_parts(self) -> (Term, Option<Heap>) {
+    (self.term, self._fragment)
+  }
+}
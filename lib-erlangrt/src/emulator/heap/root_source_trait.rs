@@ -10,6 +10,17 @@ pub trait TRootIterator {
   fn roots_next(&mut self) -> MutableRoot;
 }
 
+/// Allows borrowing a root iterator into a `ChainRootIterator`
+impl<T: TRootIterator + ?Sized> TRootIterator for &mut T {
+  fn roots_begin(&mut self) {
+    (**self).roots_begin()
+  }
+
+  fn roots_next(&mut self) -> MutableRoot {
+    (**self).roots_next()
+  }
+}
+
 /// Roots source is an object which is able to provide an array of mutable pointers
 /// to term values, so called ROOTS for the garbage collector. The root set
 /// serves as a starting point for tracing the heap value liveness during GC.
@@ -43,15 +54,49 @@ impl TRootIterator for ArrayRootIterator {
     self.position = self.start;
   }
 
-  /// Step forward, return null if reached the end.
+  /// Return current root and step forward, return null if reached the end.
   #[inline]
   fn roots_next(&mut self) -> *mut Term {
     if self.position >= self.stop {
       return core::ptr::null_mut();
     }
     unsafe {
+      let result = self.position;
       self.position = self.position.add(1);
-      self.position
+      result
+    }
+  }
+}
+
+/// Root source which visits several other root iterators one after another.
+/// Used to combine roots from different places (registers, stack, mailbox...)
+pub struct ChainRootIterator<'a> {
+  parts: Vec<Box<dyn TRootIterator + 'a>>,
+  index: usize,
+}
+
+impl<'a> ChainRootIterator<'a> {
+  pub fn new(parts: Vec<Box<dyn TRootIterator + 'a>>) -> Self {
+    Self { parts, index: 0 }
+  }
+}
+
+impl<'a> TRootIterator for ChainRootIterator<'a> {
+  fn roots_begin(&mut self) {
+    self.index = 0;
+    for p in self.parts.iter_mut() {
+      p.roots_begin();
+    }
+  }
+
+  fn roots_next(&mut self) -> *mut Term {
+    while self.index < self.parts.len() {
+      let r = self.parts[self.index].roots_next();
+      if !r.is_null() {
+        return r;
+      }
+      self.index += 1;
     }
+    core::ptr::null_mut()
   }
 }
@@ -0,0 +1,224 @@
+//! Debug tool to check the heap integrity. Walks the heap data and the stack
+//! and checks every term found there. Enabled by the `verify_heap` feature or
+//! at runtime with `set_enabled` (command line option `-verify_heap`), then
+//! the process heap is checked after every GC, message delivery and native
+//! function call.
+use crate::{
+  defs::Word,
+  emulator::heap::{gc_trait::GcArea, literal_area, Heap},
+  term::{
+    boxed::{
+      self,
+      binary::{match_state::BinaryMatchState, BinarySlice, BinaryType, TBinary},
+    },
+    PrimaryTag, Term,
+  },
+};
+use core::{
+  fmt,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+static VERIFY_HEAP: AtomicBool = AtomicBool::new(false);
+
+/// Check whether heap verification is enabled by the feature or at runtime.
+#[inline]
+pub fn is_enabled() -> bool {
+  cfg!(feature = "verify_heap") || VERIFY_HEAP.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enable: bool) {
+  VERIFY_HEAP.store(enable, Ordering::Relaxed)
+}
+
+/// Describes the first found heap corruption.
+pub struct HeapViolation {
+  /// Memory location where the bad value was found (null for root terms)
+  pub addr: *const Word,
+  pub value: Word,
+  pub reason: &'static str,
+}
+
+impl fmt::Display for HeapViolation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} at {:p} (value 0x{:x})",
+      self.reason, self.addr, self.value
+    )
+  }
+}
+
+pub type VerifyResult = Result<(), HeapViolation>;
+
+fn violation(addr: *const Word, value: Word, reason: &'static str) -> VerifyResult {
+  Err(HeapViolation {
+    addr,
+    value,
+    reason,
+  })
+}
+
+/// Knows which memory areas are valid pointer targets, and checks the heaps
+/// and root terms against them.
+pub struct HeapVerifier {
+  areas: Vec<GcArea>,
+}
+
+impl HeapVerifier {
+  pub fn new() -> Self {
+    Self { areas: Vec::new() }
+  }
+
+  /// Allow pointers to the data of this heap.
+  pub fn add_heap(&mut self, hp: &Heap) {
+    self.areas.append(&mut hp.get_data_areas());
+  }
+
+  #[inline]
+  fn is_valid_target(&self, p: *const Word) -> bool {
+    self.areas.iter().any(|area| area.contains(p)) || literal_area::is_literal(p)
+  }
+
+  /// Walk the heap data objects one by one, then the stack.
+  pub unsafe fn verify_heap(&self, hp: &Heap) -> VerifyResult {
+    for area in hp.get_data_areas() {
+      self.verify_area(&area)?;
+    }
+    let stack = hp.get_stack_area();
+    let mut p = stack.start;
+    while p < stack.end {
+      let val = Term::from_raw(*p);
+      if val.is_header_word() {
+        return violation(p, val.raw(), "Header word on stack");
+      }
+      self.verify_term(p, val)?;
+      p = p.add(1);
+    }
+    Ok(())
+  }
+
+  pub unsafe fn verify_roots(&self, roots: &[Term]) -> VerifyResult {
+    for r in roots.iter() {
+      self.verify_term(core::ptr::null(), *r)?;
+    }
+    Ok(())
+  }
+
+  unsafe fn verify_area(&self, area: &GcArea) -> VerifyResult {
+    let mut p = area.start;
+    while p < area.end {
+      let val = Term::from_raw(*p);
+      if !val.is_header_word() {
+        self.verify_term(p, val)?;
+        p = p.add(1);
+        continue;
+      }
+      let size = boxed::BoxHeader::headerword_to_storage_size(val.raw());
+      if size.words == 0 || p.add(size.words) > area.end {
+        return violation(p, val.raw(), "Header arity is out of the heap bounds");
+      }
+      self.verify_boxed(p as *const boxed::BoxHeader)?;
+      p = p.add(size.words);
+    }
+    Ok(())
+  }
+
+  /// Check the terms stored inside a boxed object.
+  unsafe fn verify_boxed(&self, header_p: *const boxed::BoxHeader) -> VerifyResult {
+    let trait_ptr = (*header_p).get_trait_ptr();
+    match (*trait_ptr).get_type() {
+      boxed::BOXTYPETAG_TUPLE => {
+        let tuple_p = header_p as *const boxed::Tuple;
+        let arity = (*tuple_p).get_arity();
+        if arity + 1 > (*header_p).get_storage_size().words {
+          return violation(header_p as *const Word, arity, "Tuple arity exceeds its size");
+        }
+        for i in 0..arity {
+          let el_p = (&(*tuple_p).data0 as *const Term).add(i) as *const Word;
+          self.verify_term(el_p, (*tuple_p).get_element(i))?;
+        }
+      }
+      boxed::BOXTYPETAG_CLOSURE => {
+        let closure_p = header_p as *const boxed::Closure;
+        for frozen in (*closure_p).get_frozen().iter() {
+          self.verify_term(frozen as *const Term as *const Word, *frozen)?;
+        }
+      }
+      boxed::BOXTYPETAG_MAP => {
+        for kv in boxed::Map::get_pairs_mut(header_p as *mut boxed::Map).iter() {
+          self.verify_term(kv as *const Term as *const Word, *kv)?;
+        }
+      }
+      boxed::BOXTYPETAG_EXTERNALPID => {
+        let epid_p = header_p as *const boxed::ExternalPid;
+        self.verify_term(&(*epid_p).node as *const Term as *const Word, (*epid_p).node)?;
+      }
+      boxed::BOXTYPETAG_JUMP_TABLE => {
+        let jt_p = header_p as *const boxed::JumpTable;
+        for i in 0..(*jt_p).get_count() {
+          self.verify_term(header_p as *const Word, (*jt_p).get_pair(i).0)?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY => {
+        let bin_p = boxed::Binary::get_trait(header_p as *const boxed::Binary);
+        if let BinaryType::Slice = (*bin_p).get_type() {
+          let slice_p = header_p as *const BinarySlice;
+          self.verify_binary_ptr(header_p as *const Word, (*slice_p).orig)?;
+        }
+      }
+      boxed::BOXTYPETAG_BINARY_MATCH_STATE => {
+        let ms_p = header_p as *const BinaryMatchState;
+        self.verify_binary_ptr(header_p as *const Word, (*ms_p).get_src_binary())?;
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  unsafe fn verify_binary_ptr(
+    &self,
+    addr: *const Word,
+    bin_p: *const dyn TBinary,
+  ) -> VerifyResult {
+    let p = bin_p as *const Word;
+    if !self.is_valid_target(p) {
+      return violation(addr, p as Word, "Binary pointer outside of the heap");
+    }
+    Ok(())
+  }
+
+  /// Check that a pointer term points to a valid object in the heap or in a
+  /// literal area, and the object was not moved by GC.
+  unsafe fn verify_term(&self, addr: *const Word, val: Term) -> VerifyResult {
+    match val.get_term_tag() {
+      PrimaryTag::BOX_PTR => {
+        if val.is_cp() {
+          return Ok(());
+        }
+        let p = val.get_box_ptr::<Word>();
+        if !self.is_valid_target(p) {
+          return violation(addr, val.raw(), "Boxed pointer outside of the heap");
+        }
+        let header = Term::from_raw(*p);
+        if header.is_boxed() {
+          return violation(addr, val.raw(), "Stale forwarding pointer (moved boxed)");
+        }
+        if !header.is_header_word() {
+          return violation(addr, val.raw(), "Boxed pointer does not point to a header");
+        }
+      }
+      PrimaryTag::CONS_PTR => {
+        let p = val.get_cons_ptr() as *const Word;
+        if !self.is_valid_target(p) {
+          return violation(addr, val.raw(), "Cons pointer outside of the heap");
+        }
+        if Term::from_raw(*p).is_non_value() {
+          return violation(addr, val.raw(), "Stale forwarding pointer (moved cons)");
+        }
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
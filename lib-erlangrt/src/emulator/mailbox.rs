@@ -1,86 +1,423 @@
-use crate::term::*;
+//! Process message queue. Messages are kept in slots of a slab, linked in the
+//! order of arrival into a double linked list, so appending a message and
+//! removing the message at the receive pointer are O(1). Message terms are
+//! stored in one array, which is given to the GC as roots; free slots hold
+//! non-values.
+//!
+//! Messages from other processes arrive as signals with their own heap
+//! fragments, see `signal.rs`, and are put here by the receiving process.
+use crate::{
+  emulator::{
+    heap::{copy_term, Heap},
+    spawn_options::MessageQueueLocation,
+  },
+  fail::RtResult,
+  term::*,
+};
+
+#[derive(Clone, Copy, Default)]
+struct SlotLink {
+  prev: Option<usize>,
+  next: Option<usize>,
+}
 
 pub struct ProcessMailbox {
+  /// Message in each slot, non-value for free slots.
   inbox: Vec<Term>,
-  // TODO: Some structure on proc heap?
-  read_index: usize,
+  /// For off-heap messages: heap fragment where the message in the same slot
+  /// is stored. None for on-heap messages.
+  fragments: Vec<Option<Heap>>,
+  links: Vec<SlotLink>,
+  /// Slots which can be reused.
+  free: Vec<usize>,
+  head: Option<usize>,
+  tail: Option<usize>,
+  /// Number of messages in the queue.
+  count: usize,
+  /// Receive pointer (save pointer): the slot of the next message to be
+  /// matched by `loop_rec`, `None` when the current receive has seen all
+  /// messages. Messages before it were already seen by the current receive.
+  save: Option<usize>,
+  /// Set by `recv_mark`: label of the receive loop and the last slot at that
+  /// moment (`None` if the queue was empty). Messages up to and including
+  /// this slot can not match.
+  mark: Option<(Term, Option<usize>)>,
+  /// Where the incoming messages are stored.
+  pub location: MessageQueueLocation,
 }
 
 impl ProcessMailbox {
-  pub fn new() -> Self {
+  pub fn new(location: MessageQueueLocation) -> Self {
     Self {
       inbox: Vec::with_capacity(32),
-      read_index: 0,
+      fragments: Vec::with_capacity(32),
+      links: Vec::with_capacity(32),
+      free: Vec::new(),
+      head: None,
+      tail: None,
+      count: 0,
+      save: None,
+      mark: None,
+      location,
     }
   }
 
+  /// Whether there are messages not yet seen by the current receive.
   #[inline]
   pub fn have_unread_messages(&self) -> bool {
-    self.read_index < self.inbox.len()
+    self.save.is_some()
+  }
+
+  /// Number of messages in the queue.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.count
   }
 
-  //  #[inline]
-  //  pub fn is_empty(&self) -> bool {
-  //    self.inbox.is_empty()
-  //  }
+  /// Messages in the order of arrival.
+  pub fn messages(&self) -> Vec<Term> {
+    let mut result = Vec::with_capacity(self.count);
+    let mut cursor = self.head;
+    while let Some(slot) = cursor {
+      result.push(self.inbox[slot]);
+      cursor = self.links[slot].next;
+    }
+    result
+  }
 
   /// Copy a message and put into process mailbox.
   /// Assumes: the message is already copied to receiving process heap.
   pub fn put(&mut self, message: Term) {
-    self.inbox.push(message);
+    self.push_back(message, None);
   }
 
-  /// Read message at the current receive pointer.
-  pub fn get_current(&mut self) -> Option<Term> {
-    if self.inbox.is_empty() {
-      return None;
+  /// Copy a message into its own heap fragment and put into process mailbox.
+  /// The receiving process heap is not touched until the message is removed
+  /// or the process does a GC.
+  pub fn put_off_heap(&mut self, message: Term) -> RtResult<()> {
+    let (m1, fragment) = Self::copy_to_fragment(message)?;
+    self.push_back(m1, fragment);
+    Ok(())
+  }
+
+  /// Put a message which is stored in `fragment`, or has no data on any heap
+  /// if `fragment` is None.
+  pub fn put_with_fragment(&mut self, message: Term, fragment: Option<Heap>) {
+    self.push_back(message, fragment);
+  }
+
+  fn copy_to_fragment(message: Term) -> RtResult<(Term, Option<Heap>)> {
+    let size = copy_term::size_of_shared(message)?;
+    if size.words == 0 {
+      // Immediate values do not need a heap
+      return Ok((message, None));
+    }
+    let mut fragment = Heap::new_fragment(size);
+    let m1 = copy_term::copy_shared_to(message, &mut fragment)?;
+    Ok((m1, Some(fragment)))
+  }
+
+  fn push_back(&mut self, message: Term, fragment: Option<Heap>) {
+    let link = SlotLink {
+      prev: self.tail,
+      next: None,
+    };
+    let slot = match self.free.pop() {
+      Some(slot) => {
+        self.inbox[slot] = message;
+        self.fragments[slot] = fragment;
+        self.links[slot] = link;
+        slot
+      }
+      None => {
+        self.inbox.push(message);
+        self.fragments.push(fragment);
+        self.links.push(link);
+        self.inbox.len() - 1
+      }
+    };
+    match self.tail {
+      Some(tail) => self.links[tail].next = Some(slot),
+      None => self.head = Some(slot),
+    }
+    self.tail = Some(slot);
+    self.count += 1;
+    // The receive has seen everything before, the new message is next
+    if self.save.is_none() {
+      self.save = Some(slot);
+    }
+  }
+
+  /// Unlink the message in `slot` and free the slot. Returns the message and
+  /// its heap fragment. The receive pointer moves to the next message if it
+  /// pointed to this one.
+  fn unlink(&mut self, slot: usize) -> (Term, Option<Heap>) {
+    let link = self.links[slot];
+    match link.prev {
+      Some(prev) => self.links[prev].next = link.next,
+      None => self.head = link.next,
+    }
+    match link.next {
+      Some(next) => self.links[next].prev = link.prev,
+      None => self.tail = link.prev,
+    }
+    if self.save == Some(slot) {
+      self.save = link.next;
+    }
+    if let Some((_, Some(marked))) = self.mark {
+      if marked == slot {
+        // The mark can not be placed at a freed slot, start from the queue head
+        self.mark = None;
+      }
     }
+    let message = core::mem::replace(&mut self.inbox[slot], Term::non_value());
+    let fragment = self.fragments[slot].take();
+    self.links[slot] = SlotLink::default();
+    self.free.push(slot);
+    self.count -= 1;
 
-    let mri = self.read_index;
-    debug_assert!(mri < self.inbox.len());
+    if self.count == 0 {
+      // Drop the free list, everything arriving now goes after the mark
+      self.inbox.clear();
+      self.fragments.clear();
+      self.links.clear();
+      self.free.clear();
+      if let Some((_, marked)) = self.mark.as_mut() {
+        *marked = None;
+      }
+    }
+    (message, fragment)
+  }
 
-    let val = self.inbox[mri];
-    debug_assert!(val.is_value());
-    Some(val)
+  /// Take all heap fragments of the messages still in the mailbox, for the
+  /// process heap to attach them before the GC.
+  pub fn take_fragments(&mut self) -> Vec<Heap> {
+    self
+      .fragments
+      .iter_mut()
+      .filter_map(|frag| frag.take())
+      .collect()
+  }
+
+  /// Access all stored messages, used by the GC to update the message
+  /// locations. Free slots are non-values and will be ignored.
+  pub fn get_inbox_mut(&mut self) -> &mut [Term] {
+    &mut self.inbox
+  }
+
+  /// Access all stored messages, read-only.
+  pub fn get_inbox(&self) -> &[Term] {
+    &self.inbox
+  }
+
+  /// Heap fragments of the off-heap messages still in the mailbox.
+  pub fn get_fragments(&self) -> impl Iterator<Item = &Heap> {
+    self.fragments.iter().filter_map(|frag| frag.as_ref())
+  }
+
+  /// Remove the first message for which the predicate returns true, without
+  /// receiving it. Returns whether a message was removed.
+  pub fn remove_first<F>(&mut self, pred: F) -> bool
+  where
+    F: Fn(Term) -> bool,
+  {
+    let mut cursor = self.head;
+    while let Some(slot) = cursor {
+      if pred(self.inbox[slot]) {
+        self.unlink(slot);
+        return true;
+      }
+      cursor = self.links[slot].next;
+    }
+    false
   }
 
-  // TODO: This is ugly, do proper mailbox algorithm impl here
+  /// Read message at the current receive pointer, `None` if all messages
+  /// have been seen.
+  pub fn get_current(&mut self) -> Option<Term> {
+    self.save.map(|slot| self.inbox[slot])
+  }
+
+  /// Move the receive pointer back to the first message, when a receive has
+  /// finished without taking a message (timeout).
+  pub fn reset_read_index(&mut self) {
+    self.mark = None;
+    self.save = self.head;
+  }
+
+  /// Advance the receive pointer past the current message (it did not match).
   pub fn step_over(&mut self) {
-    // Guard
-    if self.inbox.is_empty() {
-      self.read_index = 0;
-      return;
-    }
-
-    let mut mri = self.read_index;
-    // remember starting pos to know if we traversed all messages and all of
-    // them were nonvalues
-    let starting_pos = mri;
-    let max_mri = self.inbox.len();
-    // Increase mail receive index over nonvalues (received values) until
-    // we hit the end of the mailbox
-    while self.inbox[mri].is_non_value() {
-      mri += 1;
-      if mri == max_mri {
-        mri = 0;
+    if let Some(slot) = self.save {
+      self.save = self.links[slot].next;
+    }
+  }
+
+  /// Remember the end of the queue for the receive loop at `label`. Used
+  /// when a receive matches on a reference which was created after the mark,
+  /// then no older message can match (`recv_mark` opcode).
+  pub fn set_mark(&mut self, label: Term) {
+    self.mark = Some((label, self.tail));
+  }
+
+  /// Start the receive loop at `label` from the marked position, if the mark
+  /// was set for the same loop (`recv_set` opcode).
+  pub fn use_mark(&mut self, label: Term) {
+    if let Some((mark_label, marked)) = self.mark {
+      if mark_label == label {
+        self.save = match marked {
+          Some(slot) => self.links[slot].next,
+          None => self.head,
+        };
       }
-      if mri == starting_pos {
-        // Done a full loop around mailbox and all values were non-values
-        self.inbox.clear();
-        self.read_index = 0;
-        return;
+    }
+  }
+
+  // Remove value from current mailbox position and return it. The receive
+  // pointer returns to the first message for the next receive. For an
+  // off-heap message also returns its heap fragment, which must be attached
+  // to the process heap to keep the message data valid.
+  pub fn remove_current(&mut self) -> (Term, Option<Heap>) {
+    let slot = self.save.expect("remove_message without a current message");
+    let result = self.unlink(slot);
+    self.reset_read_index();
+    result
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small(n: isize) -> Term {
+    Term::make_small_signed(n)
+  }
+
+  fn make_mailbox(messages: &[isize]) -> ProcessMailbox {
+    let mut mb = ProcessMailbox::new(MessageQueueLocation::OnHeap);
+    for m in messages {
+      mb.put(small(*m));
+    }
+    mb
+  }
+
+  /// Like a selective receive: take the first message equal to `n`, or reset
+  /// the receive pointer if there is none.
+  fn receive(mb: &mut ProcessMailbox, n: isize) -> Option<Term> {
+    while let Some(m) = mb.get_current() {
+      if m == small(n) {
+        return Some(mb.remove_current().0);
       }
+      mb.step_over();
+    }
+    mb.reset_read_index();
+    None
+  }
+
+  fn smalls(ns: &[isize]) -> Vec<Term> {
+    ns.iter().map(|n| small(*n)).collect()
+  }
+
+  #[test]
+  fn test_mailbox_append() {
+    let mut mb = make_mailbox(&[]);
+    assert_eq!(mb.len(), 0);
+    assert!(!mb.have_unread_messages());
+    for n in 1..=3 {
+      mb.put(small(n));
     }
-    self.read_index = mri;
+    assert_eq!(mb.len(), 3);
+    assert_eq!(mb.messages(), smalls(&[1, 2, 3]));
+    assert!(mb.have_unread_messages());
+    assert_eq!(mb.get_current(), Some(small(1)));
   }
 
-  // Remove value from current mailbox position and return it, move pointer
-  // forward.
-  pub fn remove_current(&mut self) -> Term {
-    let mri = self.read_index;
-    let val = self.inbox[mri];
-    self.inbox[mri] = Term::non_value();
-    self.step_over();
-    val
+  #[test]
+  fn test_mailbox_remove_current() {
+    let mut mb = make_mailbox(&[1, 2, 3, 4]);
+    // The middle, the tail and the head
+    assert_eq!(receive(&mut mb, 2), Some(small(2)));
+    assert_eq!(mb.get_current(), Some(small(1)), "must restart from the head");
+    assert_eq!(receive(&mut mb, 4), Some(small(4)));
+    assert_eq!(receive(&mut mb, 1), Some(small(1)));
+    assert_eq!(mb.messages(), smalls(&[3]));
+    assert_eq!(receive(&mut mb, 5), None);
+    assert_eq!(receive(&mut mb, 3), Some(small(3)));
+    assert_eq!(mb.len(), 0);
+    assert!(mb.messages().is_empty());
+    assert_eq!(mb.get_current(), None);
+  }
+
+  #[test]
+  fn test_mailbox_step_over() {
+    let mut mb = make_mailbox(&[1, 2]);
+    mb.step_over();
+    assert_eq!(mb.get_current(), Some(small(2)));
+    mb.step_over();
+    assert_eq!(mb.get_current(), None);
+    assert!(!mb.have_unread_messages());
+    mb.step_over();
+    // A new message is the next one for the waiting receive
+    mb.put(small(3));
+    assert!(mb.have_unread_messages());
+    assert_eq!(mb.get_current(), Some(small(3)));
+    mb.reset_read_index();
+    assert_eq!(mb.get_current(), Some(small(1)));
+  }
+
+  #[test]
+  fn test_mailbox_mark() {
+    let label = small(100);
+    let mut mb = make_mailbox(&[1, 2]);
+    mb.set_mark(label);
+    mb.put(small(3));
+    mb.put(small(4));
+    // Another receive loop does not use the mark
+    mb.use_mark(small(101));
+    assert_eq!(mb.get_current(), Some(small(1)));
+    mb.use_mark(label);
+    assert_eq!(mb.get_current(), Some(small(3)));
+    mb.step_over();
+    assert_eq!(mb.get_current(), Some(small(4)));
+    // The receive has finished, the mark is forgotten
+    mb.reset_read_index();
+    mb.use_mark(label);
+    assert_eq!(mb.get_current(), Some(small(1)));
+
+    // The marked message is removed, start from the head
+    mb.set_mark(label);
+    assert!(mb.remove_first(|m| m == small(4)));
+    mb.use_mark(label);
+    assert_eq!(mb.get_current(), Some(small(1)));
+
+    // Mark in an empty mailbox, every new message is after it
+    let mut mb = make_mailbox(&[]);
+    mb.set_mark(label);
+    mb.put(small(1));
+    mb.use_mark(label);
+    assert_eq!(mb.get_current(), Some(small(1)));
+  }
+
+  #[test]
+  fn test_mailbox_slot_reuse() {
+    let mut mb = make_mailbox(&[1, 2, 3]);
+    assert_eq!(receive(&mut mb, 2), Some(small(2)));
+    assert!(mb.get_inbox()[1].is_non_value());
+    // Goes to the free slot in the middle, but is last in the queue
+    mb.put(small(4));
+    assert_eq!(mb.get_inbox().len(), 3);
+    assert_eq!(mb.get_inbox()[1], small(4));
+    assert_eq!(mb.messages(), smalls(&[1, 3, 4]));
+    assert_eq!(receive(&mut mb, 3), Some(small(3)));
+    assert_eq!(mb.messages(), smalls(&[1, 4]));
+    for n in [1, 4] {
+      assert_eq!(receive(&mut mb, n), Some(small(n)));
+    }
+    // An empty mailbox drops its storage
+    assert!(mb.get_inbox().is_empty());
+    mb.put(small(5));
+    assert_eq!(mb.messages(), smalls(&[5]));
   }
 }
@@ -3,6 +3,8 @@ pub mod atom;
 pub mod code;
 pub mod code_srv;
 pub mod disasm;
+pub mod erl_timers;
+pub mod error_report;
 pub mod export;
 pub mod funarity;
 pub mod function;
@@ -11,10 +13,16 @@ pub mod heap;
 pub mod mailbox;
 pub mod mfa;
 pub mod module;
+pub mod monitor;
+pub mod native_trap;
 pub mod process;
+pub mod process_dict;
 pub mod process_flags;
 pub mod process_registry;
+pub mod run_queue;
 pub mod runtime_ctx;
 pub mod scheduler;
+pub mod signal;
 pub mod spawn_options;
+pub mod timer_wheel;
 pub mod vm;
@@ -13,6 +13,7 @@ use crate::{
   fail::{RtErr, RtResult},
   term::Term,
 };
+use core::mem::ManuallyDrop;
 use std::collections::BTreeMap;
 
 /// Stores f/arity mapping to offset in code.
@@ -44,7 +45,9 @@ pub struct Module {
   // TODO: attrs
   // TODO: lit table
   pub code: Code,
-  pub lit_heap: Heap, // set by module loader
+  /// Set by module loader. Never freed: processes and messages refer to the
+  /// literals without copying them, also after the module is gone.
+  pub lit_heap: ManuallyDrop<Heap>,
 }
 
 impl Module {
@@ -53,7 +56,7 @@ impl Module {
     Module {
       code: Vec::new(),
       funs: BTreeMap::new(),
-      lit_heap: Heap::new(Designation::TransientDestructible),
+      lit_heap: ManuallyDrop::new(Heap::new(Designation::TransientDestructible)),
       versioned_name: *name,
       lambdas: Vec::new(),
     }
@@ -0,0 +1,79 @@
+//! Process monitors. The monitoring process (watcher) keeps a `MonitorTarget`
+//! and the monitored process keeps a `Monitor`, both under the id of the
+//! monitor reference. When the monitored process terminates, the watcher
+//! receives `{'DOWN', Ref, process, Object, Reason}`.
+use crate::{
+  defs::Word,
+  emulator::{
+    gen_atoms,
+    heap::{owned_term::OwnedTerm, Heap},
+  },
+  fail::RtResult,
+  term::{
+    boxed,
+    term_builder::{tuple_builder::tuple2, TupleBuilder},
+    Term,
+  },
+};
+
+/// A monitor as seen by the watcher.
+pub struct MonitorTarget {
+  pub pid: Term,
+  /// Registered name if the monitor was created by name, otherwise nil
+  pub name: Term,
+}
+
+/// A monitor as seen by the monitored process.
+pub struct Monitor {
+  pub watcher: Term,
+  /// Registered name if the monitor was created by name, otherwise nil
+  pub name: Term,
+  /// First element of the message, `'DOWN'` unless set with `{tag, Tag}`
+  pub tag: OwnedTerm,
+}
+
+/// Build `{Tag, Ref, process, Object, Reason}` for the watcher. Object is the
+/// pid, or `{Name, Node}` for monitors created by name.
+pub fn make_down(
+  tag: Term,
+  ref_id: Word,
+  pid: Term,
+  name: Term,
+  reason: Term,
+) -> RtResult<OwnedTerm> {
+  let tuple2_size = boxed::Tuple::storage_size(2);
+  let mut hp = Heap::new_fragment(
+    boxed::LocalRef::storage_size() + boxed::Tuple::storage_size(5) + tuple2_size,
+  );
+  let tref = boxed::LocalRef::create_into(&mut hp, ref_id)?;
+  let object = if name == Term::nil() {
+    pid
+  } else {
+    tuple2(&mut hp, name, gen_atoms::NONODE_AT_NOHOST)?
+  };
+  let tb = TupleBuilder::with_arity(5, &mut hp)?;
+  unsafe {
+    tb.set_element(0, tag);
+    tb.set_element(1, tref);
+    tb.set_element(2, gen_atoms::PROCESS);
+    tb.set_element(3, object);
+    tb.set_element(4, reason);
+  }
+  OwnedTerm::new(tb.make_term())
+}
+
+/// Check whether a message is a `'DOWN'` message (with any tag) for the
+/// monitor reference id, used by `demonitor` with the `flush` option.
+pub fn is_down_message_for(msg: Term, ref_id: Word) -> bool {
+  if !msg.is_tuple() || msg == Term::empty_tuple() {
+    return false;
+  }
+  let tuple_p = msg.get_tuple_ptr();
+  unsafe {
+    if (*tuple_p).get_arity() != 5 {
+      return false;
+    }
+    let tref = (*tuple_p).get_element(1);
+    tref.is_local_ref() && boxed::LocalRef::get_id(tref) == ref_id
+  }
+}
@@ -0,0 +1,46 @@
+//! Progress saved by a native function which has run out of reductions
+//! (trapped). The function returns `RtErr::Trap`, the process yields, and the
+//! same call instruction runs again when the process is scheduled back in.
+//! The function then finds its saved state and continues from there.
+use crate::term::Term;
+
+pub struct NativeTrap {
+  /// Name of the native function, like `"erlang:length/1"`
+  name: &'static str,
+  arity: usize,
+  /// Call arguments followed by the saved state, given to the GC as roots.
+  terms: Vec<Term>,
+}
+
+impl NativeTrap {
+  pub fn new(name: &'static str, args: &[Term], state: &[Term]) -> Self {
+    let mut terms = Vec::with_capacity(args.len() + state.len());
+    terms.extend_from_slice(args);
+    terms.extend_from_slice(state);
+    Self {
+      name,
+      arity: args.len(),
+      terms,
+    }
+  }
+
+  /// The saved state, if it was saved by the function `name` called with the
+  /// same `args`.
+  pub fn get_state(&self, name: &'static str, args: &[Term]) -> Option<&[Term]> {
+    if self.name == name && self.terms[..self.arity] == *args {
+      Some(&self.terms[self.arity..])
+    } else {
+      None
+    }
+  }
+
+  /// Access all terms, used by the GC to update their locations.
+  pub fn get_terms_mut(&mut self) -> &mut [Term] {
+    &mut self.terms
+  }
+
+  /// Access all terms, read-only.
+  pub fn get_terms(&self) -> &[Term] {
+    &self.terms
+  }
+}
@@ -2,22 +2,38 @@
 //! heap, stack, registers, and message queue.
 
 use crate::{
-  defs::{exc_type::ExceptionType, SizeWords},
+  defs::{exc_type::ExceptionType, SizeWords, Word},
   emulator::{
+    code::CodePtr,
     code_srv::CodeServer,
-    heap::*,
+    error_report, gen_atoms,
+    heap::{owned_term::OwnedTerm, verify, *},
     mailbox::ProcessMailbox,
     mfa::{ModFunArgs, ModFunArity},
-    process_flags::ProcessFlags,
-    process_registry::ProcessRegistry,
+    monitor::{Monitor, MonitorTarget},
+    native_trap::NativeTrap,
+    process_dict::ProcessDict,
+    process_flags::{self, MaxHeapSize, ProcessFlags},
+    run_queue::RunQueueLink,
     runtime_ctx::RuntimeContext,
-    scheduler::{self, Scheduler},
-    spawn_options::SpawnOptions,
+    scheduler,
+    signal::{Signal, SignalQueue},
+    spawn_options::{MessageQueueLocation, SpawnOptions},
+    vm::VM,
   },
-  fail::RtResult,
-  term::*,
+  fail::{self, RtErr, RtResult},
+  native_fun::erlang::process_info,
+  term::{term_builder::tuple_builder::tuple3, *},
 };
-use core::ptr;
+use core::sync::atomic::AtomicUsize;
+use std::{
+  collections::{HashMap, HashSet},
+  sync::Arc,
+};
+
+fn module() -> &'static str {
+  "process: "
+}
 
 //#[allow(dead_code)]
 //#[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -40,6 +56,38 @@ use core::ptr;
 //  }
 //}
 
+/// State of the timer for `receive ... after`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReceiveTimer {
+  None,
+  /// Timer is running, on expiry the process continues at this location (the
+  /// `timeout` instruction).
+  Active(CodePtr),
+  /// Timer of `timeout_ms` set by `wait_timeout`, the scheduler starts it when
+  /// the process stops running.
+  Pending(u64, CodePtr),
+  /// Timer has expired while the process was not waiting, the next
+  /// `wait_timeout` will continue to the `timeout` instruction.
+  Expired,
+}
+
+/// Part of a process which other threads reach through the process registry,
+/// while the process itself is touched only by the thread which runs it.
+pub struct ProcessShared {
+  pub pid: Term,
+  /// Index of the scheduler which owns the process, changes when another
+  /// scheduler steals it
+  pub scheduler: AtomicUsize,
+  /// Signals from other processes, see `signal.rs`
+  pub signals: SignalQueue,
+  /// Value of `process_flag(save_calls, N)`, the calls are not recorded yet
+  pub save_calls: AtomicUsize,
+}
+
+// The terms in the signals point only to their own heap fragments
+unsafe impl Send for ProcessShared {}
+unsafe impl Sync for ProcessShared {}
+
 pub struct Process {
   pub pid: Term,
 
@@ -49,7 +97,11 @@ pub struct Process {
 
   /// Current scheduler queue where this process is registered
   pub current_queue: scheduler::Queue,
-  pub owned_by_scheduler: *mut Scheduler,
+  /// Neighbours in the run queue, while `current_queue` is a run queue
+  pub run_queue_link: RunQueueLink,
+  pub shared: Arc<ProcessShared>,
+  /// Receive timeout timer set by `wait_timeout`
+  pub recv_timer: ReceiveTimer,
 
   // Execution Context, etc.
   /// Runtime context with registers, instruction pointer etc
@@ -66,10 +118,31 @@ pub struct Process {
   /// Error field is set on exception when the execution loop is interrupted
   /// with `DispatchResult::Exception`
   pub error: Option<(ExceptionType, Term)>,
+  /// Stacktrace given to `erlang:raise/3` for the exception being raised,
+  /// otherwise nil. Taken by the catch or by the exit reason.
+  pub stacktrace: Term,
+  /// Progress of a native function which ran out of reductions, see
+  /// `native_trap`
+  pub trap: Option<NativeTrap>,
   /// How many catch frames are there on stack
   pub num_catches: isize,
+  /// Exit signal which has arrived, the process will be terminated with this
+  /// reason the next time the scheduler sees it
+  pub pending_exit: Option<OwnedTerm>,
+  /// Linked processes, they exchange exit signals on termination
+  pub links: HashSet<Term>,
+  /// Monitors created by this process, by reference id
+  pub monitors: HashMap<Word, MonitorTarget>,
+  /// Monitors watching this process, by reference id
+  pub monitored_by: HashMap<Word, Monitor>,
+  /// Reply to `process_info` of another process, see `Signal::InfoReply`
+  pub info_reply: Option<Option<OwnedTerm>>,
+  /// Process dictionary, `erlang:put/get/erase`
+  pub dictionary: ProcessDict,
 
   pub process_flags: ProcessFlags,
+  /// Heap size limit checked after every GC
+  pub max_heap_size: MaxHeapSize,
 }
 
 impl Process {
@@ -80,7 +153,7 @@ impl Process {
     _parent_pid: Term,
     mfarity: &ModFunArity,
     spawn_opts: &SpawnOptions,
-    code_server: &mut CodeServer,
+    code_server: &CodeServer,
   ) -> RtResult<Process> {
     assert!(pid.is_local_pid());
     assert!(_parent_pid.is_local_pid() || _parent_pid == Term::nil());
@@ -88,25 +161,62 @@ impl Process {
     // Process must start with some code location
     match code_server.lookup_beam_code_and_load(mfarity) {
       Ok(ip) => {
+        let mut heap = Heap::new(Designation::ProcessHeap);
+        heap.set_fullsweep_after(spawn_opts.fullsweep_after);
+        heap.set_min_heap_size(spawn_opts.min_heap_size);
+        heap.set_min_bin_vheap_size(spawn_opts.min_bin_vheap_size);
+
+        // Links and monitors of `spawn_link` and `spawn_monitor` exist before
+        // anyone can send a signal to the new process
+        let mut links = HashSet::new();
+        if spawn_opts.link {
+          links.insert(_parent_pid);
+        }
+        let mut monitored_by = HashMap::new();
+        if let Some(ref_id) = spawn_opts.monitor {
+          let mon = Monitor {
+            watcher: _parent_pid,
+            name: Term::nil(),
+            tag: OwnedTerm::new(gen_atoms::DOWN_TAG)?,
+          };
+          monitored_by.insert(ref_id, mon);
+        }
+
         let p = Process {
           pid,
           process_flags: spawn_opts.process_flags,
+          max_heap_size: spawn_opts.max_heap_size,
 
           // Scheduling
           prio: spawn_opts.prio,
           current_queue: scheduler::Queue::None,
+          run_queue_link: RunQueueLink::default(),
           timeslice_result: scheduler::SliceResult::None,
-          owned_by_scheduler: ptr::null_mut(),
+          shared: Arc::new(ProcessShared {
+            pid,
+            scheduler: AtomicUsize::new(0),
+            signals: SignalQueue::new(),
+            save_calls: AtomicUsize::new(0),
+          }),
+          recv_timer: ReceiveTimer::None,
 
           // Memory
-          heap: Heap::new(Designation::ProcessHeap),
-          mailbox: ProcessMailbox::new(),
+          heap,
+          mailbox: ProcessMailbox::new(spawn_opts.msg_queue),
 
           // Execution
           context: RuntimeContext::new(ip),
 
           error: None,
+          stacktrace: Term::nil(),
+          trap: None,
           num_catches: 0,
+          pending_exit: None,
+          info_reply: None,
+          dictionary: ProcessDict::new(),
+          links,
+          monitors: HashMap::new(),
+          monitored_by,
         };
         Ok(p)
         // Ok(sync::Arc::new(sync::RwLock::new(p)))
@@ -120,7 +230,8 @@ impl Process {
   pub fn set_spawn_args(&mut self, mfargs: &ModFunArgs) -> RtResult<()> {
     let mut xindex = 0;
     mfargs.for_each_arg(|arg| -> RtResult<()> {
-      self.context.set_x(xindex, arg);
+      let arg1 = copy_term::copy_shared_to(arg, &mut self.heap)?;
+      self.context.set_x(xindex, arg1);
       xindex += 1;
       Ok(())
     })
@@ -141,7 +252,7 @@ impl Process {
   pub fn jump(
     &mut self,
     mfarity: &ModFunArity,
-    code_server: &mut CodeServer,
+    code_server: &CodeServer,
   ) -> RtResult<()> {
     // TODO: Find mfa in code server and set IP to it
     match code_server.lookup_beam_code_and_load(mfarity) {
@@ -153,6 +264,43 @@ impl Process {
     }
   }
 
+  /// Put the process to sleep until a message arrives, using as little memory
+  /// as possible. The stack is discarded and the heap is compacted. On wakeup
+  /// the process will call `mfargs` with an empty stack.
+  pub fn hibernate(
+    &mut self,
+    mfargs: &ModFunArgs,
+    code_server: &CodeServer,
+  ) -> RtResult<()> {
+    let mfarity = mfargs.get_mfarity()?;
+    // Reserve the heap for the args before anything is changed, because the
+    // call will run again after a GC if the heap is full
+    let mut args_size = SizeWords::zero();
+    mfargs.for_each_arg(|arg| {
+      args_size = args_size + copy_term::size_of_shared(arg)?;
+      Ok(())
+    })?;
+    if !self.heap.heap_check_available(args_size) {
+      return Err(RtErr::HeapIsFull(args_size));
+    }
+    let ip = match code_server.lookup_beam_code_and_load(&mfarity) {
+      Ok(ip) => ip,
+      Err(_) => return fail::create::undef(),
+    };
+    self.set_spawn_args(mfargs)?;
+    self.context.ip = ip;
+    self.context.live = mfarity.arity;
+    self.context.clear_cp();
+
+    for fragment in self.mailbox.take_fragments() {
+      self.heap.attach_fragment(fragment);
+    }
+    let mut roots = self.roots_get_iterator();
+    self.heap.gc_hibernate(roots.as_mut())?;
+    self.verify_heap("hibernate", &[]);
+    Ok(())
+  }
+
   pub fn set_exception(&mut self, exc_type: ExceptionType, reason: Term) {
     // panic!("{}{} set_error {}", module(), self.pid, e);
     self.error = Some((exc_type, reason));
@@ -162,23 +310,286 @@ impl Process {
   //    self.error = ProcessError::None;
   //  }
 
-  /// Copy a message and put into process mailbox.
-  pub fn deliver_message(
+  /// Copy a message and put into process mailbox. Depending on the message
+  /// queue setting, the message is copied to the process heap, or to a new
+  /// heap fragment owned by the mailbox. Only the process itself can do this,
+  /// other processes send `Signal::Message`.
+  pub fn deliver_message(&mut self, message: Term) -> RtResult<()> {
+    match self.mailbox.location {
+      MessageQueueLocation::OnHeap => {
+        let size = copy_term::size_of_shared(message)?;
+        if self.heap.heap_check_available(size) {
+          let m1 = copy_term::copy_shared_to(message, &mut self.heap)?;
+          self.mailbox.put(m1);
+        } else {
+          // No room on the heap and no GC can be done in the middle of an
+          // instruction, the heap fragment is merged on the next GC.
+          self.mailbox.put_off_heap(message)?
+        }
+      }
+      MessageQueueLocation::OffHeap => self.mailbox.put_off_heap(message)?,
+    }
+    self.verify_heap("message delivery", &[]);
+    Ok(())
+  }
+
+  /// Apply the signals which other processes have sent, in the order of
+  /// arrival. Fails with `RtErr::ProcessKilled` if an exit signal is fatal,
+  /// the reason is in `pending_exit`.
+  pub fn handle_signals(&mut self, vm: &VM) -> RtResult<()> {
+    for signal in self.shared.signals.take() {
+      self.handle_signal(vm, signal);
+    }
+    if self.pending_exit.is_some() {
+      return Err(RtErr::ProcessKilled);
+    }
+    Ok(())
+  }
+
+  /// Stop accepting signals when the process terminates. The signals which
+  /// have arrived are applied, so that the links and monitors are complete.
+  pub fn close_signal_queue(&mut self, vm: &VM) {
+    for signal in self.shared.signals.close() {
+      self.handle_signal(vm, signal);
+    }
+  }
+
+  fn handle_signal(&mut self, vm: &VM, signal: Signal) {
+    match signal {
+      Signal::Message(message) => {
+        let (m, fragment) = message.into_parts();
+        self.mailbox.put_with_fragment(m, fragment);
+      }
+      Signal::Exit {
+        from,
+        reason,
+        from_link,
+      } => {
+        if let Err(e) = self.exit_signal(from, reason, from_link) {
+          error_report::lost_message("Exit signal", self.pid, &e);
+        }
+      }
+      Signal::Link(pid) => {
+        self.links.insert(pid);
+      }
+      Signal::Unlink(pid) => {
+        self.links.remove(&pid);
+      }
+      Signal::Monitor(ref_id, mon) => {
+        self.monitored_by.insert(ref_id, mon);
+      }
+      Signal::Demonitor(ref_id) => {
+        self.monitored_by.remove(&ref_id);
+      }
+      Signal::Down(ref_id, message) => {
+        // Dropped if `demonitor` was called while the signal was on its way
+        if self.monitors.remove(&ref_id).is_some() {
+          let (m, fragment) = message.into_parts();
+          self.mailbox.put_with_fragment(m, fragment);
+        }
+      }
+      Signal::InfoRequest { from, query } => {
+        process_info::reply_to_request(vm, self, from, query)
+      }
+      Signal::InfoReply(reply) => self.info_reply = Some(reply),
+    }
+  }
+
+  /// Exit signal from `from`, either from a terminated linked process
+  /// (`from_link`) or from `exit/2`.
+  /// * Reason `kill` from `exit/2` can not be trapped, the process is killed.
+  /// * A process trapping exits receives `{'EXIT', From, Reason}` message.
+  /// * Reason `normal` is ignored, unless a process sends it to itself.
+  /// * Otherwise the process will terminate with the same reason.
+  pub fn exit_signal(
     &mut self,
-    proc_reg: &mut ProcessRegistry,
-    message: Term,
+    from: Term,
+    reason: OwnedTerm,
+    from_link: bool,
   ) -> RtResult<()> {
-    let m1 = copy_term::copy_to(message, &mut self.heap)?;
-    self.mailbox.put(m1);
+    // The link is gone together with the terminated process, and a signal
+    // from a link which was removed on its way is ignored
+    if from_link && !self.links.remove(&from) {
+      return Ok(());
+    }
+    if self.pending_exit.is_some() {
+      // Already terminating
+      return Ok(());
+    }
+    let r = reason.get();
+    if r == gen_atoms::KILL && !from_link {
+      self.pending_exit = Some(OwnedTerm::new(gen_atoms::KILLED)?);
+    } else if self.process_flags.get(process_flags::TRAP_EXIT) {
+      let mut msg_heap = Heap::new_fragment(boxed::Tuple::storage_size(3));
+      let msg = tuple3(&mut msg_heap, gen_atoms::EXIT_TAG, from, r)?;
+      self.deliver_message(msg)?;
+    } else if r != gen_atoms::NORMAL || from == self.pid {
+      self.pending_exit = Some(reason);
+    }
+    Ok(())
+  }
 
-    // Notify our current scheduler that a new message has come to possibly wake
-    // up from infinite or timed wait.
-    unsafe {
-      (*self.owned_by_scheduler).notify_new_incoming_message(proc_reg, self);
+  /// Heap has grown over the `max_heap_size` limit after a GC. Log an error
+  /// report and/or kill the process, depending on the flag settings.
+  fn on_max_heap_size_exceeded(&self, heap_size: usize) -> RtResult<()> {
+    if self.max_heap_size.error_logger {
+      error_report::error_report(format!(
+        "Process {} exceeded max_heap_size, heap size {} words, limit {} words",
+        self.pid, heap_size, self.max_heap_size.size
+      ));
+    }
+    if self.max_heap_size.kill {
+      return Err(RtErr::ProcessKilled);
     }
     Ok(())
   }
 
+  /// If heap verification is enabled, check the process heap, the off-heap
+  /// messages and the root terms. The first violation found is reported with
+  /// the process, address and the current opcode, and the emulator panics.
+  pub fn verify_heap(&mut self, after: &str, extra_roots: &[Term]) {
+    if !verify::is_enabled() {
+      return;
+    }
+    let mut verifier = verify::HeapVerifier::new();
+    verifier.add_heap(&self.heap);
+    for frag in self.mailbox.get_fragments() {
+      verifier.add_heap(frag);
+    }
+    let registers = self.context.registers_slice(0, self.context.live);
+    let result = unsafe {
+      verifier
+        .verify_heap(&self.heap)
+        .and_then(|_| {
+          self
+            .mailbox
+            .get_fragments()
+            .try_for_each(|frag| verifier.verify_heap(frag))
+        })
+        .and_then(|_| verifier.verify_roots(registers))
+        .and_then(|_| verifier.verify_roots(self.mailbox.get_inbox()))
+        .and_then(|_| verifier.verify_roots(self.dictionary.get_data()))
+        .and_then(|_| verifier.verify_roots(core::slice::from_ref(&self.stacktrace)))
+        .and_then(|_| match &self.trap {
+          Some(trap) => verifier.verify_roots(trap.get_terms()),
+          None => Ok(()),
+        })
+        .and_then(|_| verifier.verify_roots(extra_roots))
+    };
+    if let Err(violation) = result {
+      let (op_p, op_name) = self.context.get_current_opcode();
+      panic!(
+        "{}Heap verification failed after {} in {}: {}, opcode {} at {:p}",
+        module(),
+        after,
+        self.pid,
+        violation,
+        op_name,
+        op_p
+      );
+    }
+  }
+
+  /// Remove the current message from the mailbox. Heap fragment of an
+  /// off-heap message is attached to the process heap.
+  pub fn remove_message(&mut self) -> Term {
+    // Message has arrived before the receive timeout
+    self.cancel_receive_timer();
+    let (message, fragment) = self.mailbox.remove_current();
+    if let Some(frag) = fragment {
+      self.heap.attach_fragment(frag);
+    }
+    message
+  }
+
+  /// Heap to ask for when a call which has run out of heap is retried: at least
+  /// double the last request, so that a result built by many small allocations
+  /// takes a few GCs and not one per allocation.
+  pub fn retry_need(need: SizeWords, failed: SizeWords) -> SizeWords {
+    SizeWords::new((need.words + failed.words).max(need.words * 2))
+  }
+
+  /// Run the GC with the process roots and `extra_roots` (terms which are
+  /// held outside of the registers, such as native function args). After it
+  /// `need` words must be available on the heap.
+  pub fn collect_garbage(
+    &mut self,
+    need: SizeWords,
+    extra_roots: &mut [Term],
+  ) -> RtResult<()> {
+    // Off-heap messages are merged into the heap by this GC
+    for fragment in self.mailbox.take_fragments() {
+      self.heap.attach_fragment(fragment);
+    }
+
+    // Binary being built is referred by a raw pointer, pass it as a root term
+    let mut current_bin = self.context.current_bin.gc_get_root();
+    let current_bin_root =
+      ArrayRootIterator::new(core::slice::from_mut(&mut current_bin));
+    let roots = ChainRootIterator::new(vec![
+      self.roots_get_iterator(),
+      Box::new(current_bin_root),
+      Box::new(ArrayRootIterator::new(extra_roots)),
+    ]);
+    self.heap.garbage_collect(need, Box::new(roots))?;
+    self.context.current_bin.gc_set_root(current_bin);
+    self.verify_heap("garbage collection", &[current_bin]);
+
+    let heap_size = self.heap.get_total_size();
+    if self.max_heap_size.is_exceeded(heap_size) {
+      self.on_max_heap_size_exceeded(heap_size)?;
+    }
+
+    if !self.heap.heap_check_available(need) {
+      return Err(RtErr::HeapIsFull(need));
+    }
+    Ok(())
+  }
+
+  /// Start a receive timeout timer, unless one is already running. When it
+  /// expires the process will continue at `timeout_ip`. The scheduler starts
+  /// the timer when the process stops running.
+  pub fn start_receive_timer(&mut self, timeout_ms: u64, timeout_ip: CodePtr) {
+    if self.recv_timer == ReceiveTimer::None {
+      self.recv_timer = ReceiveTimer::Pending(timeout_ms, timeout_ip);
+    }
+  }
+
+  /// The receive has finished, the scheduler will stop the timer if one is
+  /// running.
+  pub fn cancel_receive_timer(&mut self) {
+    self.recv_timer = ReceiveTimer::None;
+  }
+
+  /// State saved by the native function `name` when it trapped, if the
+  /// trapped call had the same `args`.
+  pub fn get_trap_state(&self, name: &'static str, args: &[Term]) -> Option<Vec<Term>> {
+    let trap = self.trap.as_ref()?;
+    trap.get_state(name, args).map(|state| state.to_vec())
+  }
+
+  /// Save the progress of the native function `name` and return
+  /// `RtErr::Trap`, the function will be called again with the same `args`.
+  pub fn trap<T>(
+    &mut self,
+    name: &'static str,
+    args: &[Term],
+    state: &[Term],
+  ) -> RtResult<T> {
+    self.trap = Some(NativeTrap::new(name, args, state));
+    Err(RtErr::Trap)
+  }
+
+  /// Heap usage in words: young heap with the stack, the stack depth, and the
+  /// total memory including the old heap and fragments.
+  pub fn get_heap_sizes(&self) -> (usize, usize, usize) {
+    (
+      self.heap.get_heap_size(),
+      self.heap.stack_depth(),
+      self.heap.get_total_size(),
+    )
+  }
+
   /// Ugly hack to mut-borrow the context without making borrow checker sad.
   /// We guarantee that this borrow will not outlive the process, or we will pay
   /// the price debugging the SIGSEGV.
@@ -191,21 +602,40 @@ impl Process {
 
 impl TRootSource for Process {
   /// Create a union iterator over all roots in the Process:
-  /// Roots are: live registers, process error value, stack, etc.
+  /// Roots are: live registers, mailbox messages, process error value.
+  /// The stack is owned by the heap and is handled by the heap itself.
   fn roots_get_iterator(&mut self) -> Box<dyn TRootIterator> {
-    let regs_slice = self.context.registers_slice_mut(0, self.context.live);
-    Box::new(ArrayRootIterator::new(regs_slice))
+    let live = self.context.live;
+    let mut parts: Vec<Box<dyn TRootIterator>> = vec![
+      Box::new(ArrayRootIterator::new(
+        self.context.registers_slice_mut(0, live),
+      )),
+      Box::new(ArrayRootIterator::new(self.mailbox.get_inbox_mut())),
+      Box::new(ArrayRootIterator::new(self.dictionary.get_data_mut())),
+      Box::new(ArrayRootIterator::new(core::slice::from_mut(
+        &mut self.stacktrace,
+      ))),
+    ];
+    if let Some(trap) = self.trap.as_mut() {
+      parts.push(Box::new(ArrayRootIterator::new(trap.get_terms_mut())));
+    }
+    if let Some((_, ref mut reason)) = self.error {
+      parts.push(Box::new(ArrayRootIterator::new(core::slice::from_mut(
+        reason,
+      ))));
+    }
+    Box::new(ChainRootIterator::new(parts))
   }
 }
 
 impl THeapOwner for Process {
   /// Request heap space from this process' heap, GC will be invoked if necessary
+  /// GC is also invoked if the process refers to too much binary heap data.
   fn ensure_heap(&mut self, need: SizeWords) -> RtResult<()> {
-    if self.heap.heap_check_available(need) {
+    if self.heap.heap_check_available(need) && !self.heap.is_bin_vheap_full() {
       return Ok(());
     }
-    let roots = self.roots_get_iterator();
-    self.heap.garbage_collect(roots)
+    self.collect_garbage(need, &mut [])
   }
 
   #[inline]
@@ -0,0 +1,124 @@
+//! Process dictionary: key-value storage private to a process. Keys and
+//! values live on the process heap, stored as pairs in one array which is
+//! given to the GC as roots.
+use crate::{
+  fail::RtResult,
+  term::{compare, Term},
+};
+
+pub struct ProcessDict {
+  /// Keys and values interleaved: key0, value0, key1, value1...
+  data: Vec<Term>,
+}
+
+impl ProcessDict {
+  pub fn new() -> Self {
+    Self { data: Vec::new() }
+  }
+
+  /// Keys are compared exactly (`=:=`).
+  fn find(&self, key: Term) -> RtResult<Option<usize>> {
+    for (i, pair) in self.data.chunks_exact(2).enumerate() {
+      if compare::exact_eq(pair[0], key)? {
+        return Ok(Some(i * 2));
+      }
+    }
+    Ok(None)
+  }
+
+  pub fn get(&self, key: Term) -> RtResult<Option<Term>> {
+    Ok(self.find(key)?.map(|i| self.data[i + 1]))
+  }
+
+  /// Store the value, returns the previous value for the key.
+  pub fn put(&mut self, key: Term, value: Term) -> RtResult<Option<Term>> {
+    match self.find(key)? {
+      Some(i) => Ok(Some(core::mem::replace(&mut self.data[i + 1], value))),
+      None => {
+        self.data.push(key);
+        self.data.push(value);
+        Ok(None)
+      }
+    }
+  }
+
+  /// Remove the key, returns the value which was stored. The last pair
+  /// takes the place of the removed pair, the order is not kept.
+  pub fn erase(&mut self, key: Term) -> RtResult<Option<Term>> {
+    match self.find(key)? {
+      Some(i) => {
+        let value = self.data[i + 1];
+        self.data.swap_remove(i + 1);
+        self.data.swap_remove(i);
+        Ok(Some(value))
+      }
+      None => Ok(None),
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.data.clear();
+  }
+
+  /// Iterate over `(key, value)` pairs.
+  pub fn iter(&self) -> impl Iterator<Item = (Term, Term)> + '_ {
+    self.data.chunks_exact(2).map(|pair| (pair[0], pair[1]))
+  }
+
+  /// Access all keys and values, used by the GC to update their locations.
+  pub fn get_data_mut(&mut self) -> &mut [Term] {
+    &mut self.data
+  }
+
+  /// Access all keys and values, read-only.
+  pub fn get_data(&self) -> &[Term] {
+    &self.data
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    defs::SizeWords,
+    emulator::{gen_atoms, heap::Heap},
+    term::term_builder::tuple_builder::tuple2,
+  };
+
+  #[test]
+  fn test_dict_mixed_number_keys() {
+    let mut hp = Heap::new_fragment(SizeWords::new(64));
+    let one = Term::make_small_signed(1);
+    let two = Term::make_small_signed(2);
+    let one_f = Term::make_float(&mut hp, 1.0).unwrap();
+    let one_and_half = Term::make_float(&mut hp, 1.5).unwrap();
+    let two_f = Term::make_float(&mut hp, 2.0).unwrap();
+    let (a, b, c) = (gen_atoms::TRUE, gen_atoms::FALSE, gen_atoms::UNDEFINED);
+
+    let mut dict = ProcessDict::new();
+    assert_eq!(dict.put(one, a).unwrap(), None);
+    assert_eq!(dict.get(one_and_half).unwrap(), None);
+    assert_eq!(dict.get(one_f).unwrap(), None);
+    // 1 and 1.0 are different keys
+    assert_eq!(dict.put(one_f, b).unwrap(), None);
+    assert_eq!(dict.get(one).unwrap(), Some(a));
+    assert_eq!(dict.get(one_f).unwrap(), Some(b));
+
+    assert_eq!(dict.put(two, c).unwrap(), None);
+    assert_eq!(dict.erase(two_f).unwrap(), None);
+    assert_eq!(dict.erase(one_f).unwrap(), Some(b));
+    assert_eq!(dict.get(one).unwrap(), Some(a));
+    assert_eq!(dict.get(two).unwrap(), Some(c));
+
+    // Also inside of the keys
+    let key_int = tuple2(&mut hp, one, two).unwrap();
+    let key_float = tuple2(&mut hp, one_f, two).unwrap();
+    let key_int2 = tuple2(&mut hp, one, two).unwrap();
+    assert_eq!(dict.put(key_int, a).unwrap(), None);
+    assert_eq!(dict.get(key_float).unwrap(), None);
+    assert_eq!(dict.get(key_int2).unwrap(), Some(a));
+  }
+}
@@ -1,3 +1,9 @@
+use crate::{
+  emulator::{gen_atoms, heap::THeap},
+  fail::{self, RtResult},
+  term::{boxed, Term},
+};
+
 #[derive(Debug, Clone, Copy)]
 pub struct ProcessFlag(usize);
 
@@ -47,3 +53,77 @@ impl ProcessFlags {
     self.0 &= !flag.0;
   }
 }
+
+/// Process heap size limit, same as `max_heap_size` process flag in
+/// Erlang/OTP. Checked after every GC.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxHeapSize {
+  /// Limit in words, 0 means no limit.
+  pub size: usize,
+  /// Kill the process with reason `killed` when the limit is exceeded.
+  pub kill: bool,
+  /// Log an error report when the limit is exceeded.
+  pub error_logger: bool,
+}
+
+impl MaxHeapSize {
+  pub fn default() -> Self {
+    Self {
+      size: 0,
+      kill: true,
+      error_logger: true,
+    }
+  }
+
+  #[inline]
+  pub fn is_exceeded(&self, heap_size: usize) -> bool {
+    self.size != 0 && heap_size > self.size
+  }
+
+  /// Parse the flag value: either a non-negative small integer (size), or a
+  /// map with mandatory `size` and optional `kill` and `error_logger` keys.
+  pub fn from_term(val: Term) -> RtResult<Self> {
+    let mut result = Self::default();
+    if val.is_small() && val.get_small_signed() >= 0 {
+      result.size = val.get_small_unsigned();
+      return Ok(result);
+    }
+    if !val.is_map() || val == Term::empty_map() {
+      return fail::create::badarg();
+    }
+    let map_p = val.get_box_ptr::<boxed::Map>();
+    match unsafe { boxed::Map::get(map_p, gen_atoms::SIZE)? } {
+      Some(size) if size.is_small() && size.get_small_signed() >= 0 => {
+        result.size = size.get_small_unsigned()
+      }
+      _ => return fail::create::badarg(),
+    }
+    result.kill = Self::get_bool_key(map_p, gen_atoms::KILL, result.kill)?;
+    result.error_logger =
+      Self::get_bool_key(map_p, gen_atoms::ERROR_LOGGER, result.error_logger)?;
+    Ok(result)
+  }
+
+  fn get_bool_key(map_p: *const boxed::Map, key: Term, default: bool) -> RtResult<bool> {
+    match unsafe { boxed::Map::get(map_p, key)? } {
+      None => Ok(default),
+      Some(val) if val.is_bool() => Ok(val.is_true()),
+      Some(_) => fail::create::badarg(),
+    }
+  }
+
+  /// Create a map `#{size, kill, error_logger}` on the heap.
+  pub fn to_term(self, hp: &mut dyn THeap) -> RtResult<Term> {
+    let map_p = boxed::Map::create_into(hp, 3)?;
+    unsafe {
+      boxed::Map::add(map_p, gen_atoms::SIZE, Term::make_small_unsigned(self.size))?;
+      boxed::Map::add(map_p, gen_atoms::KILL, Term::make_bool(self.kill))?;
+      boxed::Map::add(
+        map_p,
+        gen_atoms::ERROR_LOGGER,
+        Term::make_bool(self.error_logger),
+      )?;
+    }
+    Ok(Term::make_boxed(map_p))
+  }
+}
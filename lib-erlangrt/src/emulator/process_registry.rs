@@ -1,82 +1,193 @@
-use crate::{emulator::process::Process, term::Term};
-use core::ptr;
-use std::collections::HashMap;
+//! Processes by pid and the registered names, shared by all scheduler
+//! threads. The registry only knows how to reach a process, the process
+//! itself belongs to a scheduler, see `ProcessShared`.
+use crate::{
+  emulator::{gen_atoms, process::ProcessShared},
+  fail::{self, RtResult},
+  term::Term,
+};
+use std::{
+  collections::HashMap,
+  sync::{Arc, RwLock},
+};
 
-pub struct ProcessRegistry {
-  /// Dict of pids to process boxes
-  pid_to_proc: HashMap<Term, Process>,
+struct NameTable {
   name_to_pidport: HashMap<Term, Term>,
+  /// Reverse lookup, a process can have only one name
+  pid_to_name: HashMap<Term, Term>,
+}
+
+pub struct ProcessRegistry {
+  pid_to_proc: RwLock<HashMap<Term, Arc<ProcessShared>>>,
+  /// Taken before `pid_to_proc` when both are needed
+  names: RwLock<NameTable>,
 }
 
 impl ProcessRegistry {
   pub fn new() -> Self {
     Self {
-      pid_to_proc: HashMap::new(),
-      name_to_pidport: HashMap::new(),
+      pid_to_proc: RwLock::new(HashMap::new()),
+      names: RwLock::new(NameTable {
+        name_to_pidport: HashMap::new(),
+        pid_to_name: HashMap::new(),
+      }),
     }
   }
 
-  /// Register a process `proc_` in the process table and also queue it for
-  /// execution. This is invoked by vm when a new process is spawned.
+  /// Make a new process reachable by its pid.
   #[inline]
-  pub fn insert(&mut self, pid: Term, proc: Process) {
-    self.pid_to_proc.insert(pid, proc);
+  pub fn insert(&self, shared: Arc<ProcessShared>) {
+    self.pid_to_proc.write().unwrap().insert(shared.pid, shared);
   }
 
-  #[inline]
-  pub fn remove(&mut self, pid: Term) {
-    self.pid_to_proc.remove(&pid);
+  /// Remove a terminated process and its registered name.
+  pub fn remove(&self, pid: Term) {
+    self.pid_to_proc.write().unwrap().remove(&pid);
+    // After the pid is gone `register_name` can not give it a new name
+    let mut names = self.names.write().unwrap();
+    if let Some(name) = names.pid_to_name.remove(&pid) {
+      names.name_to_pidport.remove(&name);
+    }
   }
 
   #[inline]
   pub fn count(&self) -> usize {
-    self.pid_to_proc.len()
+    self.pid_to_proc.read().unwrap().len()
   }
 
-  /// Borrow a read-only process, if it exists. Return `None` if we are sorry.
+  /// Find a process to send signals to. Return `None` if we are sorry.
   #[inline]
-  pub fn lookup_pid(&self, pid: Term) -> Option<&Process> {
-    assert!(pid.is_local_pid());
-    self.pid_to_proc.get(&pid)
+  pub fn lookup(&self, pid: Term) -> Option<Arc<ProcessShared>> {
+    self.pid_to_proc.read().unwrap().get(&pid).cloned()
   }
 
-  /// Borrow a mutable process, if it exists. Return `None` if we are sorry.
   #[inline]
-  pub fn lookup_pid_mut(&mut self, pid: Term) -> Option<&mut Process> {
-    assert!(pid.is_local_pid());
-    self.pid_to_proc.get_mut(&pid)
+  pub fn exists(&self, pid: Term) -> bool {
+    self.pid_to_proc.read().unwrap().contains_key(&pid)
   }
 
+  /// Query contents of the name-to-pid/port table
+  pub fn find_registered(&self, name: Term) -> Option<Term> {
+    self.names.read().unwrap().name_to_pidport.get(&name).cloned()
+  }
 
-  /// Find a process and instead of borrowing return a pointer to it.
-  #[inline]
-  #[allow(dead_code)]
-  pub fn unsafe_lookup_pid(&self, pid: Term) -> *const Process {
-    assert!(pid.is_local_pid());
-    match self.pid_to_proc.get(&pid) {
-      Some(p) => p as *const Process,
-      None => ptr::null(),
+  /// Give the name to a pid or a port. Fails if the name is taken, or if the
+  /// process is not alive or already has a name.
+  pub fn register_name(&self, name: Term, pid_or_port: Term) -> bool {
+    let mut names = self.names.write().unwrap();
+    if names.name_to_pidport.contains_key(&name) {
+      return false;
     }
+    if pid_or_port.is_pid() {
+      let can_register = pid_or_port.is_local_pid()
+        && !names.pid_to_name.contains_key(&pid_or_port)
+        && self.exists(pid_or_port);
+      if !can_register {
+        return false;
+      }
+      names.pid_to_name.insert(pid_or_port, name);
+    }
+    names.name_to_pidport.insert(name, pid_or_port);
+    true
   }
 
-  /// Find a process and instead of borrowing return a mutable pointer to it.
-  #[inline]
-  pub fn unsafe_lookup_pid_mut(&mut self, pid: Term) -> *mut Process {
-    assert!(pid.is_local_pid());
-    match self.pid_to_proc.get_mut(&pid) {
-      Some(p) => p as *mut Process,
-      None => ptr::null_mut(),
+  /// Remove the name from the name table, returns the pid or port which had
+  /// the name.
+  pub fn unregister_name(&self, name: Term) -> Option<Term> {
+    let mut names = self.names.write().unwrap();
+    let pid_or_port = names.name_to_pidport.remove(&name)?;
+    names.pid_to_name.remove(&pid_or_port);
+    Some(pid_or_port)
+  }
+
+  /// Name given to the process by `erlang:register`.
+  pub fn registered_name(&self, pid: Term) -> Option<Term> {
+    self.names.read().unwrap().pid_to_name.get(&pid).cloned()
+  }
+
+  /// All registered names.
+  pub fn registered_names(&self) -> Vec<Term> {
+    self.names.read().unwrap().name_to_pidport.keys().cloned().collect()
+  }
+
+  /// Find where a message to `dest` goes: a pid, a registered name or
+  /// `{Name, Node}`. A name which is not registered on the local node is a
+  /// `badarg`. Returns `None` if the message is to be dropped silently, for a
+  /// remote pid or node, as there is no distribution.
+  pub fn resolve_send_dest(&self, dest: Term) -> RtResult<Option<Term>> {
+    if dest.is_local_pid() {
+      return Ok(Some(dest));
+    }
+    if dest.is_pid() {
+      return Ok(None);
+    }
+    let name = if dest.is_atom() {
+      dest
+    } else if dest.is_tuple() && dest != Term::empty_tuple() {
+      let tuple_p = dest.get_tuple_ptr();
+      let (name, node) = unsafe {
+        if (*tuple_p).get_arity() != 2 {
+          return fail::create::badarg();
+        }
+        ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+      };
+      if !name.is_atom() || !node.is_atom() {
+        return fail::create::badarg();
+      }
+      if node != gen_atoms::NONODE_AT_NOHOST {
+        return Ok(None);
+      }
+      name
+    } else {
+      return fail::create::badarg();
+    };
+    match self.find_registered(name) {
+      Some(pid_or_port) => Ok(Some(pid_or_port)),
+      None => fail::create::badarg(),
     }
   }
+}
 
-  /// Query contents of the name-to-pid/port table
-  pub fn find_registered(&self, name: Term) -> Option<Term> {
-    self.name_to_pidport.get(&name).cloned()
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::{atom, signal::SignalQueue};
+  use core::sync::atomic::AtomicUsize;
+
+  fn make_shared(n: usize) -> Arc<ProcessShared> {
+    Arc::new(ProcessShared {
+      pid: Term::make_local_pid(n),
+      scheduler: AtomicUsize::new(0),
+      signals: SignalQueue::new(),
+      save_calls: AtomicUsize::new(0),
+    })
   }
 
-  /// Add contents of the name-to-pid/port table, no check is made for whether
-  /// the value is new, will overwrite.
-  pub fn register_name(&mut self, name: Term, pid_or_port: Term) {
-    self.name_to_pidport.insert(name, pid_or_port);
+  #[test]
+  fn test_registry_names() {
+    let reg = ProcessRegistry::new();
+    let (pid1, pid2) = (Term::make_local_pid(1), Term::make_local_pid(2));
+    let (name1, name2) = (atom::from_str("reg_test1"), atom::from_str("reg_test2"));
+    reg.insert(make_shared(1));
+    // A process which is not alive can not get a name
+    assert!(!reg.register_name(name1, pid2));
+    assert!(reg.register_name(name1, pid1));
+    // The name is taken and the process already has a name
+    reg.insert(make_shared(2));
+    assert!(!reg.register_name(name1, pid2));
+    assert!(!reg.register_name(name2, pid1));
+    assert_eq!(reg.find_registered(name1), Some(pid1));
+    assert_eq!(reg.registered_name(pid1), Some(name1));
+
+    // The name goes away with the process
+    reg.remove(pid1);
+    assert_eq!(reg.find_registered(name1), None);
+    assert!(reg.register_name(name1, pid2));
+    assert_eq!(reg.unregister_name(name1), Some(pid2));
+    assert_eq!(reg.registered_name(pid2), None);
+    assert_eq!(reg.count(), 1);
   }
 }
@@ -0,0 +1,181 @@
+//! Run queue as an intrusive double linked list. The links are stored in the
+//! queued processes (`Process::run_queue_link`), so pushing, popping and
+//! removing a process from the middle of the queue are O(1).
+use crate::{emulator::process::Process, term::Term};
+use std::collections::HashMap;
+
+/// Neighbours of a process in its run queue, both `None` when the process is
+/// first and last or is not queued.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunQueueLink {
+  pub prev: Option<Term>,
+  pub next: Option<Term>,
+}
+
+/// Storage of the run queue links, the scheduler stores them in its
+/// processes.
+pub trait TRunQueueLinks {
+  /// Links of a queued process, which must exist.
+  fn run_queue_link(&mut self, pid: Term) -> &mut RunQueueLink;
+}
+
+impl TRunQueueLinks for HashMap<Term, Box<Process>> {
+  fn run_queue_link(&mut self, pid: Term) -> &mut RunQueueLink {
+    &mut self.get_mut(&pid).unwrap().run_queue_link
+  }
+}
+
+pub struct RunQueue {
+  head: Option<Term>,
+  tail: Option<Term>,
+  len: usize,
+}
+
+impl RunQueue {
+  pub fn new() -> Self {
+    Self {
+      head: None,
+      tail: None,
+      len: 0,
+    }
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.head.is_none()
+  }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn push_back(&mut self, links: &mut impl TRunQueueLinks, pid: Term) {
+    *links.run_queue_link(pid) = RunQueueLink {
+      prev: self.tail,
+      next: None,
+    };
+    match self.tail {
+      Some(tail) => links.run_queue_link(tail).next = Some(pid),
+      None => self.head = Some(pid),
+    }
+    self.tail = Some(pid);
+    self.len += 1;
+  }
+
+  pub fn pop_front(&mut self, links: &mut impl TRunQueueLinks) -> Option<Term> {
+    let pid = self.head?;
+    self.remove(links, pid);
+    Some(pid)
+  }
+
+  /// Take the process which was queued last, used to give it away to another
+  /// scheduler.
+  pub fn pop_back(&mut self, links: &mut impl TRunQueueLinks) -> Option<Term> {
+    let pid = self.tail?;
+    self.remove(links, pid);
+    Some(pid)
+  }
+
+  /// Unlink a process which is known to be in this queue.
+  pub fn remove(&mut self, links: &mut impl TRunQueueLinks, pid: Term) {
+    let link = *links.run_queue_link(pid);
+    match link.prev {
+      Some(prev) => links.run_queue_link(prev).next = link.next,
+      None => self.head = link.next,
+    }
+    match link.next {
+      Some(next) => links.run_queue_link(next).prev = link.prev,
+      None => self.tail = link.prev,
+    }
+    *links.run_queue_link(pid) = RunQueueLink::default();
+    self.len -= 1;
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  impl TRunQueueLinks for HashMap<Term, RunQueueLink> {
+    fn run_queue_link(&mut self, pid: Term) -> &mut RunQueueLink {
+      self.get_mut(&pid).unwrap()
+    }
+  }
+
+  fn pid(n: usize) -> Term {
+    Term::make_local_pid(n)
+  }
+
+  fn make_links(count: usize) -> HashMap<Term, RunQueueLink> {
+    (0..count).map(|n| (pid(n), RunQueueLink::default())).collect()
+  }
+
+  /// Pop everything and check the order and the links left behind.
+  fn drain(q: &mut RunQueue, links: &mut HashMap<Term, RunQueueLink>) -> Vec<Term> {
+    let mut result = Vec::new();
+    while let Some(p) = q.pop_front(links) {
+      assert_eq!(links[&p], RunQueueLink::default());
+      result.push(p);
+    }
+    assert!(q.is_empty());
+    assert_eq!(q.len(), 0);
+    result
+  }
+
+  #[test]
+  fn test_run_queue_fifo() {
+    let mut links = make_links(3);
+    let mut q = RunQueue::new();
+    assert!(q.is_empty());
+    assert_eq!(q.pop_front(&mut links), None);
+    for n in 0..3 {
+      q.push_back(&mut links, pid(n));
+    }
+    assert_eq!(q.len(), 3);
+    assert_eq!(links[&pid(1)].prev, Some(pid(0)));
+    assert_eq!(links[&pid(1)].next, Some(pid(2)));
+    assert_eq!(drain(&mut q, &mut links), vec![pid(0), pid(1), pid(2)]);
+  }
+
+  #[test]
+  fn test_run_queue_pop_back() {
+    let mut links = make_links(3);
+    let mut q = RunQueue::new();
+    for n in 0..3 {
+      q.push_back(&mut links, pid(n));
+    }
+    assert_eq!(q.pop_back(&mut links), Some(pid(2)));
+    assert_eq!(links[&pid(1)].next, None);
+    q.push_back(&mut links, pid(2));
+    assert_eq!(drain(&mut q, &mut links), vec![pid(0), pid(1), pid(2)]);
+  }
+
+  #[test]
+  fn test_run_queue_remove() {
+    let mut links = make_links(5);
+    let mut q = RunQueue::new();
+    for n in 0..5 {
+      q.push_back(&mut links, pid(n));
+    }
+    // The middle, the head and the tail
+    q.remove(&mut links, pid(2));
+    q.remove(&mut links, pid(0));
+    q.remove(&mut links, pid(4));
+    assert_eq!(q.len(), 2);
+    for n in [0, 2, 4] {
+      assert_eq!(links[&pid(n)], RunQueueLink::default());
+    }
+    // A removed process can be queued again
+    q.push_back(&mut links, pid(2));
+    assert_eq!(drain(&mut q, &mut links), vec![pid(1), pid(3), pid(2)]);
+
+    q.push_back(&mut links, pid(4));
+    q.remove(&mut links, pid(4));
+    assert!(q.is_empty());
+    assert_eq!(q.pop_back(&mut links), None);
+  }
+}
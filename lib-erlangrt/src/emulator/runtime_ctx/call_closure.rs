@@ -14,7 +14,7 @@ fn module() -> &'static str {
 /// The `closure` is a callable closure with some frozen variables made with
 /// `fun() -> code end`.
 pub fn apply(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   _curr_p: &mut Process,
   closure: *mut boxed::Closure,
@@ -53,10 +53,7 @@ pub fn apply(
   // OR TODO: subscribe from all exports to the module and get invalidation notifications
   ctx.ip = match dst {
     Some(p) => p.ptr,
-    None => unsafe {
-      let cs = vm.get_code_server_p();
-      (*closure).update_location(&mut (*cs))?
-    },
+    None => unsafe { (*closure).update_location(&vm.code_server)? },
   };
   Ok(DispatchResult::Normal)
 }
@@ -18,7 +18,7 @@ fn module() -> &'static str {
 /// The `exp` is an export made with `fun module:name/0` which can point to
 /// either an Erlang function or to a BIF (native built-in function).
 pub fn apply(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   curr_p: &mut Process,
   export: *const boxed::Export,
@@ -52,8 +52,7 @@ pub fn apply(
       false,
     );
   } else {
-    let code_server = vm.get_code_server_p();
-    match unsafe { (*code_server).lookup_beam_code_and_load(&mfa) } {
+    match vm.code_server.lookup_beam_code_and_load(&mfa) {
       Ok(ip) => {
         if save_cp {
           ctx.cp = ctx.ip
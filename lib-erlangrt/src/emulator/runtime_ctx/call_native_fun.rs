@@ -1,6 +1,7 @@
 use super::RuntimeContext;
 use crate::{
   beam::disp_result::DispatchResult,
+  defs::SizeWords,
   emulator::{
     code_srv::CodeServer, heap::THeapOwner, mfa::ModFunArity, process::Process, vm::VM,
   },
@@ -42,7 +43,7 @@ pub enum CallBifTarget {
 #[inline]
 #[allow(clippy::too_many_arguments)]
 pub fn find_and_call_native_fun(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   curr_p: &mut Process,
   fail_label: Term,
@@ -168,7 +169,7 @@ fn callbif_resolve_mfa(
 /// in them, first resolve these args to values, and then call the function
 // #[inline]
 pub fn call_native_fun_fn(
-  vm: &mut VM,
+  vm: &VM,
   ctx: &mut RuntimeContext,
   curr_p: &mut Process,
   func_pointer: NativeFn,
@@ -187,9 +188,31 @@ pub fn call_native_fun_fn(
     }
   }
 
-  // Take n_args elements from args
-  let loaded_args1 = unsafe { slice::from_raw_parts(&loaded_args[0], n_args) };
-
-  // Apply the BIF call and return BifResult
-  (func_pointer)(vm, curr_p, loaded_args1)
+  // Apply the BIF call and return BifResult. If the heap is full, collect the
+  // garbage keeping the args alive and call again, each retry asks for at least
+  // twice as much.
+  let mut need = SizeWords::zero();
+  loop {
+    // Take n_args elements from args
+    let loaded_args1 = unsafe { slice::from_raw_parts(&loaded_args[0], n_args) };
+
+    let ip_before = curr_p.context.ip;
+    match (func_pointer)(vm, curr_p, loaded_args1) {
+      Err(RtErr::HeapIsFull(failed)) => {
+        // The call will be retried, it must not have changed the process yet
+        debug_assert!(
+          curr_p.context.ip == ip_before,
+          "Native function changed the process before running out of heap"
+        );
+        need = Process::retry_need(need, failed);
+        curr_p.collect_garbage(need, &mut loaded_args[0..n_args])?;
+      }
+      result => {
+        if let Ok(val) = result {
+          curr_p.verify_heap("native function call", &[val]);
+        }
+        return result;
+      }
+    }
+  }
 }
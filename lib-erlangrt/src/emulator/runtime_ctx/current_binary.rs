@@ -32,4 +32,21 @@ impl CurrentBinaryState {
   pub fn valid(&self) -> bool {
     self.dst.is_some()
   }
+
+  /// The binary under construction is referred by a raw pointer, which the GC
+  /// cannot see. Convert it to a term for the GC, or nil if there's none.
+  pub fn gc_get_root(&self) -> Term {
+    match self.dst {
+      Some(p) => unsafe { (*p).make_term() },
+      None => Term::nil(),
+    }
+  }
+
+  /// Restore the pointer from the term updated by the GC, without changing
+  /// the write offset.
+  pub fn gc_set_root(&mut self, t: Term) {
+    if self.dst.is_some() {
+      self.dst = Some(unsafe { boxed::Binary::get_trait_mut_from_term(t) });
+    }
+  }
 }
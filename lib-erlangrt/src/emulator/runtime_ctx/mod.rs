@@ -42,6 +42,8 @@ pub struct RuntimeContext {
 
   /// A metric of CPU time spent on running the code, roughly equal to 1 function call
   pub reductions: isize,
+  /// Reductions spent in the previous timeslices
+  reductions_done: usize,
 
   /// Current state of X registers.
   regs: [Term; MAX_XREGS],
@@ -75,7 +77,8 @@ impl RuntimeContext {
       ip,
       regs: [Term::non_value(); MAX_XREGS],
       live: 0,
-      reductions: 0,
+      reductions: Reductions::DEFAULT,
+      reductions_done: 0,
       current_bin: CurrentBinaryState::new(),
     }
   }
@@ -131,9 +134,37 @@ impl RuntimeContext {
   pub fn swap_in(&mut self) {
     // This amount is RESET every time process is about to be scheduled in, i.e.
     // there can be no "debt" of reductions, but the idea is nice.
+    self.reductions_done = self.get_reductions_done();
     self.reductions = Reductions::DEFAULT;
   }
 
+  /// Total reductions spent by the process, including the current timeslice.
+  pub fn get_reductions_done(&self) -> usize {
+    self.reductions_done + self.get_slice_reductions()
+  }
+
+  /// Reductions spent in the current timeslice.
+  pub fn get_slice_reductions(&self) -> usize {
+    (Reductions::DEFAULT - self.reductions.min(Reductions::DEFAULT)) as usize
+  }
+
+  #[inline]
+  pub fn consume_reductions(&mut self, amount: isize) {
+    self.reductions -= amount;
+  }
+
+  /// Units of work a native function can do in the rest of the timeslice, at
+  /// least one reduction worth so that it always makes progress.
+  pub fn get_native_work_budget(&self) -> usize {
+    self.reductions.max(1) as usize * Reductions::NATIVE_WORK_PER_REDUCTION
+  }
+
+  /// Charge reductions for `work` units done by a native function.
+  pub fn consume_native_work(&mut self, work: usize) {
+    let per_reduction = Reductions::NATIVE_WORK_PER_REDUCTION;
+    self.consume_reductions(work.div_ceil(per_reduction) as isize);
+  }
+
   #[inline]
   pub fn fetch_opcode(&mut self) -> opcode::RawOpcode {
     self.reductions -= Reductions::FETCH_OPCODE_COST;
@@ -143,6 +174,15 @@ impl RuntimeContext {
     op
   }
 
+  /// Location and name of the last fetched opcode, used in debug reports.
+  pub fn get_current_opcode(&self) -> (*const Word, &'static str) {
+    if self.args_ptr.is_null() {
+      return (ptr::null(), "none");
+    }
+    let op_p = unsafe { self.args_ptr.sub(1) };
+    (op_p, gen_op::opcode_name(opcode::from_memory_ptr(op_p)))
+  }
+
   /// Read a word from `self.ip` and advance `ip` by 1 word.
   /// NOTE: The compiler seems to be smart enough to optimize multiple fetches
   /// as multiple reads and a single increment.
@@ -318,7 +358,7 @@ impl RuntimeContext {
   /// Optional `save_cp` defines whether CP will be saved
   pub fn call_mfa(
     &mut self,
-    vm: &mut VM,
+    vm: &VM,
     curr_p: &mut Process,
     lr: &MFALookupResult,
     args: &[Term],
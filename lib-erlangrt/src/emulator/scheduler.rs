@@ -1,28 +1,65 @@
 //! Code related to task scheduling and priorities.
+//!
+//! Every scheduler owns the processes in its queues and wait sets, and is
+//! locked by the thread which runs it and by the threads which wake up or
+//! steal its processes. A running process is taken out of its scheduler, so
+//! only the thread which runs it can touch it. Everything one process does to
+//! another goes through the signal queue of the other, see `signal.rs`.
 use crate::{
   defs::{exc_type::ExceptionType, Word},
   emulator::{
-    gen_atoms, heap::THeapOwner, process::Process, process_flags,
-    process_registry::ProcessRegistry,
+    error_report,
+    gen_atoms,
+    heap::{owned_term::OwnedTerm, Heap, THeap, THeapOwner},
+    monitor,
+    process::{Process, ReceiveTimer},
+    run_queue::RunQueue,
+    signal::Signal,
+    timer_wheel::TimerWheel,
+    vm::VM,
   },
-  term::*,
+  fail::{self, RtResult},
+  term::{boxed, term_builder::tuple_builder::tuple2, *},
 };
 use colored::Colorize;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 
 fn module() -> &'static str {
   "scheduler: "
 }
 
 #[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
 pub enum Prio {
   /// Runs when no more jobs to take or at 8x disadvantage to normal
   Low = 0,
   /// Most of user processes run at this priority
   Normal = 1,
-  /// Takes priority always over everything else
+  /// Takes priority over normal and low
   High = 2,
+  /// Takes priority always over everything else, reserved for the system
+  Max = 3,
+}
+
+impl Prio {
+  /// Parse a priority atom `low`, `normal`, `high` or `max`.
+  pub fn from_term(val: Term) -> RtResult<Self> {
+    match val {
+      gen_atoms::LOW => Ok(Prio::Low),
+      gen_atoms::NORMAL => Ok(Prio::Normal),
+      gen_atoms::HIGH => Ok(Prio::High),
+      gen_atoms::MAX => Ok(Prio::Max),
+      _ => fail::create::badarg(),
+    }
+  }
+
+  pub fn to_term(self) -> Term {
+    match self {
+      Prio::Low => gen_atoms::LOW,
+      Prio::Normal => gen_atoms::NORMAL,
+      Prio::High => gen_atoms::HIGH,
+      Prio::Max => gen_atoms::MAX,
+    }
+  }
 }
 
 /// Enum identifies current registration of the process
@@ -31,6 +68,7 @@ pub enum Prio {
 pub enum Queue {
   None,
   // PendingTimers,
+  Max,
   High,
   Normal,
   Low,
@@ -47,53 +85,54 @@ pub enum SliceResult {
   Yield,
   /// Process entered infinite wait during the last timeslice
   InfiniteWait,
+  /// Process entered wait with a receive timeout during the last timeslice
+  TimedWait,
   /// Process normally finished during the last timeslice
   Finished,
   /// Error, exit or throw occured during the last timeslice, error is stored
   /// in the process, field `error`
   Exception,
+  /// Process was killed during the last timeslice, it is terminated without
+  /// looking for catches, reason is stored in the process, field `error`
+  Killed,
 }
 
 /// How many Normal processes can be scheduled before Low gets to run.
 const NORMAL_ADVANTAGE: Word = 8;
 
 /// Maintains run queues for different priorities and allows queuing processes,
-/// suspending processes, work balancing (TODO), etc.
+/// suspending processes, giving processes away to other schedulers, etc.
 pub struct Scheduler {
-  // This is the naive implementation of run queues.
-  // A better approach would be to build an intrusive double linked list through
-  // every process in the queue (as done by the original ERTS).
-  queue_low: VecDeque<Term>,
-  queue_normal: VecDeque<Term>,
-  queue_high: VecDeque<Term>,
-  /// Wait set for timed suspended processes (waiting for a timer)
-  timed_wait: HashMap<Term, ()>,
+  /// Processes owned by this scheduler which are not running: queued or
+  /// waiting
+  processes: HashMap<Term, Box<Process>>,
+  // Run queues are intrusive lists through the queued processes, like in ERTS
+  queue_low: RunQueue,
+  queue_normal: RunQueue,
+  queue_high: RunQueue,
+  queue_max: RunQueue,
+  /// Receive timeout timers. The processes which wait for them are in
+  /// `Queue::TimedWait`.
+  timed_wait: TimerWheel<Term>,
   /// Wait set for infinitely suspended processes (in endless receive)
   infinite_wait: HashMap<Term, ()>,
 
   /// A counter used to skip some schedulings for low processes
   advantage_count: Word,
 
-  /// Currently selected process
+  /// Process taken out to run, until it is checked in or terminated
   current: Option<Term>,
 }
 
-/// Hint from the logic finalizing timeslice result from a running process.
-/// The logic may continue running same process if the reductions allow, after
-/// having been interrupted by an exception.
-#[derive(PartialOrd, PartialEq)]
-enum ScheduleHint {
-  ContinueSameProcess,
-  TakeAnotherProcess,
-}
-
 impl Scheduler {
   pub fn new() -> Self {
     Self {
-      queue_low: VecDeque::new(),
-      queue_normal: VecDeque::new(),
-      queue_high: VecDeque::new(),
-      timed_wait: HashMap::new(),
+      processes: HashMap::new(),
+      queue_low: RunQueue::new(),
+      queue_normal: RunQueue::new(),
+      queue_high: RunQueue::new(),
+      queue_max: RunQueue::new(),
+      timed_wait: TimerWheel::new(),
       infinite_wait: HashMap::new(),
 
       advantage_count: 0,
@@ -101,54 +140,91 @@ impl Scheduler {
     }
   }
 
-  /// Queue a process by its pid.
-  pub fn enqueue(&mut self, proc_reg: &mut ProcessRegistry, pid: Term) {
-    self.enqueue_opt(proc_reg, pid, false);
+  /// Take a new process and queue it.
+  pub fn add_process(&mut self, proc: Box<Process>) {
+    let pid = proc.pid;
+    self.processes.insert(pid, proc);
+    self.enqueue(pid);
   }
 
   /// Queue a process by its pid.
   /// Will `panic!` if the process doesn't exist or is already queued.
-  /// Arg: `skip_queue_check` allows skipping the current queue assertion for
-  ///   when you can guarantee that the process is not queued anywhere.
-  pub fn enqueue_opt(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    pid: Term,
-    skip_queue_check: bool,
-  ) {
+  fn enqueue(&mut self, pid: Term) {
     assert!(pid.is_local_pid());
 
-    let prio = {
-      // Lookup the pid
-      let p = proc_reg.lookup_pid(pid).unwrap();
-      if !skip_queue_check {
-        assert_eq!(
-          p.current_queue,
-          Queue::None,
-          "Process must not be in any queue when queuing, now in {:?}",
-          p.current_queue
-        );
-      }
-      p.prio
+    let queue = {
+      let p = self.processes.get_mut(&pid).unwrap();
+      assert_eq!(
+        p.current_queue,
+        Queue::None,
+        "Process must not be in any queue when queuing, now in {:?}",
+        p.current_queue
+      );
+      p.current_queue = match p.prio {
+        Prio::Normal => Queue::Normal,
+        Prio::Low => Queue::Low,
+        Prio::High => Queue::High,
+        Prio::Max => Queue::Max,
+      };
+      p.current_queue
     };
 
-    match prio {
-      Prio::Normal => self.queue_normal.push_back(pid),
-      Prio::Low => self.queue_low.push_back(pid),
-      Prio::High => self.queue_high.push_back(pid),
+    let links = &mut self.processes;
+    match queue {
+      Queue::Normal => self.queue_normal.push_back(links, pid),
+      Queue::Low => self.queue_low.push_back(links, pid),
+      Queue::High => self.queue_high.push_back(links, pid),
+      _ => self.queue_max.push_back(links, pid),
     }
   }
 
-  /// Queue a process by its pid into either timed_wait or infinite_wait queue.
-  #[inline]
-  pub fn enqueue_wait(&mut self, infinite: bool, pid: Term) {
-    assert!(pid.is_local_pid());
+  /// Milliseconds until the next receive timeout expires, `None` if there are
+  /// no receive timers.
+  pub fn time_to_next_timer(&self) -> Option<u64> {
+    self.timed_wait.time_to_next()
+  }
 
-    if infinite {
-      self.infinite_wait.insert(pid, ());
+  /// Number of processes in the run queues.
+  pub fn queued_count(&self) -> usize {
+    self.queue_max.len()
+      + self.queue_high.len()
+      + self.queue_normal.len()
+      + self.queue_low.len()
+  }
+
+  /// Whether anything can still run on this scheduler: the running process,
+  /// queued processes or processes waiting for a receive timeout.
+  pub fn is_active(&self) -> bool {
+    self.current.is_some() || self.queued_count() > 0 || !self.timed_wait.is_empty()
+  }
+
+  /// Give away a queued process to another scheduler: the last one queued in
+  /// the highest priority queue. Returns the process and the time left on its
+  /// receive timer, which moves with the process, see `adopt_process`.
+  pub fn steal_process(&mut self) -> Option<(Box<Process>, Option<u64>)> {
+    let queue = if !self.queue_max.is_empty() {
+      &mut self.queue_max
+    } else if !self.queue_high.is_empty() {
+      &mut self.queue_high
+    } else if !self.queue_normal.is_empty() {
+      &mut self.queue_normal
     } else {
-      self.timed_wait.insert(pid, ());
+      &mut self.queue_low
+    };
+    let pid = queue.pop_back(&mut self.processes)?;
+    let mut proc = self.processes.remove(&pid).unwrap();
+    proc.current_queue = Queue::None;
+    let timer = self.timed_wait.cancel(pid);
+    Some((proc, timer))
+  }
+
+  /// Take over a process given away by another scheduler with `steal_process`
+  /// and queue it.
+  pub fn adopt_process(&mut self, proc: Box<Process>, recv_timer: Option<u64>) {
+    if let Some(timeout_ms) = recv_timer {
+      self.timed_wait.add(proc.pid, timeout_ms);
     }
+    self.add_process(proc);
   }
 
   #[inline]
@@ -169,131 +245,244 @@ impl Scheduler {
     }
   }
 
-  /// Get another process from the run queue for this scheduler.
-  /// Returns: `Option(pid)`
-  pub fn next_process(&mut self, proc_reg: &mut ProcessRegistry) -> Option<Term> {
-    if let Some(prev_pid) = self.current {
-      let hint = self.next_process_finalize_previous(proc_reg, prev_pid);
-      if hint == ScheduleHint::ContinueSameProcess {
-        // do not change self.current and just do the same process again
-        return self.current;
-      }
-    }
-
-    // Do necessities before taking another process
-    self.next_process_duties();
-
-    // Now try and find another process to run
-    loop {
-      // See if any are waiting in realtime (high) priority queue
-      if let Some(next_pid) = self.next_process_pick_from_the_queues() {
-        self.current = Some(next_pid);
-        break;
-      }
+  /// Take the next process to run out of the run queues, it becomes the
+  /// current process. Returns `None` if no process can run right now.
+  pub fn take_next_process(&mut self) -> Option<Box<Process>> {
+    if !self.timed_wait.is_empty() {
+      self.wake_expired_timers();
     }
-
-    Self::log_next_process(self.current);
-    self.current
+    let next = self.next_process_pick_from_the_queues();
+    Self::log_next_process(next);
+    let pid = next?;
+    let mut proc = self.processes.remove(&pid).unwrap();
+    proc.current_queue = Queue::None;
+    self.current = Some(pid);
+    Some(proc)
   }
 
   /// Look through the queues and find some queue with highest priority where
   /// a process is waiting to be selected.
   /// Advantage counter allows running lower queues even if a higher is running.
   fn next_process_pick_from_the_queues(&mut self) -> Option<Term> {
-    if !self.queue_high.is_empty() {
-      return self.queue_high.pop_front();
+    let queue = if !self.queue_max.is_empty() {
+      &mut self.queue_max
+    } else if !self.queue_high.is_empty() {
+      &mut self.queue_high
     } else if self.advantage_count < NORMAL_ADVANTAGE {
       if !self.queue_normal.is_empty() {
-        return self.queue_normal.pop_front();
+        &mut self.queue_normal
       } else if !self.queue_low.is_empty() {
-        return self.queue_low.pop_front();
+        &mut self.queue_low
+      } else {
+        self.advantage_count += 1;
+        return None;
       }
-      self.advantage_count += 1;
+    } else if !self.queue_low.is_empty() {
+      &mut self.queue_low
+    } else if !self.queue_normal.is_empty() {
+      &mut self.queue_normal
     } else {
-      if !self.queue_low.is_empty() {
-        return self.queue_low.pop_front();
-      } else if !self.queue_normal.is_empty() {
-        return self.queue_normal.pop_front();
-      }
       self.advantage_count = 0;
+      return None;
     };
-    None
+    queue.pop_front(&mut self.processes)
   }
 
-  /// When time has come to select next running process, first we take a look
-  /// at the previous process, what happened to it.
-  #[inline]
-  fn next_process_finalize_previous(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    curr_pid: Term,
-  ) -> ScheduleHint {
-    // Extract the last running process from the process registry
-    let curr_ptr = proc_reg.unsafe_lookup_pid_mut(curr_pid);
-    assert!(!curr_ptr.is_null());
-
-    // Unspeakable horrors are happening as we speak: (bypassing borrow checker)
-    let curr_proc = unsafe { &mut (*curr_ptr) };
-
+  /// Put back the current process after its time slice. A process which has
+  /// entered a receive goes to a wait set, unless a message or another signal
+  /// has arrived, otherwise it is queued.
+  pub fn check_in(&mut self, mut proc: Box<Process>) {
+    let pid = proc.pid;
     debug_assert_eq!(
-      curr_proc.current_queue,
+      proc.current_queue,
       Queue::None,
-      "Finalizing previous process which is not dequeued, now in {:?}",
-      curr_proc.current_queue
+      "Checking in a process which is not dequeued, now in {:?}",
+      proc.current_queue
     );
+    self.current = None;
+    match proc.recv_timer {
+      ReceiveTimer::Pending(timeout_ms, timeout_ip) => {
+        self.timed_wait.add(pid, timeout_ms);
+        proc.recv_timer = ReceiveTimer::Active(timeout_ip);
+      }
+      ReceiveTimer::None => {
+        self.timed_wait.cancel(pid);
+      }
+      _ => {}
+    }
+
+    // A signal pushed after this check will find the process in a wait set,
+    // see `VM::wake_process`. Messages already seen by this receive do not
+    // count.
+    let waits = match proc.timeslice_result {
+      SliceResult::InfiniteWait | SliceResult::TimedWait => {
+        !proc.mailbox.have_unread_messages() && proc.shared.signals.is_empty()
+      }
+      _ => false,
+    };
+    if !waits {
+      self.processes.insert(pid, proc);
+      self.enqueue(pid);
+    } else if proc.timeslice_result == SliceResult::InfiniteWait {
+      proc.current_queue = Queue::InfiniteWait;
+      self.processes.insert(pid, proc);
+      self.infinite_wait.insert(pid, ());
+    } else {
+      debug_assert!(
+        self.timed_wait.time_left(pid).is_some(),
+        "Timed wait without a timer"
+      );
+      proc.current_queue = Queue::TimedWait;
+      self.processes.insert(pid, proc);
+    }
+  }
 
-    match curr_proc.timeslice_result {
-      SliceResult::Yield => {
-        self.enqueue(proc_reg, curr_pid);
-        self.current = None
+  /// Receive timeouts which have expired: the waiting processes are woken up
+  /// and continue at their `timeout` instruction. A process which is not
+  /// waiting (was woken by a message) will see the timeout on its next
+  /// `wait_timeout`.
+  fn wake_expired_timers(&mut self) {
+    for pid in self.timed_wait.advance() {
+      let p = match self.processes.get_mut(&pid) {
+        Some(p) => p,
+        None => continue,
+      };
+      let timeout_ip = match p.recv_timer {
+        ReceiveTimer::Active(ip) => ip,
+        _ => continue,
+      };
+      p.recv_timer = ReceiveTimer::Expired;
+      if p.current_queue == Queue::TimedWait {
+        p.context.ip = timeout_ip;
+        p.current_queue = Queue::None;
+        // Not a wait anymore, the process runs to its `timeout` instruction
+        p.timeslice_result = SliceResult::None;
+        self.enqueue(pid);
       }
+    }
+  }
+
+  /// A signal has arrived for the process, wake it up if it is in one of the
+  /// wait sets. Returns true if the process was queued.
+  pub fn wake_process(&mut self, pid: Term) -> bool {
+    let p = match self.processes.get_mut(&pid) {
+      Some(p) => p,
+      // Running, or moved to another scheduler while queued
+      None => return false,
+    };
+    match p.current_queue {
+      Queue::InfiniteWait => {
+        self.infinite_wait.remove(&pid);
+      }
+      // The timer keeps running until the receive is finished
+      Queue::TimedWait => {}
+      _ => return false,
+    }
+    p.current_queue = Queue::None;
+    self.enqueue(pid);
+    true
+  }
+
+  /// Forget the current process which has terminated.
+  fn remove_current(&mut self, pid: Term) {
+    self.timed_wait.cancel(pid);
+    self.infinite_wait.remove(&pid);
+    self.current = None;
+  }
+}
 
-      SliceResult::None => {
-        self.enqueue(proc_reg, curr_pid);
-        self.current = None
+/// Exit reason as seen by the linked and monitoring processes. Like in OTP, an uncaught
+/// error or throw also carries the stacktrace (only known when given to `raise/3`,
+/// otherwise empty).
+fn make_exit_reason(
+  e: (ExceptionType, Term),
+  stacktrace: Term,
+  hp: &mut dyn THeap,
+) -> RtResult<Term> {
+  match e.0 {
+    ExceptionType::Exit => Ok(e.1),
+    ExceptionType::Throw => {
+      let nocatch = tuple2(hp, gen_atoms::NOCATCH, e.1)?;
+      tuple2(hp, nocatch, stacktrace)
+    }
+    ExceptionType::Error | ExceptionType::Panic => tuple2(hp, e.1, stacktrace),
+  }
+}
+
+impl VM {
+  /// Get another process to run on the scheduler `index`. Signals which have
+  /// arrived are handled first, a process woken up only by signals which are
+  /// not messages goes back to wait.
+  /// Returns: `None` if no process can run right now.
+  pub fn next_process(&self, index: usize) -> Option<Box<Process>> {
+    self.fire_timers();
+    loop {
+      let mut proc = self.schedulers[index].lock().unwrap().take_next_process()?;
+      // Not under the scheduler lock, signals may go to this scheduler
+      if proc.handle_signals(self).is_err() {
+        let reason = proc.pending_exit.take().unwrap();
+        self.terminate_process(index, proc, (ExceptionType::Exit, reason.get()));
+        continue;
+      }
+      let waits = matches!(
+        proc.timeslice_result,
+        SliceResult::InfiniteWait | SliceResult::TimedWait
+      );
+      if waits && !proc.mailbox.have_unread_messages() {
+        self.schedulers[index].lock().unwrap().check_in(proc);
+        continue;
       }
+      return Some(proc);
+    }
+  }
+
+  /// Done with a time slice: put the process back to the scheduler `index`,
+  /// or terminate it. Returns the process if it has caught an exception and
+  /// continues running.
+  pub fn finalize_slice(
+    &self,
+    index: usize,
+    mut proc: Box<Process>,
+  ) -> Option<Box<Process>> {
+    // An exit signal has arrived during the timeslice
+    if let Some(reason) = proc.pending_exit.take() {
+      self.terminate_process(index, proc, (ExceptionType::Exit, reason.get()));
+      return None;
+    }
 
+    match proc.timeslice_result {
       SliceResult::Finished => {
         // Scheduler will terminate the process with EXIT:NORMAL
         let err = (ExceptionType::Exit, gen_atoms::NORMAL);
-        self.terminate_process(proc_reg, curr_pid, err)
-      }
-
-      SliceResult::Exception => {
-        return self.handle_process_exception(proc_reg, curr_ptr, curr_pid);
+        self.terminate_process(index, proc, err)
       }
-
-      SliceResult::InfiniteWait => {
-        // Check if there is anything that should wake it up right now, like
-        // an incoming message or another signal?
-        // TODO: Respect already viewed messages in the mailbox
-        if !curr_proc.mailbox.have_unread_messages() {
-          self.enqueue_wait(true, curr_pid);
-        }
+      SliceResult::Exception => return self.handle_process_exception(index, proc),
+      SliceResult::Killed => {
+        let err = proc.error.unwrap();
+        self.terminate_process(index, proc, err);
       }
+      SliceResult::None
+      | SliceResult::Yield
+      | SliceResult::InfiniteWait
+      | SliceResult::TimedWait => self.schedulers[index].lock().unwrap().check_in(proc),
     }
-    ScheduleHint::TakeAnotherProcess
+    None
   }
 
   /// If exception happened, check whether a process is catching anything at
   /// this moment, otherwise proceed to terminate.
   fn handle_process_exception(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    proc_p: *mut Process,
-    proc_pid: Term,
-  ) -> ScheduleHint {
-    // Bypassing the borrow checker again
-    let proc = unsafe { &mut (*proc_p) };
-
+    &self,
+    index: usize,
+    mut proc: Box<Process>,
+  ) -> Option<Box<Process>> {
     assert!(proc.is_failed());
     let p_error = proc.error.unwrap();
 
     if proc.num_catches <= 0 {
       // time to terminate, no catches
-      self.terminate_process(proc_reg, proc_pid, p_error);
-      self.current = None;
-      return ScheduleHint::TakeAnotherProcess;
+      self.terminate_process(index, proc, p_error);
+      return None;
     }
 
     println!("Catching {}:{}", p_error.0, p_error.1);
@@ -306,56 +495,41 @@ impl Scheduler {
         proc.context.set_x(0, Term::non_value());
         proc.context.set_x(1, p_error.0.to_atom());
         proc.context.set_x(2, p_error.1);
-        proc.context.set_x(3, Term::nil()); // stacktrace object goes here
+        let stacktrace = core::mem::replace(&mut proc.stacktrace, Term::nil());
+        proc.context.set_x(3, stacktrace);
         proc.context.jump_ptr(next_catch.loc);
         proc.context.clear_cp();
         proc.get_heap_mut().drop_stack_words(next_catch.stack_drop);
 
         // TODO: Clear save mark on recv in process.mailbox
-        return ScheduleHint::ContinueSameProcess;
+        Some(proc)
       }
 
       None => {
         println!("Catch not found, terminating...");
-        if proc.process_flags.get(process_flags::TRAP_EXIT) {
-          panic!("todo: on terminate implement trap_exit");
-        }
-        self.terminate_process(proc_reg, proc_pid, p_error);
-        self.current = None;
+        self.terminate_process(index, proc, p_error);
+        None
       }
     }
-    ScheduleHint::TakeAnotherProcess
-  }
-
-  /// Things to do before scheduling another process for execution.
-  #[inline]
-  fn next_process_duties(&self) {
-    // TODO: monotonic clock
-    // TODO: wait lists
-    // TODO: network checks
   }
 
-  /// Assuming that the error was not caught, begin process termination routine.
+  /// Assuming that the error was not caught, begin process termination
+  /// routine for the current process of the scheduler `index`.
   pub fn terminate_process(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    pid: Term,
+    &self,
+    index: usize,
+    mut proc: Box<Process>,
     e: (ExceptionType, Term),
   ) {
-    // assert that process is not in any queue
-    {
-      let p = proc_reg.lookup_pid_mut(pid).unwrap();
-      assert_eq!(p.current_queue, Queue::None);
-    }
+    let pid = proc.pid;
+    assert_eq!(proc.current_queue, Queue::None);
+    proc.close_signal_queue(self);
+    self.processes.remove(pid);
 
     // root process exits with halt()
     // assert!(p.get_registered_name() != atom::INIT);
 
     // TODO: ets tables
-    // TODO: notify monitors
-    // TODO: cancel known timers who target this process
-    // TODO: notify links
-    // TODO: unregister name if registered
     // TODO: if pending timers - become zombie and sit in pending timers queue
     println!(
       "{}Terminating pid {} reason={}:{}",
@@ -365,33 +539,69 @@ impl Scheduler {
       e.1 //, p.runtime_ctx.regs[0]
     );
 
-    self.timed_wait.remove(&pid);
-    self.infinite_wait.remove(&pid);
-    assert!(!self.queue_normal.contains(&pid));
-    assert!(!self.queue_low.contains(&pid));
-    assert!(!self.queue_high.contains(&pid));
-    proc_reg.remove(pid);
-  }
+    // Monitors created by this process are no longer needed, the target may
+    // have terminated too
+    for (ref_id, target) in core::mem::take(&mut proc.monitors) {
+      let _ = self.send_signal(target.pid, Signal::Demonitor(ref_id));
+    }
 
-  /// Called by `Process` when a new message is received. Checks whether the
-  /// process was placed in one of waiting sets and wakes it up.
-  #[inline]
-  pub fn notify_new_incoming_message(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    proc: &mut Process,
-  ) {
-    // Remove from whatever wait set
-    match proc.current_queue {
-      Queue::InfiniteWait => {
-        self.infinite_wait.remove(&proc.pid);
-        self.enqueue_opt(proc_reg, proc.pid, true);
+    let links = core::mem::take(&mut proc.links);
+    let monitored_by = core::mem::take(&mut proc.monitored_by);
+    if !links.is_empty() || !monitored_by.is_empty() {
+      // The reason refers to the process heap, every signal gets a copy
+      let tuple2_size = boxed::Tuple::storage_size(2);
+      let mut reason_heap = Heap::new_fragment(tuple2_size + tuple2_size);
+      let reason = make_exit_reason(e, proc.stacktrace, &mut reason_heap)
+        .expect("Exit reason must fit its heap fragment");
+      for linked_pid in links {
+        let signal = OwnedTerm::new(reason).map(|r| Signal::Exit {
+          from: pid,
+          reason: r,
+          from_link: true,
+        });
+        match signal {
+          // A linked process which has terminated has no link to us
+          Ok(s) => {
+            let _ = self.send_signal(linked_pid, s);
+          }
+          Err(err) => error_report::lost_message("Exit signal", linked_pid, &err),
+        }
       }
-      Queue::TimedWait => {
-        self.timed_wait.remove(&proc.pid);
-        self.enqueue_opt(proc_reg, proc.pid, true);
+      for (ref_id, mon) in monitored_by {
+        let tag = mon.tag.get();
+        match monitor::make_down(tag, ref_id, pid, mon.name, reason) {
+          Ok(down) => {
+            let _ = self.send_signal(mon.watcher, Signal::Down(ref_id, down));
+          }
+          Err(err) => error_report::lost_message("'DOWN' message", mon.watcher, &err),
+        }
       }
-      _other => {}
+    }
+
+    self.timers.lock().unwrap().cancel_for_pid(pid);
+    self.schedulers[index].lock().unwrap().remove_current(pid);
+  }
+
+  /// Send the messages of the expired `erlang:send_after` and
+  /// `erlang:start_timer` timers. If the destination process does not exist
+  /// or the name is not registered, the message is dropped.
+  fn fire_timers(&self) {
+    // Locked until the messages are sent, so that a message is never seen
+    // neither in the timers nor in a process, see `tick_scheduler`
+    let mut timers = self.timers.lock().unwrap();
+    if timers.is_empty() {
+      return;
+    }
+    for (dest, message) in timers.take_expired() {
+      let dest_pid = if dest.is_atom() {
+        match self.processes.find_registered(dest) {
+          Some(pid) => pid,
+          None => continue,
+        }
+      } else {
+        dest
+      };
+      let _ = self.send_signal(dest_pid, Signal::Message(message));
     }
   }
 }
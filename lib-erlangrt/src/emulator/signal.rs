@@ -0,0 +1,164 @@
+//! Signals between processes: messages, exit signals, links and monitors. A
+//! process which runs on another scheduler thread can not be touched, so every
+//! signal to another process is appended to the receiver's signal queue. The
+//! receiver handles its signals in the order of arrival when it is scheduled
+//! in and before it looks at its messages, see `Process::handle_signals`.
+//!
+//! A terminating process closes its queue, then the sender gets the signal
+//! back and handles it like sent to a process which does not exist.
+use crate::{
+  defs::Word,
+  emulator::{heap::owned_term::OwnedTerm, monitor::Monitor},
+  term::Term,
+};
+use std::sync::Mutex;
+
+pub enum Signal {
+  /// A message with its own copy of the data
+  Message(OwnedTerm),
+  /// Exit signal from a terminated linked process (`from_link`) or from
+  /// `exit/2`
+  Exit {
+    from: Term,
+    reason: OwnedTerm,
+    from_link: bool,
+  },
+  /// The sender has linked to the receiver
+  Link(Term),
+  /// The sender has removed its link to the receiver
+  Unlink(Term),
+  /// The sender monitors the receiver, by the monitor reference id
+  Monitor(Word, Monitor),
+  /// The sender has removed its monitor
+  Demonitor(Word),
+  /// A monitored process has terminated, the message is
+  /// `{'DOWN', Ref, process, Object, Reason}`
+  Down(Word, OwnedTerm),
+  /// `process_info` called by another process: the receiver answers with
+  /// `InfoReply`
+  InfoRequest { from: Term, query: InfoQuery },
+  /// Result of `process_info`, `None` for a bad item
+  InfoReply(Option<OwnedTerm>),
+}
+
+/// Items asked for by `process_info`
+pub enum InfoQuery {
+  /// The items of `process_info/1`
+  Default,
+  /// One item, the result is `{Item, Value}`
+  Item(Term),
+  /// A list of `{Item, Value}`
+  Items(Vec<Term>),
+}
+
+struct QueueState {
+  signals: Vec<Signal>,
+  /// Set when the process terminates, no more signals are accepted
+  closed: bool,
+}
+
+pub struct SignalQueue {
+  state: Mutex<QueueState>,
+}
+
+impl SignalQueue {
+  pub fn new() -> Self {
+    Self {
+      state: Mutex::new(QueueState {
+        signals: Vec::new(),
+        closed: false,
+      }),
+    }
+  }
+
+  /// Append a signal. Returns the signal back if the receiver has terminated.
+  #[allow(clippy::result_large_err)]
+  pub fn push(&self, signal: Signal) -> Result<(), Signal> {
+    let mut state = self.state.lock().unwrap();
+    if state.closed {
+      return Err(signal);
+    }
+    state.signals.push(signal);
+    Ok(())
+  }
+
+  /// Take all signals in the order of arrival, called by the receiver.
+  pub fn take(&self) -> Vec<Signal> {
+    core::mem::take(&mut self.state.lock().unwrap().signals)
+  }
+
+  /// Stop accepting signals, returns the signals which have arrived.
+  pub fn close(&self) -> Vec<Signal> {
+    let mut state = self.state.lock().unwrap();
+    state.closed = true;
+    core::mem::take(&mut state.signals)
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.state.lock().unwrap().signals.is_empty()
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::Heap,
+    term::{boxed, term_builder::tuple_builder::tuple2},
+  };
+
+  fn small(n: isize) -> Term {
+    Term::make_small_signed(n)
+  }
+
+  fn message(n: isize) -> Signal {
+    Signal::Message(OwnedTerm::new(small(n)).unwrap())
+  }
+
+  #[test]
+  fn test_signal_queue_order() {
+    let q = SignalQueue::new();
+    assert!(q.is_empty());
+    let mut hp = Heap::new_fragment(boxed::Tuple::storage_size(2));
+    let tuple = tuple2(&mut hp, small(2), small(3)).unwrap();
+    assert!(q.push(message(1)).is_ok());
+    assert!(q.push(Signal::Link(small(7))).is_ok());
+    assert!(q.push(Signal::Message(OwnedTerm::new(tuple).unwrap())).is_ok());
+    assert!(!q.is_empty());
+
+    let signals = q.take();
+    assert!(q.is_empty());
+    assert_eq!(signals.len(), 3);
+    assert!(matches!(&signals[0], Signal::Message(m) if m.get() == small(1)));
+    assert!(matches!(&signals[1], Signal::Link(pid) if *pid == small(7)));
+    // The message is a copy, the sender's heap can go away
+    drop(hp);
+    match &signals[2] {
+      Signal::Message(m) => {
+        let t = m.get();
+        assert!(t.is_tuple() && t != tuple);
+        assert_eq!(unsafe { (*t.get_tuple_ptr()).get_element(1) }, small(3));
+      }
+      _ => panic!("Message expected"),
+    }
+  }
+
+  #[test]
+  fn test_signal_queue_close() {
+    let q = SignalQueue::new();
+    assert!(q.push(message(1)).is_ok());
+    let left = q.close();
+    assert_eq!(left.len(), 1);
+    // A terminated process gives the signal back to the sender
+    match q.push(message(2)) {
+      Err(Signal::Message(m)) => assert_eq!(m.get(), small(2)),
+      _ => panic!("Closed queue must not accept signals"),
+    }
+    assert!(q.is_empty());
+    assert!(q.take().is_empty());
+  }
+}
@@ -1,6 +1,21 @@
-use crate::emulator::{process_flags::ProcessFlags, scheduler::Prio};
+use crate::{
+  defs::Word,
+  emulator::{
+    process_flags::{MaxHeapSize, ProcessFlags},
+    scheduler::Prio,
+  },
+};
 
-#[allow(dead_code)]
+/// Default for `fullsweep_after` option, same as in Erlang/OTP.
+pub const DEFAULT_FULLSWEEP_AFTER: usize = 65535;
+
+/// Default for `min_heap_size` option in words, same as in Erlang/OTP.
+pub const DEFAULT_MIN_HEAP_SIZE: usize = 233;
+
+/// Default for `min_bin_vheap_size` option in words, same as in Erlang/OTP.
+pub const DEFAULT_MIN_BIN_VHEAP_SIZE: usize = 46422;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum MessageQueueLocation {
   OnHeap,
   OffHeap,
@@ -11,6 +26,20 @@ pub struct SpawnOptions {
   pub prio: Prio,
   // TODO: Use bit flags?
   pub process_flags: ProcessFlags,
+  /// Heap size limit, and what to do when it is exceeded.
+  pub max_heap_size: MaxHeapSize,
+  /// How many minor GCs can happen before a fullsweep is forced. Set to 0 to
+  /// disable the generational GC for this process.
+  pub fullsweep_after: usize,
+  /// Initial heap size in words, the heap will never shrink below this.
+  pub min_heap_size: usize,
+  /// Initial virtual binary heap size in words: how much off-heap binary data
+  /// the process may refer to before a GC is wanted.
+  pub min_bin_vheap_size: usize,
+  /// Link the new process to the parent, for `spawn_link`
+  pub link: bool,
+  /// Monitor reference id of the parent, for `spawn_monitor`
+  pub monitor: Option<Word>,
 }
 
 impl SpawnOptions {
@@ -19,6 +48,12 @@ impl SpawnOptions {
       msg_queue: MessageQueueLocation::OnHeap,
       prio: Prio::Normal,
       process_flags: ProcessFlags::default(),
+      max_heap_size: MaxHeapSize::default(),
+      fullsweep_after: DEFAULT_FULLSWEEP_AFTER,
+      min_heap_size: DEFAULT_MIN_HEAP_SIZE,
+      min_bin_vheap_size: DEFAULT_MIN_BIN_VHEAP_SIZE,
+      link: false,
+      monitor: None,
     }
   }
 }
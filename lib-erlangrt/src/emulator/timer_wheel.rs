@@ -0,0 +1,200 @@
+//! Timer wheel: timers are stored in slots by their deadline, one slot per
+//! millisecond tick of the monotonic clock. Advancing the wheel only visits
+//! the slots for the ticks which have passed. Timers further away than one
+//! wheel revolution stay in their slot until their deadline comes.
+use std::{collections::HashMap, hash::Hash, time::Instant};
+
+/// Number of slots, one wheel revolution is this many milliseconds.
+const WHEEL_SLOTS: usize = 1024;
+
+struct TimerEntry<K> {
+  key: K,
+  deadline: u64,
+}
+
+/// Timers keyed by `K`, at most one timer per key.
+pub struct TimerWheel<K>
+where
+  K: Copy + Eq + Hash,
+{
+  /// Beginning of the monotonic clock, ticks are milliseconds since then
+  start: Instant,
+  /// Last tick for which the expired timers were collected
+  current_tick: u64,
+  slots: Vec<Vec<TimerEntry<K>>>,
+  /// Deadline for each active timer. Cancelled timers are removed from here
+  /// and their entries in the slots are dropped lazily.
+  active: HashMap<K, u64>,
+}
+
+impl<K> TimerWheel<K>
+where
+  K: Copy + Eq + Hash,
+{
+  pub fn new() -> Self {
+    let mut slots = Vec::with_capacity(WHEEL_SLOTS);
+    slots.resize_with(WHEEL_SLOTS, Vec::new);
+    Self {
+      start: Instant::now(),
+      current_tick: 0,
+      slots,
+      active: HashMap::new(),
+    }
+  }
+
+  /// Monotonic clock: milliseconds since the wheel was created.
+  pub fn now(&self) -> u64 {
+    self.start.elapsed().as_millis() as u64
+  }
+
+  /// Start a timer which expires after `timeout_ms` milliseconds, an existing
+  /// timer for the same key is replaced.
+  pub fn add(&mut self, key: K, timeout_ms: u64) {
+    self.add_at(self.now(), key, timeout_ms)
+  }
+
+  fn add_at(&mut self, now: u64, key: K, timeout_ms: u64) {
+    // Never schedule into the slot which was already visited
+    let deadline = (now + timeout_ms).max(self.current_tick + 1);
+    self.active.insert(key, deadline);
+    self.slots[deadline as usize % WHEEL_SLOTS].push(TimerEntry { key, deadline });
+  }
+
+  /// Cancel the timer for the key, returns milliseconds which were left, or
+  /// `None` if there was no timer.
+  pub fn cancel(&mut self, key: K) -> Option<u64> {
+    let deadline = self.active.remove(&key)?;
+    Some(deadline.saturating_sub(self.now()))
+  }
+
+  /// Milliseconds left until the timer for the key expires.
+  pub fn time_left(&self, key: K) -> Option<u64> {
+    let deadline = self.active.get(&key)?;
+    Some(deadline.saturating_sub(self.now()))
+  }
+
+  /// Milliseconds until the earliest timer expires, `None` if there are no
+  /// timers.
+  pub fn time_to_next(&self) -> Option<u64> {
+    let deadline = self.active.values().min()?;
+    Some(deadline.saturating_sub(self.now()))
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.active.is_empty()
+  }
+
+  /// Advance the wheel to the current time, remove and return the keys of
+  /// the expired timers.
+  pub fn advance(&mut self) -> Vec<K> {
+    self.advance_to(self.now())
+  }
+
+  fn advance_to(&mut self, now: u64) -> Vec<K> {
+    let mut expired = Vec::new();
+    if now <= self.current_tick {
+      return expired;
+    }
+    // Visit each slot at most once, even if more than a revolution has passed
+    let steps = (now - self.current_tick).min(WHEEL_SLOTS as u64);
+    for step in 1..=steps {
+      let index = (self.current_tick + step) as usize % WHEEL_SLOTS;
+      let active = &mut self.active;
+      self.slots[index].retain(|entry| {
+        if active.get(&entry.key) != Some(&entry.deadline) {
+          return false; // cancelled or restarted
+        }
+        if entry.deadline > now {
+          return true;
+        }
+        active.remove(&entry.key);
+        expired.push(entry.key);
+        false
+      });
+    }
+    self.current_tick = now;
+    expired
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sorted(mut keys: Vec<u32>) -> Vec<u32> {
+    keys.sort_unstable();
+    keys
+  }
+
+  #[test]
+  fn test_timer_wheel_expiry() {
+    let mut tw = TimerWheel::<u32>::new();
+    tw.add_at(0, 1, 10);
+    tw.add_at(0, 2, 20);
+    tw.add_at(0, 3, 20);
+    assert!(tw.advance_to(9).is_empty());
+    assert_eq!(tw.advance_to(10), vec![1]);
+    // Several ticks passed at once
+    assert_eq!(sorted(tw.advance_to(25)), vec![2, 3]);
+    assert!(tw.is_empty());
+  }
+
+  #[test]
+  fn test_timer_wheel_zero_timeout() {
+    let mut tw = TimerWheel::<u32>::new();
+    tw.advance_to(5);
+    // The current tick was visited already, the timer goes to the next one
+    tw.add_at(5, 1, 0);
+    assert_eq!(tw.advance_to(6), vec![1]);
+  }
+
+  #[test]
+  fn test_timer_wheel_cancel_and_restart() {
+    let mut tw = TimerWheel::<u32>::new();
+    tw.add_at(0, 1, 10);
+    tw.add_at(0, 2, 10);
+    assert!(tw.cancel(1).is_some());
+    assert!(tw.cancel(1).is_none());
+    // Restarting moves the deadline, the old entry is ignored
+    tw.add_at(0, 2, 50);
+    assert!(tw.advance_to(10).is_empty());
+    assert!(tw.advance_to(49).is_empty());
+    assert_eq!(tw.advance_to(50), vec![2]);
+    assert!(tw.is_empty());
+  }
+
+  #[test]
+  fn test_timer_wheel_long_timeouts() {
+    let mut tw = TimerWheel::<u32>::new();
+    let revolution = WHEEL_SLOTS as u64;
+    tw.add_at(0, 1, revolution * 2 + 5);
+    tw.add_at(0, 2, 5);
+    // Same slot, but the long timer is not due yet
+    assert_eq!(tw.advance_to(5), vec![2]);
+    assert!(tw.advance_to(revolution + 5).is_empty());
+    assert!(tw.advance_to(revolution * 2 + 4).is_empty());
+    assert_eq!(tw.advance_to(revolution * 2 + 5), vec![1]);
+
+    // More than a revolution has passed since the last advance
+    tw.add_at(revolution * 2 + 5, 3, 100);
+    tw.add_at(revolution * 2 + 5, 4, 700);
+    let later = revolution * 5;
+    assert_eq!(sorted(tw.advance_to(later)), vec![3, 4]);
+  }
+
+  #[test]
+  fn test_timer_wheel_time_left() {
+    let mut tw = TimerWheel::<u32>::new();
+    assert_eq!(tw.time_to_next(), None);
+    tw.add(1, 100_000);
+    tw.add(2, 50_000);
+    let left = tw.time_left(2).unwrap();
+    assert!(left <= 50_000 && left > 40_000);
+    assert!(tw.time_to_next().unwrap() <= left);
+    assert_eq!(tw.time_left(3), None);
+  }
+}
@@ -1,92 +1,169 @@
 //! Implements virtual machine, as a collection of processes and their
 //! registrations, schedulers, ETS tables and atom table etc.
+pub mod scheduler_threads;
+pub mod waker;
 
 use crate::{
   command_line_args::ErlStartArgs,
-  defs::Word,
+  defs::SizeWords,
   emulator::{
-    code_srv::CodeServer, mfa::ModFunArgs, process::Process, process_flags,
-    process_registry::ProcessRegistry, scheduler::Scheduler, spawn_options::SpawnOptions,
+    code_srv::CodeServer,
+    erl_timers::ErlTimers,
+    error_report,
+    heap::{copy_term, verify, THeap, THeapOwner},
+    mfa::ModFunArgs,
+    process::Process,
+    process_flags,
+    process_registry::ProcessRegistry,
+    scheduler::Scheduler,
+    signal::Signal,
+    spawn_options::SpawnOptions,
+    vm::waker::VmWaker,
   },
-  fail::RtResult,
+  fail::{RtErr, RtResult},
   term::*,
 };
+use core::{
+  cell::Cell,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+thread_local! {
+  /// Index of the scheduler which the thread runs, see `tick_scheduler`
+  static CURRENT_SCHEDULER: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The earlier of two timeouts, `None` is no timeout.
+fn earliest<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(a.min(b)),
+    (a, b) => a.or(b),
+  }
+}
 
-mod binary_heap_owner;
-use binary_heap_owner::*;
+/// What a scheduler has done during a `VM::tick_scheduler` call.
+pub enum Tick {
+  /// Ran a time slice, there may be more work
+  Ran,
+  /// No process can run now, the scheduler can sleep until notified through
+  /// the waker or until the timeout (`None` waits forever)
+  Wait(Option<Duration>),
+  /// No process can ever run again
+  Finished,
+}
 
 /// VM environment, heaps, tables, processes all goes here.
 /// Atoms are a global API in `atom.rs`.
 /// Code server is a global API in `code_srv.rs`.
 pub struct VM {
   /// Pid counter increments every time a new process is spawned
-  pid_counter: Word,
+  pid_counter: AtomicUsize,
+  /// Reference counter increments every time a new reference is created
+  ref_counter: AtomicUsize,
 
   /// Contains all loaded modules and manages versions
   pub code_server: CodeServer,
 
-  pub scheduler: Scheduler,
+  /// One scheduler per scheduler thread (`+S`), each owns the processes in
+  /// its queues. Lock order: timers, then process registry, then schedulers
+  /// by index.
+  pub schedulers: Vec<Mutex<Scheduler>>,
+  /// Timers started by `erlang:send_after` and `erlang:start_timer`, fired by
+  /// any scheduler
+  pub timers: Mutex<ErlTimers>,
   pub processes: ProcessRegistry,
+  /// Wakes up the idle schedulers, handles are given to embedders
+  waker: Arc<VmWaker>,
+  /// Scheduler threads which wait for work
+  idle_schedulers: AtomicUsize,
+  /// Set when all scheduler threads must stop, with the exit status
+  exit_status: Mutex<Option<i32>>,
 
-  /// Binary heap is wrapped into binary heap owner which performs GC and other
-  /// maintenance tasks on the binary heap
-  pub binary_heap: BinaryHeapOwner,
+  /// Reductions spent by all processes in the finished timeslices
+  pub reductions_total: AtomicUsize,
+  /// Value of `reductions_total` at the last `statistics(reductions)` call
+  pub reductions_last_stat: AtomicUsize,
 }
 
+// Shared state is behind locks and atomics, and a process is touched only by
+// the thread which has taken it out of its scheduler
+unsafe impl Sync for VM {}
+
 impl VM {
   /// Create a VM, multiple VMs can be created but atom table and code server
   /// will be shared (global).
   pub fn new(args: &mut ErlStartArgs) -> VM {
+    if args.verify_heap {
+      verify::set_enabled(true);
+    }
+    let schedulers = (0..args.schedulers.max(1))
+      .map(|_| Mutex::new(Scheduler::new()))
+      .collect();
     VM {
       code_server: CodeServer::new(args),
-      pid_counter: 0,
-      scheduler: Scheduler::new(),
+      pid_counter: AtomicUsize::new(0),
+      ref_counter: AtomicUsize::new(0),
+      schedulers,
+      timers: Mutex::new(ErlTimers::new()),
       processes: ProcessRegistry::new(),
-      binary_heap: BinaryHeapOwner::new(),
+      waker: Arc::new(VmWaker::new()),
+      idle_schedulers: AtomicUsize::new(0),
+      exit_status: Mutex::new(None),
+      reductions_total: AtomicUsize::new(0),
+      reductions_last_stat: AtomicUsize::new(0),
     }
   }
 
-  /// Dirty trick to not have to dynamically borrow scheduler via
-  /// `RefCell<Box<>>` because schedulers live just as long as the VM itself.
+  /// A handle for other threads to wake up the idle VM or to post jobs to it.
+  /// While any handle is alive, the VM with no runnable processes and no
+  /// timers waits for it instead of stopping.
   #[allow(dead_code)]
-  pub fn get_scheduler_p(&self) -> *mut Scheduler {
-    let p = &self.scheduler as *const Scheduler;
-    p as *mut Scheduler
-  }
-
-  /// Dirty trick to not have to dynamically borrow code server via
-  /// `RefCell<Box<>>` because code server lives just as long as the VM itself.
-  pub fn get_code_server_p(&self) -> *mut CodeServer {
-    let p = &self.code_server as *const CodeServer;
-    p as *mut CodeServer
+  pub fn get_waker(&self) -> Arc<VmWaker> {
+    self.waker.clone()
   }
 
   /// Spawn a new process, create a new pid, register the process and jump to
   /// the MFA specified. Arguments are copies into the new process heap and
   /// stored into the registers.
   pub fn create_process(
-    &mut self,
+    &self,
     parent: Term,
     mfargs: &ModFunArgs,
     spawn_opts: &SpawnOptions,
   ) -> RtResult<Term> {
-    let pid_c = self.pid_counter;
-    self.pid_counter += 1;
+    let pid_c = self.pid_counter.fetch_add(1, Ordering::Relaxed);
 
     let pid = Term::make_local_pid(pid_c);
     let mfarity = mfargs.get_mfarity()?;
-    let cs = self.get_code_server_p();
-    let mut p0 = Process::new(pid, parent, &mfarity, spawn_opts, unsafe { &mut (*cs) })?;
+    let mut p0 = Process::new(pid, parent, &mfarity, spawn_opts, &self.code_server)?;
+
+    // Make room for the args, the new process has no live data yet
+    let mut args_size = SizeWords::zero();
+    mfargs.for_each_arg(|arg| {
+      args_size = args_size + copy_term::size_of_shared(arg)?;
+      Ok(())
+    })?;
+    p0.ensure_heap(args_size)?;
 
     // Error may happen here due to arg term copy error
     p0.set_spawn_args(mfargs)?;
 
-    self.register_new_process(pid, p0);
+    self.register_new_process(p0);
     Ok(pid)
   }
 
+  /// Create a new local reference on the heap, unique for this VM.
+  pub fn make_ref(&self, hp: &mut dyn THeap) -> RtResult<Term> {
+    let id = self.ref_counter.fetch_add(1, Ordering::Relaxed) + 1;
+    boxed::LocalRef::create_into(hp, id)
+  }
+
   pub fn spawn_system_process(
-    &mut self,
+    &self,
     parent: Term,
     mfargs: &ModFunArgs,
     mut spawn_opts: SpawnOptions,
@@ -98,17 +175,150 @@ impl VM {
     self.create_process(parent, mfargs, &spawn_opts)
   }
 
-  pub fn register_new_process(&mut self, pid: Term, mut proc: Process) {
-    proc.owned_by_scheduler = (&mut self.scheduler) as *mut Scheduler;
-    self.processes.insert(pid, proc);
-    self.scheduler.enqueue(&mut self.processes, pid);
+  /// A new process goes to the run queue of the running scheduler, like the
+  /// processes spawned by its parent. Idle schedulers will steal it if this
+  /// one is busy.
+  pub fn register_new_process(&self, proc: Process) {
+    let index = CURRENT_SCHEDULER.with(|c| c.get());
+    proc.shared.scheduler.store(index, Ordering::SeqCst);
+    self.processes.insert(proc.shared.clone());
+    self.schedulers[index].lock().unwrap().add_process(Box::new(proc));
+  }
+
+  /// Push a signal to the process `to` and wake it up. Returns the signal
+  /// back if the process does not exist or is terminating.
+  #[allow(clippy::result_large_err)]
+  pub fn send_signal(&self, to: Term, signal: Signal) -> Result<(), Signal> {
+    let shared = match self.processes.lookup(to) {
+      Some(shared) => shared,
+      None => return Err(signal),
+    };
+    shared.signals.push(signal)?;
+    // A process which goes to wait after this sees the signal, see
+    // `Scheduler::check_in`
+    let index = shared.scheduler.load(Ordering::SeqCst);
+    let woken = self.schedulers[index].lock().unwrap().wake_process(to);
+    if woken && self.idle_schedulers.load(Ordering::SeqCst) > 0 {
+      self.waker.notify();
+    }
+    Ok(())
+  }
+
+  /// Run one time slice on every scheduler from the calling thread, call this
+  /// repeatedly to run forever, or use `run` to have a thread per scheduler.
+  /// When no process can run, blocks until the next timer or until woken up
+  /// by an embedder.
+  /// Returns: `false` when all processes have finished.
+  #[allow(dead_code)]
+  pub fn tick(&self) -> RtResult<bool> {
+    let seen = self.waker.generation();
+    let mut ran = false;
+    let mut timeout: Option<Duration> = None;
+    for index in 0..self.schedulers.len() {
+      match self.tick_scheduler(index)? {
+        Tick::Ran => ran = true,
+        Tick::Wait(t) => timeout = earliest(timeout, t),
+        Tick::Finished => return Ok(false),
+      }
+    }
+    if !ran {
+      self.waker.wait(seen, timeout);
+    }
+    Ok(true)
+  }
+
+  /// Run one time slice on the scheduler `index`. Time slice ends when the
+  /// current process yields or when reduction count reaches zero. A scheduler
+  /// with nothing in its queues steals a process from another scheduler.
+  /// Fails with `RtErr::Deadlock` if processes are left waiting forever.
+  pub fn tick_scheduler(&self, index: usize) -> RtResult<Tick> {
+    CURRENT_SCHEDULER.with(|c| c.set(index));
+    for job in self.waker.take_jobs() {
+      job(self);
+    }
+    let ran = self.dispatch(index)?;
+    error_report::flush(self);
+    if ran {
+      self.wake_idle_schedulers();
+      return Ok(Tick::Ran);
+    }
+
+    let own_timer = self.schedulers[index].lock().unwrap().time_to_next_timer();
+    let erl_timer = self.timers.lock().unwrap().time_to_next();
+    if let Some(ms) = earliest(own_timer, erl_timer) {
+      return Ok(Tick::Wait(Some(Duration::from_millis(ms))));
+    }
+    if Arc::strong_count(&self.waker) > 1 {
+      return Ok(Tick::Wait(None));
+    }
+    // Other schedulers will wake us up if they get more work than they can
+    // run. Look at all of them at once, then no signal can be on its way from
+    // a running process, a timer or an error report.
+    let timers = self.timers.lock().unwrap();
+    let schedulers: Vec<_> = self.schedulers.iter().map(|s| s.lock().unwrap()).collect();
+    if !timers.is_empty()
+      || error_report::is_pending()
+      || schedulers.iter().any(|s| s.is_active())
+    {
+      return Ok(Tick::Wait(None));
+    }
+    // No timers and nobody can wake us up
+    match self.processes.count() {
+      0 => Ok(Tick::Finished),
+      count => Err(RtErr::Deadlock(count)),
+    }
+  }
+
+  /// Move a queued process from the busiest scheduler to the idle scheduler
+  /// `index`. A process stays on its new scheduler until stolen again, its
+  /// receive timer moves with it. Waiting processes are never moved.
+  /// Returns: `false` if all other run queues are empty.
+  pub fn steal_process(&self, index: usize) -> bool {
+    let victim = match (0..self.schedulers.len())
+      .filter(|&i| i != index)
+      .max_by_key(|&i| self.schedulers[i].lock().unwrap().queued_count())
+    {
+      Some(v) => v,
+      None => return false,
+    };
+    // Both are locked in the lock order, the process is never seen outside of
+    // a scheduler, see `tick_scheduler`
+    let (first, second) = (index.min(victim), index.max(victim));
+    let mut first_sched = self.schedulers[first].lock().unwrap();
+    let mut second_sched = self.schedulers[second].lock().unwrap();
+    let (own, other) = if index == first {
+      (&mut *first_sched, &mut *second_sched)
+    } else {
+      (&mut *second_sched, &mut *first_sched)
+    };
+    match other.steal_process() {
+      Some((proc, recv_timer)) => {
+        proc.shared.scheduler.store(index, Ordering::SeqCst);
+        own.adopt_process(proc, recv_timer);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// If processes are waiting in the run queues, let the idle schedulers
+  /// steal them.
+  fn wake_idle_schedulers(&self) {
+    if self.idle_schedulers.load(Ordering::SeqCst) > 0
+      && self
+        .schedulers
+        .iter()
+        .any(|s| s.lock().unwrap().queued_count() > 0)
+    {
+      self.waker.notify();
+    }
   }
 
-  /// Run the VM loop (one time slice), call this repeatedly to run forever.
-  /// Time slice ends when a current process yields or when reduction count
-  /// reaches zero.
-  #[inline]
-  pub fn tick(&mut self) -> RtResult<bool> {
-    self.dispatch()
+  /// Make all scheduler threads stop, the first status given is kept.
+  /// Returns: the exit status.
+  fn stop(&self, status: i32) -> i32 {
+    let status = *self.exit_status.lock().unwrap().get_or_insert(status);
+    self.waker.notify();
+    status
   }
 }
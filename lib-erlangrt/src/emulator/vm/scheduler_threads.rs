@@ -0,0 +1,151 @@
+//! Runs the VM on one OS thread per scheduler (`+S`). Every thread runs the
+//! processes from its own scheduler's run queues, and steals queued processes
+//! from the other schedulers when it has nothing to run. The threads run their
+//! time slices in parallel, see `scheduler.rs` for what is shared.
+use crate::{
+  emulator::vm::{Tick, VM},
+  fail::{RtErr, RtResult},
+};
+use core::sync::atomic::Ordering;
+use std::thread;
+
+/// Stops the other scheduler threads when this one panics.
+struct StopOnPanic<'a> {
+  vm: &'a VM,
+}
+
+impl Drop for StopOnPanic<'_> {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      self.vm.stop(1);
+    }
+  }
+}
+
+impl VM {
+  /// Run the VM on a thread per scheduler until all processes have finished,
+  /// or until `erlang:halt` is called.
+  /// Returns: the exit status, or the error which has stopped the VM, such as
+  /// `RtErr::Deadlock`.
+  pub fn run(self) -> RtResult<i32> {
+    let count = self.schedulers.len();
+    let vm = &self;
+    let results: Vec<RtResult<i32>> = thread::scope(|s| {
+      let threads: Vec<_> = (0..count)
+        .map(|index| {
+          thread::Builder::new()
+            .name(format!("scheduler {}", index + 1))
+            .spawn_scoped(s, move || scheduler_loop(vm, index))
+            .unwrap()
+        })
+        .collect();
+      threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+    results.into_iter().collect::<RtResult<Vec<i32>>>().map(|s| s[0])
+  }
+}
+
+/// Run time slices on the scheduler `index`, sleep when there is nothing to
+/// run. Returns: the exit status given to all threads.
+fn scheduler_loop(vm: &VM, index: usize) -> RtResult<i32> {
+  let _stop_on_panic = StopOnPanic { vm };
+  loop {
+    // Read the generation before looking for work, work which comes after it
+    // will wake us up
+    let seen = vm.waker.generation();
+    if let Some(status) = *vm.exit_status.lock().unwrap() {
+      return Ok(status);
+    }
+    let timeout = match vm.tick_scheduler(index) {
+      Ok(Tick::Ran) => continue,
+      Ok(Tick::Wait(timeout)) => timeout,
+      Ok(Tick::Finished) => return Ok(vm.stop(0)),
+      Err(RtErr::Halt(status)) => return Ok(vm.stop(status)),
+      Err(e) => {
+        vm.stop(1);
+        return Err(e);
+      }
+    };
+    vm.idle_schedulers.fetch_add(1, Ordering::SeqCst);
+    // A process woken up before we were counted as idle did not notify
+    if vm.schedulers[index].lock().unwrap().queued_count() == 0 {
+      vm.waker.wait(seen, timeout);
+    }
+    vm.idle_schedulers.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    beam::gen_op,
+    command_line_args::ErlStartArgs,
+    emulator::{
+      code::opcode,
+      funarity::FunArity,
+      gen_atoms,
+      mfa::ModFunArgs,
+      module::{Module, VersionedModuleName},
+      spawn_options::SpawnOptions,
+    },
+    term::Term,
+  };
+  use core::sync::atomic::AtomicBool;
+  use std::sync::Arc;
+
+  fn make_vm(schedulers: usize) -> VM {
+    let mut args = ErlStartArgs::new(&[]);
+    args.schedulers = schedulers;
+    VM::new(&mut args)
+  }
+
+  #[test]
+  fn test_run_without_processes() {
+    let vm = make_vm(4);
+    assert_eq!(vm.schedulers.len(), 4);
+    assert_eq!(vm.run().unwrap(), 0);
+  }
+
+  #[test]
+  fn test_run_embedder_job() {
+    let vm = make_vm(3);
+    let ran = Arc::new(AtomicBool::new(false));
+    let job_ran = ran.clone();
+    // The last handle goes away with the job, then the VM has nothing to wait
+    // for and stops
+    let handle = vm.get_waker();
+    vm.get_waker().post(Box::new(move |_vm: &VM| {
+      job_ran.store(true, Ordering::Relaxed);
+      drop(handle);
+    }));
+    assert_eq!(vm.run().unwrap(), 0);
+    assert!(ran.load(Ordering::Relaxed));
+  }
+
+  #[test]
+  fn test_run_processes_on_all_schedulers() {
+    let vm = make_vm(4);
+    // A fake module with a function which returns its argument, it does not
+    // start at the first word like in a real module
+    let mut m = Module::new(&VersionedModuleName::new(gen_atoms::ERLANG, 1));
+    let ret = opcode::to_memory_word(gen_op::OPCODE_RETURN);
+    m.code = vec![ret, ret];
+    m.funs.insert(FunArity::new(gen_atoms::APPLY, 1), 1);
+    vm.code_server.module_loaded(Box::new(m));
+
+    // All start on the first scheduler, the other threads steal them
+    let args = [Term::make_small_unsigned(1)];
+    let mfargs = ModFunArgs::with_args_slice(gen_atoms::ERLANG, gen_atoms::APPLY, &args);
+    for _ in 0..200 {
+      vm.create_process(Term::nil(), &mfargs, &SpawnOptions::default())
+        .unwrap();
+    }
+    assert_eq!(vm.processes.count(), 200);
+    // Finishes only when no process is left
+    assert_eq!(vm.run().unwrap(), 0);
+  }
+}
@@ -0,0 +1,81 @@
+//! Wakes up an idle VM from other threads. When no process can run, the
+//! scheduler threads sleep until the next timer deadline or until notified by
+//! an embedder, for example after an IO has completed or to inject a message,
+//! or by another scheduler which has work to share.
+use crate::emulator::vm::VM;
+use std::{
+  sync::{Condvar, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Work posted by an embedder, runs on a scheduler thread with access to the VM
+/// (e.g. to deliver a message to a process).
+pub type VmJob = Box<dyn FnOnce(&VM) + Send>;
+
+struct WakerState {
+  /// Incremented by every notification, the waiting threads wake up when it
+  /// changes
+  generation: u64,
+  jobs: Vec<VmJob>,
+}
+
+pub struct VmWaker {
+  state: Mutex<WakerState>,
+  cond: Condvar,
+}
+
+impl VmWaker {
+  pub fn new() -> Self {
+    Self {
+      state: Mutex::new(WakerState {
+        generation: 0,
+        jobs: Vec::new(),
+      }),
+      cond: Condvar::new(),
+    }
+  }
+
+  /// Wake up the idle schedulers, they will check their queues and timers
+  /// again.
+  pub fn notify(&self) {
+    self.state.lock().unwrap().generation += 1;
+    self.cond.notify_all();
+  }
+
+  /// Current notification count, to be passed to `wait`. Read it before
+  /// checking for work, so that a notification which comes after the check
+  /// is not missed.
+  pub fn generation(&self) -> u64 {
+    self.state.lock().unwrap().generation
+  }
+
+  /// Run `job` on a scheduler thread before it schedules the next process.
+  #[allow(dead_code)]
+  pub fn post(&self, job: VmJob) {
+    self.state.lock().unwrap().jobs.push(job);
+    self.notify();
+  }
+
+  pub fn take_jobs(&self) -> Vec<VmJob> {
+    core::mem::take(&mut self.state.lock().unwrap().jobs)
+  }
+
+  /// Block the calling thread until notified after `seen` was read with
+  /// `generation`, or until the timeout has passed, `None` waits forever.
+  pub fn wait(&self, seen: u64, timeout: Option<Duration>) {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut state = self.state.lock().unwrap();
+    while state.generation == seen {
+      match deadline {
+        Some(d) => {
+          let now = Instant::now();
+          if now >= d {
+            break;
+          }
+          state = self.cond.wait_timeout(state, d - now).unwrap().0;
+        }
+        None => state = self.cond.wait(state).unwrap(),
+      }
+    }
+  }
+}
@@ -3,7 +3,7 @@ pub mod create;
 
 use crate::{
   beam::loader::CompactTermError,
-  defs::{exc_type::ExceptionType, SizeBytes},
+  defs::{exc_type::ExceptionType, SizeBytes, SizeWords},
   rt_util::bin_reader::{self, ReadError},
   term::Term,
 };
@@ -28,13 +28,28 @@ pub enum RtErr {
 
   //--- Memory allocation events and errors ---
   AtomNotExist(String),
-  /// No space left in heap. GC requested.
-  HeapIsFull,
+  /// No space left in heap for an allocation of this size. GC requested, then
+  /// the failed instruction or native function call is retried. The retried
+  /// code runs from the start, so it must not change any state before it can
+  /// fail with this: allocate (or check the heap space) first.
+  HeapIsFull(SizeWords),
   /// Attempt to index outside of the current stack.
   StackIndexRange(usize),
 
   //--- VM Checks --
   Exception(ExceptionType, Term), // type, value
+  /// Process must exit with reason `killed`, this cannot be caught.
+  ProcessKilled,
+  /// Process has hibernated and must not run until a message arrives.
+  Hibernate,
+  /// `erlang:halt` was called, the emulator stops with this exit status.
+  Halt(i32),
+  /// No process can run and nothing can wake up the processes which are
+  /// waiting forever, their count is given.
+  Deadlock(usize),
+  /// Native function has run out of reductions and saved its progress in the
+  /// process, the call instruction runs again when the process is scheduled.
+  Trap,
   TermIsNotABoxed,
   // used by `helper_get_mut_from_boxed_term` when boxed tag is different from
   // what is expected
@@ -1,16 +1,19 @@
 use crate::{
   command_line_args::ErlStartArgs,
   emulator::{atom, mfa::ModFunArgs, spawn_options::SpawnOptions, vm::VM},
+  fail::RtErr,
   term::*,
 };
-use std::{
-  io::{stdout, Write},
-  thread, time,
-};
+use std::io::{stdout, Write};
+
+/// Exit status when processes are left waiting forever.
+pub const DEADLOCK_EXIT_STATUS: i32 = 2;
 
 /// Entry point for the command-line interface. Pre-parse command line args
 /// by calling StartArgs methods, or just use default constructed StartArgs.
-pub fn start_emulator(args: &mut ErlStartArgs) {
+/// Returns the exit status: 0 when all processes have finished,
+/// `DEADLOCK_EXIT_STATUS` on a deadlock, or the status given to `erlang:halt`.
+pub fn start_emulator(args: &mut ErlStartArgs) -> i32 {
   if cfg!(feature = "r20") {
     println!("Erlang Runtime (compat OTP 20)");
   }
@@ -21,7 +24,7 @@ pub fn start_emulator(args: &mut ErlStartArgs) {
     println!("Erlang Runtime (compat OTP 22)");
   }
 
-  let mut beam_vm = VM::new(args);
+  let beam_vm = VM::new(args);
 
   let mfargs = ModFunArgs::with_args_list(
     atom::from_str("test2"),
@@ -37,9 +40,18 @@ pub fn start_emulator(args: &mut ErlStartArgs) {
     .create_process(Term::nil(), &mfargs, &SpawnOptions::default())
     .unwrap();
 
-  println!("Process created. Entering main loop...");
-  while beam_vm.tick().unwrap() {
-    thread::sleep(time::Duration::from_millis(0));
-  }
+  println!(
+    "Process created. Entering main loop with {} schedulers...",
+    beam_vm.schedulers.len()
+  );
+  let status = match beam_vm.run() {
+    Ok(status) => status,
+    Err(RtErr::Deadlock(count)) => {
+      println!("Deadlock, {count} processes are waiting forever");
+      DEADLOCK_EXIT_STATUS
+    }
+    Err(e) => panic!("Emulator failed: {:?}", e),
+  };
   stdout().flush().unwrap();
+  status
 }
@@ -12,7 +12,7 @@ fn module() -> &'static str {
 /// Subtraction for 2 mixed terms. Algorithm comes from Erlang/OTP file
 /// `erl_arith.c`, function `erts_mixed_minus`
 pub fn nativefun_minus_2(
-  _vm: &mut VM,
+  _vm: &VM,
   cur_proc: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
@@ -33,7 +33,7 @@ pub fn nativefun_minus_2(
 
 /// Addition for 2 mixed terms.
 pub fn nativefun_plus_2(
-  _vm: &mut VM,
+  _vm: &VM,
   cur_proc: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
@@ -81,7 +81,7 @@ fn add_two_small(cur_proc: &mut Process, a: Term, b: Term) -> RtResult<Term> {
 
 /// Multiplication for 2 mixed terms.
 pub fn nativefun_multiply_2(
-  _vm: &mut VM,
+  _vm: &VM,
   cur_proc: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
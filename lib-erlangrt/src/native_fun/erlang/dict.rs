@@ -0,0 +1,109 @@
+use crate::{
+  emulator::{gen_atoms, heap::THeapOwner, process::Process},
+  fail::RtResult,
+  term::{
+    compare,
+    term_builder::{tuple_builder::tuple2, ListBuilder},
+    Term,
+  },
+};
+
+// Stores a value in the process dictionary, returns the previous value or
+// `undefined`.
+// Spec: erlang:put(key, val)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:put/2", struct_name: NfErlangPut2, arity: 2,
+  invoke: {
+    Ok(proc.dictionary.put(key, val)?.unwrap_or(gen_atoms::UNDEFINED))
+  },
+  args: term(key), term(val),
+);
+
+// Spec: erlang:get(key)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get/1", struct_name: NfErlangGet1, arity: 1,
+  invoke: {
+    Ok(proc.dictionary.get(key)?.unwrap_or(gen_atoms::UNDEFINED))
+  },
+  args: term(key),
+);
+
+// Returns the whole dictionary as a list of `{Key, Val}`.
+// Spec: erlang:get()
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get/0", struct_name: NfErlangGet0, arity: 0,
+  invoke: { dictionary_to_list(proc) },
+  args:
+);
+
+// Spec: erlang:get_keys()
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get_keys/0", struct_name: NfErlangGetKeys0, arity: 0,
+  invoke: {
+    let keys: Vec<Term> = proc.dictionary.iter().map(|(k, _v)| k).collect();
+    terms_to_list(proc, &keys)
+  },
+  args:
+);
+
+// Returns keys which have the value `val`.
+// Spec: erlang:get_keys(val)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get_keys/1", struct_name: NfErlangGetKeys1, arity: 1,
+  invoke: { get_keys_1(proc, val) },
+  args: term(val),
+);
+
+pub fn get_keys_1(proc: &mut Process, val: Term) -> RtResult<Term> {
+  let mut keys = Vec::new();
+  for (k, v) in proc.dictionary.iter() {
+    if compare::exact_eq(v, val)? {
+      keys.push(k);
+    }
+  }
+  terms_to_list(proc, &keys)
+}
+
+// Removes a key, returns its value or `undefined`.
+// Spec: erlang:erase(key)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:erase/1", struct_name: NfErlangErase1, arity: 1,
+  invoke: {
+    Ok(proc.dictionary.erase(key)?.unwrap_or(gen_atoms::UNDEFINED))
+  },
+  args: term(key),
+);
+
+// Removes all keys, returns the dictionary as a list of `{Key, Val}`.
+// Spec: erlang:erase()
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:erase/0", struct_name: NfErlangErase0, arity: 0,
+  invoke: {
+    // Build the result first, running out of heap would retry this function
+    let result = dictionary_to_list(proc)?;
+    proc.dictionary.clear();
+    Ok(result)
+  },
+  args:
+);
+
+/// Build a list of `{Key, Val}` pairs for the process dictionary.
+pub fn dictionary_to_list(proc: &mut Process) -> RtResult<Term> {
+  let pairs: Vec<(Term, Term)> = proc.dictionary.iter().collect();
+  let hp = proc.get_heap_mut();
+  let mut lb = ListBuilder::new()?;
+  for (k, v) in pairs {
+    let pair = tuple2(hp, k, v)?;
+    unsafe { lb.append(pair, hp)? };
+  }
+  Ok(lb.make_term())
+}
+
+fn terms_to_list(proc: &mut Process, terms: &[Term]) -> RtResult<Term> {
+  let hp = proc.get_heap_mut();
+  let mut lb = ListBuilder::new()?;
+  for t in terms {
+    unsafe { lb.append(*t, hp)? };
+  }
+  Ok(lb.make_term())
+}
@@ -0,0 +1,46 @@
+use crate::{
+  defs::Word,
+  emulator::process::Process,
+  fail::{self, RtResult},
+  rt_util::ext_term_format,
+  term::Term,
+};
+
+/// Default range for `phash2/1`, same as in Erlang/OTP.
+const PHASH2_DEFAULT_RANGE: Word = 1 << 27;
+
+// Portable hash of a term in range `0..2^27`. The hash is calculated from the
+// external term format encoding, so terms which are exactly equal have equal
+// hashes. The values are not the same as in Erlang/OTP.
+// Spec: erlang:phash2(term)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:phash2/1", struct_name: NfErlangPhash2_1, arity: 1,
+  invoke: { phash2(proc, val, PHASH2_DEFAULT_RANGE) },
+  args: term(val),
+);
+
+// Portable hash of a term in range `0..range`, `range` is from 1 to 2^32.
+// Spec: erlang:phash2(term, range)
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:phash2/2", struct_name: NfErlangPhash2_2, arity: 2,
+  invoke: {
+    if range == 0 || range > 1 << 32 {
+      return fail::create::badarg();
+    }
+    phash2(proc, val, range)
+  },
+  args: term(val), usize(range),
+);
+
+fn phash2(proc: &mut Process, val: Term, range: Word) -> RtResult<Term> {
+  let data = ext_term_format::encode(val).or_else(|_| fail::create::badarg())?;
+  proc.context.consume_native_work(data.len());
+  Ok(Term::make_small_unsigned(hash_bytes(&data) as Word % range))
+}
+
+/// 32-bit FNV-1a hash.
+fn hash_bytes(data: &[u8]) -> u32 {
+  data.iter().fold(0x811c_9dc5u32, |hash, byte| {
+    (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+  })
+}
@@ -1,6 +1,6 @@
 use crate::{
   emulator::{heap::THeapOwner, process::Process},
-  fail::RtResult,
+  fail::{self, RtResult},
   term::*,
 };
 
@@ -9,42 +9,93 @@ fn module() -> &'static str {
   "native funs module for erlang[list]: "
 }
 
-// Calculate length of a list by traversing it.
-define_nativefun!(_vm, _proc, args,
+// Calculate length of a list by traversing it. Long lists are counted over
+// several timeslices.
+define_nativefun!(_vm, proc, args,
   name: "erlang:length/1", struct_name: NfErlangLength1, arity: 1,
-  invoke: {
-    let result = cons::list_length(list)?;
-    Ok(Term::make_small_unsigned(result))
-  },
+  invoke: { length_1(proc, args, list) },
   args: list(list),
 );
 
+pub fn length_1(proc: &mut Process, args: &[Term], list: Term) -> RtResult<Term> {
+  // Saved state: the rest of the list and the count so far
+  let (mut rest, mut count) = match proc.get_trap_state("erlang:length/1", args) {
+    Some(state) => (state[0], state[1].get_small_unsigned()),
+    None => (list, 0),
+  };
+  let budget = proc.context.get_native_work_budget();
+  let mut work = 0;
+  while rest.is_cons() {
+    if work == budget {
+      proc.context.consume_native_work(work);
+      let state = [rest, Term::make_small_unsigned(count)];
+      return proc.trap("erlang:length/1", args, &state);
+    }
+    rest = unsafe { (*rest.get_cons_ptr()).tl() };
+    count += 1;
+    work += 1;
+  }
+  proc.context.consume_native_work(work);
+  if rest != Term::nil() {
+    return fail::create::badarg();
+  }
+  Ok(Term::make_small_unsigned(count))
+}
+
 // Calculate a new list made of two lists joined together.
 // Arg1 must be list or NIL.
 define_nativefun!(_vm, proc, args,
-  name: "erlang:hd/1", struct_name: NfErlangPlusPlus2, arity: 2,
-  invoke: { plusplus_2(proc, a, b) },
+  name: "erlang:++/2", struct_name: NfErlangPlusPlus2, arity: 2,
+  invoke: { plusplus_2(proc, args, a, b) },
   args: list(a), term(b),
 );
 
-#[inline]
-pub fn plusplus_2(curr_p: &mut Process, a: Term, b: Term) -> RtResult<Term> {
+pub fn plusplus_2(
+  curr_p: &mut Process,
+  args: &[Term],
+  a: Term,
+  b: Term,
+) -> RtResult<Term> {
   // Doing [] ++ X -> X
   if a == Term::nil() {
     return Ok(b);
   }
 
-  // Copy the list a without setting its tail, ...
-  let hp = curr_p.get_heap_mut();
-  let (l1, tail) = unsafe { cons::copy_list_leave_tail(a, hp) }?;
-
-  // then append the tail
-  unsafe {
-    (*tail).set_tl(b);
+  // Copy `a` in two passes: reverse it, then reverse the copy onto `b`. Cells
+  // are only prepended, so a cell made in an earlier timeslice and promoted by
+  // a GC since then never gets a pointer to a younger cell. Saved state: the
+  // pass, the rest of the list being reversed and the result so far.
+  let (mut pass, mut rest, mut acc) = match curr_p.get_trap_state("erlang:++/2", args) {
+    Some(state) => (state[0].get_small_unsigned(), state[1], state[2]),
+    None => (0, a, Term::nil()),
+  };
+  let budget = curr_p.context.get_native_work_budget();
+  let mut work = 0;
+  loop {
+    let hp = curr_p.get_heap_mut();
+    let limit = budget - work;
+    let (rest1, acc1, count) = unsafe { cons::reverse_onto(rest, acc, limit, hp)? };
+    rest = rest1;
+    acc = acc1;
+    work += count;
+    if rest.is_cons() {
+      curr_p.context.consume_native_work(work);
+      let state = [Term::make_small_unsigned(pass), rest, acc];
+      return curr_p.trap("erlang:++/2", args, &state);
+    }
+    if pass == 1 {
+      break;
+    }
+    if rest != Term::nil() {
+      curr_p.context.consume_native_work(work);
+      return fail::create::badarg();
+    }
+    pass = 1;
+    rest = acc;
+    acc = b;
   }
-
-  // Return what we got joined together
-  Ok(l1)
+  curr_p.context.consume_native_work(work);
+  Ok(acc)
 }
 
 // Takes head of a cons value, otherwise returns badarg.
@@ -66,3 +117,88 @@ define_nativefun!(_vm, _proc, args,
   },
   args: non_empty_list(list),
 );
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    beam::gen_op,
+    command_line_args::ErlStartArgs,
+    defs::SizeWords,
+    emulator::{
+      code::opcode,
+      code_srv::CodeServer,
+      funarity::FunArity,
+      gen_atoms,
+      mfa::ModFunArity,
+      module::{Module, VersionedModuleName},
+      spawn_options::SpawnOptions,
+    },
+    fail::RtErr,
+    term::term_builder::ListBuilder,
+  };
+
+  /// A process which is never run, it starts at a `return` in a fake module.
+  fn make_process(code_server: &CodeServer, fullsweep_after: usize) -> Process {
+    let mut m = Module::new(&VersionedModuleName::new(gen_atoms::ERLANG, 1));
+    m.code = vec![opcode::to_memory_word(gen_op::OPCODE_RETURN)];
+    m.funs.insert(FunArity::new(gen_atoms::APPLY, 0), 0);
+    code_server.module_loaded(Box::new(m));
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, gen_atoms::APPLY, 0);
+    let mut opts = SpawnOptions::default();
+    opts.fullsweep_after = fullsweep_after;
+    Process::new(Term::make_local_pid(0), Term::nil(), &mfa, &opts, code_server).unwrap()
+  }
+
+  fn make_list(p: &mut Process, values: core::ops::Range<usize>) -> Term {
+    let mut lb = ListBuilder::new().unwrap();
+    for i in values {
+      unsafe { lb.append(Term::make_small_unsigned(i), p.get_heap_mut()).unwrap() };
+    }
+    unsafe { lb.make_term_with_tail(Term::nil()) }
+  }
+
+  #[test]
+  fn test_plusplus_across_minor_gc() {
+    let code_server = CodeServer::new(&mut ErlStartArgs::new(&[]));
+    let mut p = make_process(&code_server, 100);
+    p.ensure_heap(SizeWords::new(420)).unwrap();
+    let mut args = [make_list(&mut p, 0..200), make_list(&mut p, 200..210)];
+
+    // A timeslice copies 16 cells, between the timeslices run two minor GCs so
+    // that the cells copied so far are promoted to the old heap
+    let mut slices = 0;
+    let result = loop {
+      p.context.reductions = 1;
+      match plusplus_2(&mut p, &args, args[0], args[1]) {
+        Ok(result) => break result,
+        Err(RtErr::HeapIsFull(need)) => {
+          p.collect_garbage(need, &mut args).unwrap();
+        }
+        Err(RtErr::Trap) => {
+          for _ in 0..2 {
+            p.collect_garbage(SizeWords::zero(), &mut args).unwrap();
+          }
+          slices += 1;
+        }
+        Err(e) => panic!("{:?}", e),
+      }
+    };
+    assert!(slices > 10);
+    p.trap = None;
+
+    // A minor GC after the copy is done must not lose the cells made last
+    let mut roots = [result];
+    p.collect_garbage(SizeWords::zero(), &mut roots).unwrap();
+    let mut list = roots[0];
+    for i in 0..210 {
+      let cell = list.get_cons_ptr();
+      assert_eq!(unsafe { (*cell).hd() }, Term::make_small_unsigned(i));
+      list = unsafe { (*cell).tl() };
+    }
+    assert_eq!(list, Term::nil());
+  }
+}
@@ -12,7 +12,7 @@ fn module() -> &'static str {
 
 /// Return `true` if the value is a boolean (atom `true` or atom `false`)
 pub fn nativefun_is_boolean_1(
-  _vm: &mut VM,
+  _vm: &VM,
   _curr_p: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
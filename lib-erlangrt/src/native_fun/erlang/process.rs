@@ -1,18 +1,29 @@
 use crate::{
-  defs::exc_type::ExceptionType,
+  defs::{exc_type::ExceptionType, Word},
   emulator::{
     gen_atoms,
-    heap::THeapOwner,
+    heap::{owned_term::OwnedTerm, Heap, THeapOwner},
     mfa::{ModFunArgs, ModFunArity},
+    monitor::{self, Monitor, MonitorTarget},
     process::Process,
-    process_flags,
-    spawn_options::SpawnOptions,
+    process_flags::{self, MaxHeapSize},
+    scheduler::Prio,
+    signal::Signal,
+    spawn_options::{MessageQueueLocation, SpawnOptions},
     vm::VM,
   },
   fail::{self, RtErr, RtResult},
   native_fun::assert_arity,
-  term::{boxed, *},
+  term::{
+    boxed, cons,
+    term_builder::{tuple_builder::tuple3, ListBuilder, TupleBuilder},
+    *,
+  },
 };
+use core::sync::atomic::Ordering;
+
+/// Largest value of the `save_calls` process flag, like in OTP.
+const MAX_SAVE_CALLS: isize = 10000;
 
 #[allow(dead_code)]
 fn module() -> &'static str {
@@ -25,9 +36,15 @@ define_nativefun!(_vm, proc, _args,
   args:
 );
 
+define_nativefun!(vm, proc, _args,
+  name: "erlang:make_ref/0", struct_name: NfErlangMakeRef0, arity: 0,
+  invoke: { vm.make_ref(proc.get_heap_mut()) },
+  args:
+);
+
 /// Create a function pointer from atom(), atom(), smallint()
 pub fn nativefun_make_fun_3(
-  _vm: &mut VM,
+  _vm: &VM,
   cur_proc: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
@@ -57,12 +74,478 @@ define_nativefun!(vm, _proc, _args,
   args: atom(m), atom(f), list(args),
 );
 
+// Creates a new process like `spawn/3` and links it to the current process.
+// Spec: erlang:spawn_link(mod, fun, args:list)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_link/3", struct_name: NfErlangSpawnLink3, arity: 3,
+  invoke: {
+    let mfargs = ModFunArgs::with_args_list(m, f, args);
+    spawn_link(vm, proc, &mfargs, SpawnOptions::default())
+  },
+  args: atom(m), atom(f), list(args),
+);
+
+// Creates a new process which calls a fun of arity 0, and links it to the
+// current process.
+// Spec: erlang:spawn_link(fun)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_link/1", struct_name: NfErlangSpawnLink1, arity: 1,
+  invoke: { spawn_link_1(vm, proc, fun) },
+  args: term(fun),
+);
+
+pub fn spawn_link_1(vm: &VM, proc: &mut Process, fun: Term) -> RtResult<Term> {
+  let mfargs = fun_to_spawn_mfargs(fun)?;
+  spawn_link(vm, proc, &mfargs, SpawnOptions::default())
+}
+
+/// For spawning a fun of arity 0: a closure calls its implementation function
+/// with the frozen values as args, an export is called without args.
+fn fun_to_spawn_mfargs(fun: Term) -> RtResult<ModFunArgs<'static>> {
+  if !fun.is_fun_of_arity(0) {
+    return fail::create::badarg();
+  }
+  if fun.is_export() {
+    let expt_p = fun.get_box_ptr::<boxed::Export>();
+    let mfa = unsafe { (*expt_p).exp.mfa };
+    return Ok(ModFunArgs::with_args_slice(mfa.m, mfa.f, &[]));
+  }
+  let closure_p = fun.get_box_ptr::<boxed::Closure>();
+  unsafe {
+    Ok(ModFunArgs::with_args_slice(
+      (*closure_p).mfa.m,
+      (*closure_p).mfa.f,
+      (*closure_p).get_frozen(),
+    ))
+  }
+}
+
+/// The new process is created with the link, see `SpawnOptions::link`.
+fn spawn_link(
+  vm: &VM,
+  proc: &mut Process,
+  mfargs: &ModFunArgs,
+  mut spawn_opts: SpawnOptions,
+) -> RtResult<Term> {
+  spawn_opts.link = true;
+  let pid = vm.create_process(proc.pid, mfargs, &spawn_opts)?;
+  proc.links.insert(pid);
+  Ok(pid)
+}
+
+// Links the current process to another. If the other process does not exist,
+// fails with `noproc`, or when trapping exits, an `{'EXIT', Pid, noproc}`
+// message is received.
+// Spec: erlang:link(pid)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:link/1", struct_name: NfErlangLink1, arity: 1,
+  invoke: { link_1(vm, proc, pid) },
+  args: pid(pid),
+);
+
+pub fn link_1(vm: &VM, proc: &mut Process, pid: Term) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  if pid == proc.pid {
+    return Ok(gen_atoms::TRUE);
+  }
+  if vm.send_signal(pid, Signal::Link(proc.pid)).is_ok() {
+    proc.links.insert(pid);
+  } else if proc.process_flags.get(process_flags::TRAP_EXIT) {
+    let mut msg_heap = Heap::new_fragment(boxed::Tuple::storage_size(3));
+    let msg = tuple3(&mut msg_heap, gen_atoms::EXIT_TAG, pid, gen_atoms::NOPROC)?;
+    proc.deliver_message(msg)?;
+  } else {
+    return Err(RtErr::Exception(ExceptionType::Error, gen_atoms::NOPROC));
+  }
+  Ok(gen_atoms::TRUE)
+}
+
+// Removes a link between the current process and another, if it existed.
+// Spec: erlang:unlink(pid)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:unlink/1", struct_name: NfErlangUnlink1, arity: 1,
+  invoke: { unlink_1(vm, proc, pid) },
+  args: pid(pid),
+);
+
+pub fn unlink_1(vm: &VM, proc: &mut Process, pid: Term) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  if proc.links.remove(&pid) {
+    let _ = vm.send_signal(pid, Signal::Unlink(proc.pid));
+  }
+  Ok(gen_atoms::TRUE)
+}
+
+// Sends an exit signal with `reason` to a process.
+// Spec: erlang:exit(pid, reason)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:exit/2", struct_name: NfErlangExit2, arity: 2,
+  invoke: { exit_2(vm, proc, pid, reason) },
+  args: pid(pid), term(reason),
+);
+
+pub fn exit_2(vm: &VM, proc: &mut Process, pid: Term, reason: Term) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  let reason = OwnedTerm::new(reason)?;
+  if pid != proc.pid {
+    // A process which does not exist ignores the signal
+    let signal = Signal::Exit {
+      from: proc.pid,
+      reason,
+      from_link: false,
+    };
+    let _ = vm.send_signal(pid, signal);
+    return Ok(gen_atoms::TRUE);
+  }
+  proc.exit_signal(proc.pid, reason, false)?;
+  if proc.pending_exit.is_some() {
+    // The signal was sent to self and is fatal: stop running now, the
+    // scheduler will terminate the process with the pending reason.
+    return Err(RtErr::ProcessKilled);
+  }
+  Ok(gen_atoms::TRUE)
+}
+
+// Starts monitoring a process by pid or by registered name. Returns the
+// monitor reference.
+// Spec: erlang:monitor(process, pid | name | {name, node})
+define_nativefun!(vm, proc, _args,
+  name: "erlang:monitor/2", struct_name: NfErlangMonitor2, arity: 2,
+  invoke: { monitor(vm, proc, mtype, item, Term::nil()) },
+  args: atom(mtype), term(item),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:monitor/3", struct_name: NfErlangMonitor3, arity: 3,
+  invoke: { monitor(vm, proc, mtype, item, opts) },
+  args: atom(mtype), term(item), list(opts),
+);
+
+/// Monitor options: only `{tag, Tag}` is supported, returns the tag for the
+/// 'DOWN' message.
+fn parse_monitor_options(opts: Term) -> RtResult<Term> {
+  let mut tag = gen_atoms::DOWN_TAG;
+  let tail = cons::for_each(opts, |opt| {
+    if !opt.is_tuple() || opt == Term::empty_tuple() {
+      return fail::create::badarg();
+    }
+    let tuple_p = opt.get_tuple_ptr();
+    unsafe {
+      if (*tuple_p).get_arity() != 2 || (*tuple_p).get_element(0) != gen_atoms::TAG {
+        return fail::create::badarg();
+      }
+      tag = (*tuple_p).get_element(1);
+    }
+    Ok(())
+  })?;
+  match tail {
+    Some(t) if t != Term::nil() => fail::create::badarg(),
+    _ => Ok(tag),
+  }
+}
+
+/// Find the monitored process: by pid, registered name or `{Name, Node}` on
+/// the local node. Returns the pid (nil if the name is not registered) and
+/// the name (nil if monitored by pid).
+fn resolve_monitor_item(vm: &VM, item: Term) -> RtResult<(Term, Term)> {
+  let name = if item.is_local_pid() {
+    return Ok((item, Term::nil()));
+  } else if item.is_atom() {
+    item
+  } else if item.is_tuple() && item != Term::empty_tuple() {
+    let tuple_p = item.get_tuple_ptr();
+    unsafe {
+      if (*tuple_p).get_arity() != 2
+        || !(*tuple_p).get_element(0).is_atom()
+        || (*tuple_p).get_element(1) != gen_atoms::NONODE_AT_NOHOST
+      {
+        return fail::create::badarg();
+      }
+      (*tuple_p).get_element(0)
+    }
+  } else {
+    return fail::create::badarg();
+  };
+  match vm.processes.find_registered(name) {
+    Some(pid) if pid.is_local_pid() => Ok((pid, name)),
+    _ => Ok((Term::nil(), name)),
+  }
+}
+
+pub fn monitor(
+  vm: &VM,
+  proc: &mut Process,
+  mtype: Term,
+  item: Term,
+  opts: Term,
+) -> RtResult<Term> {
+  if mtype != gen_atoms::PROCESS {
+    return fail::create::badarg();
+  }
+  let tag = parse_monitor_options(opts)?;
+  let (pid, name) = resolve_monitor_item(vm, item)?;
+  let tref = vm.make_ref(proc.get_heap_mut())?;
+  add_monitor(vm, proc, boxed::LocalRef::get_id(tref), pid, name, tag)?;
+  Ok(tref)
+}
+
+/// Register the monitor in both processes. If the monitored process does not
+/// exist, `'DOWN'` with reason `noproc` is received immediately.
+fn add_monitor(
+  vm: &VM,
+  proc: &mut Process,
+  ref_id: Word,
+  pid: Term,
+  name: Term,
+  tag: Term,
+) -> RtResult<()> {
+  if pid != Term::nil() {
+    let mon = Monitor {
+      watcher: proc.pid,
+      name,
+      tag: OwnedTerm::new(tag)?,
+    };
+    if vm.send_signal(pid, Signal::Monitor(ref_id, mon)).is_ok() {
+      proc.monitors.insert(ref_id, MonitorTarget { pid, name });
+      return Ok(());
+    }
+  }
+  let down = monitor::make_down(tag, ref_id, pid, name, gen_atoms::NOPROC)?;
+  let (message, fragment) = down.into_parts();
+  proc.mailbox.put_with_fragment(message, fragment);
+  Ok(())
+}
+
+// Removes a monitor. Option `flush` also removes the 'DOWN' message if it
+// has already arrived, option `info` returns whether the monitor was found.
+// Spec: erlang:demonitor(ref, [flush | info])
+define_nativefun!(vm, proc, _args,
+  name: "erlang:demonitor/1", struct_name: NfErlangDemonitor1, arity: 1,
+  invoke: { demonitor(vm, proc, tref, Term::nil()) },
+  args: term(tref),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:demonitor/2", struct_name: NfErlangDemonitor2, arity: 2,
+  invoke: { demonitor(vm, proc, tref, opts) },
+  args: term(tref), list(opts),
+);
+
+pub fn demonitor(vm: &VM, proc: &mut Process, tref: Term, opts: Term) -> RtResult<Term> {
+  if !tref.is_local_ref() {
+    return fail::create::badarg();
+  }
+  let mut flush = false;
+  let mut info = false;
+  let tail = cons::for_each(opts, |opt| {
+    match opt {
+      gen_atoms::FLUSH => flush = true,
+      gen_atoms::INFO => info = true,
+      _ => return fail::create::badarg(),
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+
+  let ref_id = boxed::LocalRef::get_id(tref);
+  let found = match proc.monitors.remove(&ref_id) {
+    Some(target) => {
+      // A 'DOWN' signal already on its way is dropped by the receiver
+      let _ = vm.send_signal(target.pid, Signal::Demonitor(ref_id));
+      true
+    }
+    None => false,
+  };
+  if flush {
+    proc
+      .mailbox
+      .remove_first(|msg| monitor::is_down_message_for(msg, ref_id));
+  }
+  Ok(if info {
+    Term::make_bool(found)
+  } else {
+    gen_atoms::TRUE
+  })
+}
+
+// Creates a new process like `spawn/3` and monitors it, returns `{Pid, Ref}`.
+// Spec: erlang:spawn_monitor(mod, fun, args:list)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_monitor/3", struct_name: NfErlangSpawnMonitor3, arity: 3,
+  invoke: {
+    let mfargs = ModFunArgs::with_args_list(m, f, args);
+    spawn_monitor(vm, proc, &mfargs, SpawnOptions::default(), false)
+  },
+  args: atom(m), atom(f), list(args),
+);
+
+// Creates a new process which calls a fun of arity 0 and monitors it.
+// Spec: erlang:spawn_monitor(fun)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_monitor/1", struct_name: NfErlangSpawnMonitor1, arity: 1,
+  invoke: {
+    let mfargs = fun_to_spawn_mfargs(fun)?;
+    spawn_monitor(vm, proc, &mfargs, SpawnOptions::default(), false)
+  },
+  args: term(fun),
+);
+
+/// The new process is created with the monitor and the link, see
+/// `SpawnOptions::monitor`.
+fn spawn_monitor(
+  vm: &VM,
+  proc: &mut Process,
+  mfargs: &ModFunArgs,
+  mut spawn_opts: SpawnOptions,
+  link_too: bool,
+) -> RtResult<Term> {
+  // Allocate the result first, running out of heap after the spawn would
+  // retry this function and spawn again
+  let hp = proc.get_heap_mut();
+  let tref = vm.make_ref(hp)?;
+  let tb = TupleBuilder::with_arity(2, hp)?;
+
+  let ref_id = boxed::LocalRef::get_id(tref);
+  spawn_opts.link = link_too;
+  spawn_opts.monitor = Some(ref_id);
+  let pid = vm.create_process(proc.pid, mfargs, &spawn_opts)?;
+  if link_too {
+    proc.links.insert(pid);
+  }
+  let target = MonitorTarget {
+    pid,
+    name: Term::nil(),
+  };
+  proc.monitors.insert(ref_id, target);
+  unsafe {
+    tb.set_element(0, pid);
+    tb.set_element(1, tref);
+  }
+  Ok(tb.make_term())
+}
+
+// Creates a new process with options: `link`, `monitor`, `{priority, P}`,
+// `{message_queue_data, D}`, `{max_heap_size, S}`, `{min_heap_size, N}`,
+// `{min_bin_vheap_size, N}` and `{fullsweep_after, N}`. With `monitor`
+// returns `{Pid, Ref}`.
+// Spec: erlang:spawn_opt(fun, options)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_opt/2", struct_name: NfErlangSpawnOpt2, arity: 2,
+  invoke: {
+    let mfargs = fun_to_spawn_mfargs(fun)?;
+    spawn_opt(vm, proc, &mfargs, opts)
+  },
+  args: term(fun), list(opts),
+);
+
+// Spec: erlang:spawn_opt(mod, fun, args:list, options)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:spawn_opt/4", struct_name: NfErlangSpawnOpt4, arity: 4,
+  invoke: {
+    let mfargs = ModFunArgs::with_args_list(m, f, args);
+    spawn_opt(vm, proc, &mfargs, opts)
+  },
+  args: atom(m), atom(f), list(args), list(opts),
+);
+
+fn spawn_opt(
+  vm: &VM,
+  proc: &mut Process,
+  mfargs: &ModFunArgs,
+  opts: Term,
+) -> RtResult<Term> {
+  let mut spawn_opts = SpawnOptions::default();
+  let mut link_opt = false;
+  let mut monitor_opt = false;
+  let tail = cons::for_each(opts, |opt| {
+    match opt {
+      gen_atoms::LINK => link_opt = true,
+      gen_atoms::MONITOR => monitor_opt = true,
+      _ => parse_spawn_option(&mut spawn_opts, opt)?,
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+
+  if monitor_opt {
+    spawn_monitor(vm, proc, mfargs, spawn_opts, link_opt)
+  } else if link_opt {
+    spawn_link(vm, proc, mfargs, spawn_opts)
+  } else {
+    vm.create_process(proc.pid, mfargs, &spawn_opts)
+  }
+}
+
+/// Parse a `{Key, Value}` spawn option into `spawn_opts`.
+fn parse_spawn_option(spawn_opts: &mut SpawnOptions, opt: Term) -> RtResult<()> {
+  if !opt.is_tuple() || opt == Term::empty_tuple() {
+    return fail::create::badarg();
+  }
+  let tuple_p = opt.get_tuple_ptr();
+  let (key, val) = unsafe {
+    if (*tuple_p).get_arity() != 2 {
+      return fail::create::badarg();
+    }
+    ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+  };
+  let non_neg_int = || {
+    if val.is_small() && val.get_small_signed() >= 0 {
+      Ok(val.get_small_unsigned())
+    } else {
+      fail::create::badarg()
+    }
+  };
+  match key {
+    gen_atoms::PRIORITY => spawn_opts.prio = Prio::from_term(val)?,
+    gen_atoms::MESSAGE_QUEUE_DATA => {
+      spawn_opts.msg_queue = match val {
+        gen_atoms::ON_HEAP => MessageQueueLocation::OnHeap,
+        gen_atoms::OFF_HEAP => MessageQueueLocation::OffHeap,
+        _ => return fail::create::badarg(),
+      }
+    }
+    gen_atoms::MAX_HEAP_SIZE => spawn_opts.max_heap_size = MaxHeapSize::from_term(val)?,
+    gen_atoms::MIN_HEAP_SIZE => spawn_opts.min_heap_size = non_neg_int()?,
+    gen_atoms::MIN_BIN_VHEAP_SIZE => spawn_opts.min_bin_vheap_size = non_neg_int()?,
+    gen_atoms::FULLSWEEP_AFTER => spawn_opts.fullsweep_after = non_neg_int()?,
+    _ => return fail::create::badarg(),
+  }
+  Ok(())
+}
+
 define_nativefun!(vm, _proc, args,
   name: "erlang:is_process_alive/1", struct_name: NfErlangIsPAlive1, arity: 1,
-  invoke: { Ok(Term::make_bool(vm.processes.lookup_pid(pid).is_some())) },
+  invoke: { Ok(Term::make_bool(vm.processes.exists(pid))) },
   args: pid(pid),
 );
 
+// Puts the process to sleep until a message arrives, then it calls
+// `m:f(args...)` with an empty stack. This call never returns.
+// Spec: erlang:hibernate(mod, fun, args:list)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:hibernate/3", struct_name: NfErlangHibernate3, arity: 3,
+  invoke: {
+    let mfargs = ModFunArgs::with_args_list(m, f, args);
+    proc.hibernate(&mfargs, &vm.code_server)?;
+    Err(RtErr::Hibernate)
+  },
+  args: atom(m), atom(f), list(args),
+);
+
 // erlang:register(RegName :: atom(), Pid_or_Port)
 define_nativefun!(vm, _proc, _args,
   name: "erlang:register/2", struct_name: NfErlangRegister2, arity: 2,
@@ -72,51 +555,119 @@ define_nativefun!(vm, _proc, _args,
   args: atom(name), pid_port(pid_or_port),
 );
 
-pub fn register_2(vm: &mut VM, name: Term, pid_or_port: Term) -> RtResult<Term> {
+pub fn register_2(vm: &VM, name: Term, pid_or_port: Term) -> RtResult<Term> {
   // The define_nativefun! macro will check that the arguments are atom and pid/port
-  // but here we additionally check if the name is not `undefined` and does not exist
-  if name == gen_atoms::UNDEFINED || vm.processes.find_registered(name).is_some() {
+  // but here we additionally check if the name is not `undefined` and does not exist.
+  // A process must be alive and can have only one name.
+  if name == gen_atoms::UNDEFINED || !vm.processes.register_name(name, pid_or_port) {
     return fail::create::badarg();
   }
-  vm.processes.register_name(name, pid_or_port);
   Ok(gen_atoms::TRUE)
 }
 
-define_nativefun!(_vm, _proc, _args,
+// erlang:unregister(RegName :: atom())
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:unregister/1", struct_name: NfErlangUnregister1, arity: 1,
+  invoke: {
+    match vm.processes.unregister_name(name) {
+      Some(_) => Ok(gen_atoms::TRUE),
+      None => fail::create::badarg(),
+    }
+  },
+  args: atom(name),
+);
+
+// Returns the pid or port registered under the name, or `undefined`.
+// Spec: erlang:whereis(RegName :: atom())
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:whereis/1", struct_name: NfErlangWhereis1, arity: 1,
+  invoke: {
+    Ok(vm.processes.find_registered(name).unwrap_or(gen_atoms::UNDEFINED))
+  },
+  args: atom(name),
+);
+
+define_nativefun!(vm, proc, _args,
   name: "erlang:registered/0", struct_name: NfErlangRegistered0, arity: 0,
-  invoke: { panic!("not implemented") },
+  invoke: { registered_0(vm, proc) },
   args:
 );
 
+pub fn registered_0(vm: &VM, proc: &mut Process) -> RtResult<Term> {
+  let hp = proc.get_heap_mut();
+  let mut lb = ListBuilder::new()?;
+  for name in vm.processes.registered_names() {
+    unsafe { lb.prepend(name, hp)? };
+  }
+  Ok(lb.make_term())
+}
+
 define_nativefun!(_vm, proc, args,
   name: "erlang:process_flag/2", struct_name: NfErlangProcFlag2, arity: 2,
   invoke: { do_erlang_process_flag(proc, flag, value) },
-  args: atom(flag), bool(value),
+  args: atom(flag), term(value),
 );
 
-// Set a supported process flag for some other process.
+// Set a flag for some other process, only `save_calls` is allowed.
 define_nativefun!(vm, _proc, args,
   name: "erlang:process_flag/3", struct_name: NfErlangProcFlag3, arity: 3,
   invoke: { process_flag_3(vm, pid, flag, value) },
-  args: pid(pid), atom(flag), bool(value),
+  args: pid(pid), atom(flag), term(value),
 );
 
-pub fn process_flag_3(vm: &mut VM, pid: Term, flag: Term, value: bool) -> RtResult<Term> {
-  let proc_p = vm.processes.unsafe_lookup_pid_mut(pid);
-  if proc_p.is_null() {
+pub fn process_flag_3(vm: &VM, pid: Term, flag: Term, value: Term) -> RtResult<Term> {
+  let shared = match vm.processes.lookup(pid) {
+    Some(shared) => shared,
+    None => return fail::create::badarg(),
+  };
+  if flag != gen_atoms::SAVE_CALLS {
+    return fail::create::badarg();
+  }
+  let old = shared.save_calls.swap(save_calls_value(value)?, Ordering::Relaxed);
+  Ok(Term::make_small_unsigned(old))
+}
+
+fn save_calls_value(value: Term) -> RtResult<usize> {
+  if !value.is_small() || !(0..=MAX_SAVE_CALLS).contains(&value.get_small_signed()) {
     return fail::create::badarg();
   }
-  let p = unsafe { &mut (*proc_p) };
-  do_erlang_process_flag(p, flag, value)
+  Ok(value.get_small_signed() as usize)
 }
 
 #[inline]
-fn do_erlang_process_flag(p: &mut Process, flag: Term, value: bool) -> RtResult<Term> {
+fn do_erlang_process_flag(p: &mut Process, flag: Term, value: Term) -> RtResult<Term> {
   match flag {
-    gen_atoms::TRAP_EXIT => Ok(Term::make_bool(
+    gen_atoms::TRAP_EXIT if value.is_bool() => Ok(Term::make_bool(
       p.process_flags
-        .read_and_set(process_flags::TRAP_EXIT, value),
+        .read_and_set(process_flags::TRAP_EXIT, value.is_true()),
     )),
+    gen_atoms::MESSAGE_QUEUE_DATA => {
+      let new_location = match value {
+        gen_atoms::ON_HEAP => MessageQueueLocation::OnHeap,
+        gen_atoms::OFF_HEAP => MessageQueueLocation::OffHeap,
+        _ => return fail::create::badarg(),
+      };
+      let old_location = core::mem::replace(&mut p.mailbox.location, new_location);
+      Ok(match old_location {
+        MessageQueueLocation::OnHeap => gen_atoms::ON_HEAP,
+        MessageQueueLocation::OffHeap => gen_atoms::OFF_HEAP,
+      })
+    }
+    gen_atoms::PRIORITY => {
+      let new_prio = Prio::from_term(value)?;
+      Ok(core::mem::replace(&mut p.prio, new_prio).to_term())
+    }
+    gen_atoms::MAX_HEAP_SIZE => {
+      let new_max = MaxHeapSize::from_term(value)?;
+      // Allocate the result first, a full heap fails with no changes made
+      let old_max = p.max_heap_size.to_term(p.get_heap_mut())?;
+      p.max_heap_size = new_max;
+      Ok(old_max)
+    }
+    gen_atoms::SAVE_CALLS => {
+      let old = p.shared.save_calls.swap(save_calls_value(value)?, Ordering::Relaxed);
+      Ok(Term::make_small_unsigned(old))
+    }
     _ => fail::create::badarg_val(flag, p.get_heap_mut()),
   }
 }
@@ -0,0 +1,330 @@
+//! `process_info` of the calling process reads it directly. Another process
+//! may be running on another scheduler thread, so it receives a
+//! `Signal::InfoRequest`, builds the result itself and sends it back in a
+//! `Signal::InfoReply`, while the caller traps and waits for the reply.
+use crate::{
+  defs::SizeWords,
+  emulator::{
+    gen_atoms,
+    heap::{copy_term, owned_term::OwnedTerm, Heap, THeap, THeapOwner},
+    mfa::ModFunArity,
+    process::Process,
+    process_flags,
+    scheduler::SliceResult,
+    signal::{InfoQuery, Signal},
+    vm::VM,
+  },
+  fail::{self, RtErr, RtResult},
+  term::{
+    cons,
+    term_builder::{
+      tuple_builder::{tuple2, tuple3},
+      ListBuilder,
+    },
+    Term,
+  },
+};
+
+/// Items reported by `process_info/1`, `registered_name` goes first if the
+/// process has a name.
+const DEFAULT_ITEMS: [Term; 11] = [
+  gen_atoms::CURRENT_FUNCTION,
+  gen_atoms::STATUS,
+  gen_atoms::MESSAGE_QUEUE_LEN,
+  gen_atoms::LINKS,
+  gen_atoms::DICTIONARY,
+  gen_atoms::TRAP_EXIT,
+  gen_atoms::PRIORITY,
+  gen_atoms::TOTAL_HEAP_SIZE,
+  gen_atoms::HEAP_SIZE,
+  gen_atoms::STACK_SIZE,
+  gen_atoms::REDUCTIONS,
+];
+
+/// An item value read from the inspected process. Terms may still point to
+/// the heap of that process and are copied when the result is built.
+enum InfoValue {
+  Term(Term),
+  Mfa(ModFunArity),
+  List(Vec<Term>),
+  /// Dictionary `{Key, Val}` pairs
+  Pairs(Vec<(Term, Term)>),
+  /// Monitored process pid and the registered name or nil
+  Monitors(Vec<(Term, Term)>),
+}
+
+// Returns a list of `{Item, Value}` for the process or `undefined` if it is
+// not alive.
+// Spec: erlang:process_info(pid)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:process_info/1", struct_name: NfErlangProcessInfo1, arity: 1,
+  invoke: { process_info_1(vm, proc, pid) },
+  args: pid(pid),
+);
+
+pub fn process_info_1(vm: &VM, proc: &mut Process, pid: Term) -> RtResult<Term> {
+  if pid != proc.pid {
+    let name = "erlang:process_info/1";
+    return remote_info(vm, proc, name, &[pid], InfoQuery::Default);
+  }
+  local_info(vm, proc, &InfoQuery::Default)
+}
+
+// Returns `{Item, Value}` for one item, or a list of those for a list of
+// items. Returns `undefined` if the process is not alive.
+// Spec: erlang:process_info(pid, item_or_list)
+define_nativefun!(vm, proc, _args,
+  name: "erlang:process_info/2", struct_name: NfErlangProcessInfo2, arity: 2,
+  invoke: { process_info_2(vm, proc, pid, what) },
+  args: pid(pid), term(what),
+);
+
+pub fn process_info_2(
+  vm: &VM,
+  proc: &mut Process,
+  pid: Term,
+  what: Term,
+) -> RtResult<Term> {
+  let query = if what.is_atom() {
+    InfoQuery::Item(what)
+  } else {
+    let mut items = Vec::new();
+    let tail = cons::for_each(what, |item| {
+      if !item.is_atom() {
+        return fail::create::badarg();
+      }
+      items.push(item);
+      Ok(())
+    })?;
+    if let Some(t) = tail {
+      if t != Term::nil() {
+        return fail::create::badarg();
+      }
+    }
+    InfoQuery::Items(items)
+  };
+  if pid != proc.pid {
+    let name = "erlang:process_info/2";
+    return remote_info(vm, proc, name, &[pid, what], query);
+  }
+  local_info(vm, proc, &query)
+}
+
+fn local_info(vm: &VM, proc: &mut Process, query: &InfoQuery) -> RtResult<Term> {
+  let items = query_items(vm, proc.pid, query);
+  let values = read_items(vm, proc, true, &items)?;
+  build_result(query, &items, values, proc.get_heap_mut())
+}
+
+/// Ask another process for the result and trap until the reply arrives.
+/// `args` are the call arguments, the pid goes first.
+fn remote_info(
+  vm: &VM,
+  proc: &mut Process,
+  name: &'static str,
+  args: &[Term],
+  query: InfoQuery,
+) -> RtResult<Term> {
+  if proc.get_trap_state(name, args).is_none() {
+    let request = Signal::InfoRequest {
+      from: proc.pid,
+      query,
+    };
+    if vm.send_signal(args[0], request).is_err() {
+      return Ok(gen_atoms::UNDEFINED);
+    }
+    return proc.trap(name, args, &[]);
+  }
+  match proc.info_reply.take() {
+    None => proc.trap(name, args, &[]),
+    Some(None) => fail::create::badarg(),
+    Some(Some(reply)) => {
+      match copy_term::copy_shared_to(reply.get(), proc.get_heap_mut()) {
+        Ok(result) => Ok(result),
+        Err(e) => {
+          // Keep the reply for the retry after the GC
+          proc.info_reply = Some(Some(reply));
+          Err(e)
+        }
+      }
+    }
+  }
+}
+
+/// Answer `Signal::InfoRequest` from the process `from`. The reply is `None`
+/// for a bad item.
+pub fn reply_to_request(vm: &VM, proc: &mut Process, from: Term, query: InfoQuery) {
+  let reply = make_reply(vm, proc, &query).ok();
+  let _ = vm.send_signal(from, Signal::InfoReply(reply));
+}
+
+fn make_reply(vm: &VM, proc: &mut Process, query: &InfoQuery) -> RtResult<OwnedTerm> {
+  let items = query_items(vm, proc.pid, query);
+  let mut size = SizeWords::new(64);
+  loop {
+    let values = read_items(vm, proc, false, &items)?;
+    let mut hp = Heap::new_fragment(size);
+    match build_result(query, &items, values, &mut hp) {
+      Ok(result) => return OwnedTerm::new(result),
+      Err(RtErr::HeapIsFull(_)) => size = SizeWords::new(size.words * 2),
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+fn query_items(vm: &VM, pid: Term, query: &InfoQuery) -> Vec<Term> {
+  match query {
+    InfoQuery::Default => {
+      let mut items = Vec::with_capacity(DEFAULT_ITEMS.len() + 1);
+      if vm.processes.registered_name(pid).is_some() {
+        items.push(gen_atoms::REGISTERED_NAME);
+      }
+      items.extend_from_slice(&DEFAULT_ITEMS);
+      items
+    }
+    InfoQuery::Item(item) => vec![*item],
+    InfoQuery::Items(items) => items.clone(),
+  }
+}
+
+/// Read the values for `items` from the process, without allocating on its
+/// heap.
+fn read_items(
+  vm: &VM,
+  target: &mut Process,
+  is_self: bool,
+  items: &[Term],
+) -> RtResult<Vec<InfoValue>> {
+  let mut values = Vec::with_capacity(items.len());
+  for item in items {
+    values.push(read_item(vm, target, is_self, *item)?);
+  }
+  Ok(values)
+}
+
+fn read_item(
+  vm: &VM,
+  target: &mut Process,
+  is_self: bool,
+  item: Term,
+) -> RtResult<InfoValue> {
+  let value = match item {
+    gen_atoms::REGISTERED_NAME => InfoValue::Term(
+      vm.processes
+        .registered_name(target.pid)
+        .unwrap_or(Term::nil()),
+    ),
+    gen_atoms::CURRENT_FUNCTION => {
+      match vm.code_server.code_reverse_lookup(target.context.ip) {
+        Some(mfa) => InfoValue::Mfa(mfa),
+        None => InfoValue::Term(gen_atoms::UNDEFINED),
+      }
+    }
+    gen_atoms::STATUS => InfoValue::Term(if is_self {
+      gen_atoms::RUNNING
+    } else {
+      match target.timeslice_result {
+        SliceResult::InfiniteWait | SliceResult::TimedWait => gen_atoms::WAITING,
+        _ => gen_atoms::RUNNABLE,
+      }
+    }),
+    gen_atoms::MESSAGE_QUEUE_LEN => {
+      InfoValue::Term(Term::make_small_unsigned(target.mailbox.len()))
+    }
+    gen_atoms::MESSAGES => InfoValue::List(target.mailbox.messages()),
+    gen_atoms::LINKS => InfoValue::List(target.links.iter().cloned().collect()),
+    gen_atoms::MONITORS => InfoValue::Monitors(
+      target
+        .monitors
+        .values()
+        .map(|mon| (mon.pid, mon.name))
+        .collect(),
+    ),
+    gen_atoms::DICTIONARY => InfoValue::Pairs(target.dictionary.iter().collect()),
+    gen_atoms::TRAP_EXIT => InfoValue::Term(Term::make_bool(
+      target.process_flags.get(process_flags::TRAP_EXIT),
+    )),
+    gen_atoms::PRIORITY => InfoValue::Term(target.prio.to_term()),
+    gen_atoms::HEAP_SIZE | gen_atoms::STACK_SIZE | gen_atoms::TOTAL_HEAP_SIZE => {
+      let (heap_size, stack_size, total_size) = target.get_heap_sizes();
+      InfoValue::Term(Term::make_small_unsigned(match item {
+        gen_atoms::HEAP_SIZE => heap_size,
+        gen_atoms::STACK_SIZE => stack_size,
+        _ => total_size,
+      }))
+    }
+    gen_atoms::REDUCTIONS => InfoValue::Term(Term::make_small_unsigned(
+      target.context.get_reductions_done(),
+    )),
+    _ => return fail::create::badarg(),
+  };
+  Ok(value)
+}
+
+/// Create the result on `hp`: `{Item, Value}` for one item, otherwise a list
+/// of those.
+fn build_result(
+  query: &InfoQuery,
+  items: &[Term],
+  values: Vec<InfoValue>,
+  hp: &mut dyn THeap,
+) -> RtResult<Term> {
+  if let InfoQuery::Item(what) = query {
+    let value = values.into_iter().next().unwrap();
+    // A process without a name has no registered_name item
+    if *what == gen_atoms::REGISTERED_NAME {
+      if let InfoValue::Term(t) = value {
+        if t == Term::nil() {
+          return Ok(t);
+        }
+      }
+    }
+    let val = build_value(value, hp)?;
+    return tuple2(hp, *what, val);
+  }
+  let mut lb = ListBuilder::new()?;
+  for (item, value) in items.iter().zip(values) {
+    let val = build_value(value, hp)?;
+    let pair = tuple2(hp, *item, val)?;
+    unsafe { lb.append(pair, hp)? };
+  }
+  Ok(lb.make_term())
+}
+
+/// Create the value term on `hp`. Terms from the process heap are not copied,
+/// the caller heap is the same heap or the reply is copied as a whole.
+fn build_value(value: InfoValue, hp: &mut dyn THeap) -> RtResult<Term> {
+  match value {
+    InfoValue::Term(t) => Ok(t),
+    InfoValue::Mfa(mfa) => tuple3(hp, mfa.m, mfa.f, Term::make_small_unsigned(mfa.arity)),
+    InfoValue::List(terms) => {
+      let mut lb = ListBuilder::new()?;
+      for t in terms {
+        unsafe { lb.append(t, hp)? };
+      }
+      Ok(lb.make_term())
+    }
+    InfoValue::Pairs(pairs) => {
+      let mut lb = ListBuilder::new()?;
+      for (k, v) in pairs {
+        let pair = tuple2(hp, k, v)?;
+        unsafe { lb.append(pair, hp)? };
+      }
+      Ok(lb.make_term())
+    }
+    InfoValue::Monitors(monitors) => {
+      let mut lb = ListBuilder::new()?;
+      for (mon_pid, name) in monitors {
+        // Monitors created by name report `{Name, Node}`
+        let target = if name == Term::nil() {
+          mon_pid
+        } else {
+          tuple2(hp, name, gen_atoms::NONODE_AT_NOHOST)?
+        };
+        let mon = tuple2(hp, gen_atoms::PROCESS, target)?;
+        unsafe { lb.append(mon, hp)? };
+      }
+      Ok(lb.make_term())
+    }
+  }
+}
@@ -1,9 +1,10 @@
 use crate::{
   defs::exc_type::ExceptionType,
-  emulator::{gen_atoms, heap::THeapOwner, process::Process},
-  fail::{RtErr, RtResult},
-  term::{builders::make_badfun_n, term_builder::tuple_builder::tuple2, Term},
+  emulator::{gen_atoms, heap::THeapOwner, process::Process, vm::VM},
+  fail::{self, RtErr, RtResult},
+  term::{builders::make_badfun_n, cons, term_builder::tuple_builder::tuple2, Term},
 };
+use core::{convert::TryFrom, sync::atomic::Ordering};
 
 #[allow(dead_code)]
 fn module() -> &'static str {
@@ -51,6 +52,173 @@ define_nativefun!(_vm, _proc, args,
   args: term(reason),
 );
 
+// Create an exception of type `exit`.
+define_nativefun!(_vm, _proc, args,
+  name: "erlang:exit/1", struct_name: NfErlangExit1, arity: 1,
+  invoke: { Err(RtErr::Exception(ExceptionType::Exit, reason)) },
+  args: term(reason),
+);
+
+// Create an exception of type `throw`.
+define_nativefun!(_vm, _proc, args,
+  name: "erlang:throw/1", struct_name: NfErlangThrow1, arity: 1,
+  invoke: { Err(RtErr::Exception(ExceptionType::Throw, reason)) },
+  args: term(reason),
+);
+
+// Raise an exception of the given class with a stacktrace, usually the one
+// which was caught earlier.
+// Spec: erlang:raise(class, reason, stacktrace)
+define_nativefun!(_vm, proc, args,
+  name: "erlang:raise/3", struct_name: NfErlangRaise3, arity: 3,
+  invoke: { raise_3(proc, class, reason, stacktrace) },
+  args: atom(class), term(reason), term(stacktrace),
+);
+
+pub fn raise_3(
+  proc: &mut Process,
+  class: Term,
+  reason: Term,
+  stacktrace: Term,
+) -> RtResult<Term> {
+  let exc_type = match class {
+    gen_atoms::ERROR => ExceptionType::Error,
+    gen_atoms::EXIT => ExceptionType::Exit,
+    gen_atoms::THROW => ExceptionType::Throw,
+    _ => return fail::create::badarg(),
+  };
+  if !stacktrace.is_list() {
+    return fail::create::badarg();
+  }
+  if let Some(tail) = cons::for_each(stacktrace, |_| Ok(()))? {
+    if tail != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  proc.stacktrace = stacktrace;
+  Err(RtErr::Exception(exc_type, reason))
+}
+
+// Stop the emulator with exit status 0.
+define_nativefun!(_vm, _proc, _args,
+  name: "erlang:halt/0", struct_name: NfErlangHalt0, arity: 0,
+  invoke: { Err(RtErr::Halt(0)) },
+  args:
+);
+
+// Stop the emulator with an integer exit status, or with a crash slogan
+// string (exit status 1).
+// Spec: erlang:halt(status)
+define_nativefun!(_vm, _proc, _args,
+  name: "erlang:halt/1", struct_name: NfErlangHalt1, arity: 1,
+  invoke: { Err(RtErr::Halt(halt_status(status)?)) },
+  args: term(status),
+);
+
+// Spec: erlang:halt(status, options)
+define_nativefun!(_vm, _proc, _args,
+  name: "erlang:halt/2", struct_name: NfErlangHalt2, arity: 2,
+  invoke: {
+    let status = halt_status(status)?;
+    halt_check_options(options)?;
+    Err(RtErr::Halt(status))
+  },
+  args: term(status), list(options),
+);
+
+fn halt_status(status: Term) -> RtResult<i32> {
+  if status.is_small() {
+    return match i32::try_from(status.get_small_signed()) {
+      Ok(code) if code >= 0 => Ok(code),
+      _ => fail::create::badarg(),
+    };
+  }
+  // A crash slogan is a string
+  let mut slogan = String::new();
+  if !status.is_list() {
+    return fail::create::badarg();
+  }
+  let tail = cons::for_each(status, |ch| {
+    let code = if ch.is_small() { ch.get_small_signed() } else { -1 };
+    match u32::try_from(code).ok().and_then(char::from_u32) {
+      Some(c) => slogan.push(c),
+      None => return fail::create::badarg(),
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  println!("halt: {slogan}");
+  Ok(1)
+}
+
+/// Only `{flush, boolean()}` is accepted. Output is always flushed before the
+/// emulator exits.
+fn halt_check_options(options: Term) -> RtResult<()> {
+  let tail = cons::for_each(options, |opt| {
+    if !opt.is_tuple() || opt == Term::empty_tuple() {
+      return fail::create::badarg();
+    }
+    let tuple_p = opt.get_tuple_ptr();
+    unsafe {
+      if (*tuple_p).get_arity() != 2
+        || (*tuple_p).get_element(0) != gen_atoms::FLUSH
+        || !(*tuple_p).get_element(1).is_bool()
+      {
+        return fail::create::badarg();
+      }
+    }
+    Ok(())
+  })?;
+  match tail {
+    Some(t) if t != Term::nil() => fail::create::badarg(),
+    _ => Ok(()),
+  }
+}
+
+// Charge the calling process extra reductions, it may be scheduled out sooner.
+define_nativefun!(_vm, proc, args,
+  name: "erlang:bump_reductions/1", struct_name: NfErlangBumpReductions1, arity: 1,
+  invoke: {
+    if !n.is_small() || n.get_small_signed() < 1 {
+      return fail::create::badarg();
+    }
+    proc.context.consume_reductions(n.get_small_signed());
+    Ok(gen_atoms::TRUE)
+  },
+  args: term(n),
+);
+
+// Returns `{Total, SinceLastCall}` for `reductions`, other items are not
+// supported.
+define_nativefun!(vm, proc, args,
+  name: "erlang:statistics/1", struct_name: NfErlangStatistics1, arity: 1,
+  invoke: { statistics_1(vm, proc, item) },
+  args: atom(item),
+);
+
+fn statistics_1(vm: &VM, proc: &mut Process, item: Term) -> RtResult<Term> {
+  if item != gen_atoms::REDUCTIONS {
+    return fail::create::badarg();
+  }
+  // The current timeslice is added to the total when it ends
+  let finished = vm.reductions_total.load(Ordering::Relaxed);
+  let total = finished + proc.context.get_slice_reductions();
+  // Another scheduler may have counted its running timeslice in the last call
+  let last = vm.reductions_last_stat.load(Ordering::Relaxed);
+  let since_last = total.saturating_sub(last);
+  let result = tuple2(
+    proc.get_heap_mut(),
+    Term::make_small_unsigned(total),
+    Term::make_small_unsigned(since_last),
+  )?;
+  vm.reductions_last_stat.store(total, Ordering::Relaxed);
+  Ok(result)
+}
+
 // Make a nice face like we are loading something here
 // TODO: Implement pre-linked NIF modules which are ready to be activated
 define_nativefun!(_vm, _proc, args,
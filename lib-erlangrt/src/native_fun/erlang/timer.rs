@@ -0,0 +1,192 @@
+use crate::{
+  emulator::{gen_atoms, heap::THeapOwner, process::Process, vm::VM},
+  fail::{self, RtResult},
+  term::{boxed, cons, term_builder::tuple_builder::tuple3, Term},
+};
+
+/// Parsed options list of the timer functions.
+struct TimerOptions {
+  /// `{abs, true}`: time is an absolute value of the monotonic clock
+  abs: bool,
+  /// `{async, true}`: result is sent as a message and `ok` is returned
+  async_reply: bool,
+  /// `{info, false}`: no result is returned or sent
+  info: bool,
+}
+
+/// Parse a list of `{Option, Bool}` pairs. Option names not in `allowed` and
+/// improper lists cause `badarg`.
+fn parse_timer_options(opts: Term, allowed: &[Term]) -> RtResult<TimerOptions> {
+  let mut result = TimerOptions {
+    abs: false,
+    async_reply: false,
+    info: true,
+  };
+  let tail = cons::for_each(opts, |opt| {
+    if !opt.is_tuple() || opt == Term::empty_tuple() {
+      return fail::create::badarg();
+    }
+    let tuple_p = opt.get_tuple_ptr();
+    let (key, val) = unsafe {
+      if (*tuple_p).get_arity() != 2 {
+        return fail::create::badarg();
+      }
+      ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+    };
+    if !val.is_bool() || !allowed.contains(&key) {
+      return fail::create::badarg();
+    }
+    match key {
+      gen_atoms::ABS => result.abs = val.is_true(),
+      gen_atoms::ASYNC => result.async_reply = val.is_true(),
+      gen_atoms::INFO => result.info = val.is_true(),
+      _ => unreachable!(),
+    }
+    Ok(())
+  })?;
+  match tail {
+    Some(t) if t != Term::nil() => fail::create::badarg(),
+    _ => Ok(result),
+  }
+}
+
+/// Timer reference argument must be a local reference.
+#[inline]
+fn timer_ref_id(tref: Term) -> RtResult<usize> {
+  if !tref.is_local_ref() {
+    return fail::create::badarg();
+  }
+  Ok(boxed::LocalRef::get_id(tref))
+}
+
+/// Time left as returned to the user, or `false` if there is no such timer.
+#[inline]
+fn time_left_to_term(left: Option<u64>) -> Term {
+  match left {
+    Some(ms) => Term::make_small_unsigned(ms as usize),
+    None => gen_atoms::FALSE,
+  }
+}
+
+// Starts a timer, which sends `msg` to `dest` after `time` milliseconds.
+// Spec: erlang:send_after(time, dest:pid|atom, msg) -> reference
+define_nativefun!(vm, proc, _args,
+  name: "erlang:send_after/3", struct_name: NfErlangSendAfter3, arity: 3,
+  invoke: { start_timer(vm, proc, time, dest, msg, Term::nil(), false) },
+  args: term(time), term(dest), term(msg),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:send_after/4", struct_name: NfErlangSendAfter4, arity: 4,
+  invoke: { start_timer(vm, proc, time, dest, msg, opts, false) },
+  args: term(time), term(dest), term(msg), list(opts),
+);
+
+// Starts a timer, which sends `{timeout, TRef, msg}` to `dest` after `time`
+// milliseconds.
+// Spec: erlang:start_timer(time, dest:pid|atom, msg) -> reference
+define_nativefun!(vm, proc, _args,
+  name: "erlang:start_timer/3", struct_name: NfErlangStartTimer3, arity: 3,
+  invoke: { start_timer(vm, proc, time, dest, msg, Term::nil(), true) },
+  args: term(time), term(dest), term(msg),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:start_timer/4", struct_name: NfErlangStartTimer4, arity: 4,
+  invoke: { start_timer(vm, proc, time, dest, msg, opts, true) },
+  args: term(time), term(dest), term(msg), list(opts),
+);
+
+fn start_timer(
+  vm: &VM,
+  proc: &mut Process,
+  time: Term,
+  dest: Term,
+  msg: Term,
+  opts: Term,
+  wrap_timeout: bool,
+) -> RtResult<Term> {
+  if !dest.is_local_pid() && !dest.is_atom() {
+    return fail::create::badarg();
+  }
+  if !time.is_small() || time.get_small_signed() < 0 {
+    return fail::create::badarg();
+  }
+  let options = parse_timer_options(opts, &[gen_atoms::ABS])?;
+  let time_ms = time.get_small_signed() as u64;
+  let timeout_ms = if options.abs {
+    time_ms.saturating_sub(vm.timers.lock().unwrap().now())
+  } else {
+    time_ms
+  };
+
+  let hp = proc.get_heap_mut();
+  let tref = vm.make_ref(hp)?;
+  let message = if wrap_timeout {
+    tuple3(hp, gen_atoms::TIMEOUT, tref, msg)?
+  } else {
+    msg
+  };
+  let ref_id = boxed::LocalRef::get_id(tref);
+  vm.timers.lock().unwrap().start(ref_id, timeout_ms, dest, message)?;
+  Ok(tref)
+}
+
+// Cancels a timer, returns milliseconds left or `false` if the timer did not
+// exist or has already expired.
+// Spec: erlang:cancel_timer(tref) -> integer | false
+define_nativefun!(vm, proc, _args,
+  name: "erlang:cancel_timer/1", struct_name: NfErlangCancelTimer1, arity: 1,
+  invoke: { cancel_timer(vm, proc, tref, Term::nil()) },
+  args: term(tref),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:cancel_timer/2", struct_name: NfErlangCancelTimer2, arity: 2,
+  invoke: { cancel_timer(vm, proc, tref, opts) },
+  args: term(tref), list(opts),
+);
+
+fn cancel_timer(vm: &VM, proc: &mut Process, tref: Term, opts: Term) -> RtResult<Term> {
+  let ref_id = timer_ref_id(tref)?;
+  let options = parse_timer_options(opts, &[gen_atoms::ASYNC, gen_atoms::INFO])?;
+  if options.info && options.async_reply {
+    // Build the reply before cancelling, so that a heap retry finds the timer
+    let mut timers = vm.timers.lock().unwrap();
+    let left = time_left_to_term(timers.time_left(ref_id));
+    let reply = tuple3(proc.get_heap_mut(), gen_atoms::CANCEL_TIMER, tref, left)?;
+    timers.cancel(ref_id);
+    drop(timers);
+    proc.deliver_message(reply)?;
+    return Ok(gen_atoms::OK);
+  }
+  let left = time_left_to_term(vm.timers.lock().unwrap().cancel(ref_id));
+  Ok(if options.info { left } else { gen_atoms::OK })
+}
+
+// Reads the time left for a timer, or `false` if the timer did not exist or
+// has already expired.
+// Spec: erlang:read_timer(tref) -> integer | false
+define_nativefun!(vm, proc, _args,
+  name: "erlang:read_timer/1", struct_name: NfErlangReadTimer1, arity: 1,
+  invoke: { read_timer(vm, proc, tref, Term::nil()) },
+  args: term(tref),
+);
+
+define_nativefun!(vm, proc, _args,
+  name: "erlang:read_timer/2", struct_name: NfErlangReadTimer2, arity: 2,
+  invoke: { read_timer(vm, proc, tref, opts) },
+  args: term(tref), list(opts),
+);
+
+fn read_timer(vm: &VM, proc: &mut Process, tref: Term, opts: Term) -> RtResult<Term> {
+  let ref_id = timer_ref_id(tref)?;
+  let options = parse_timer_options(opts, &[gen_atoms::ASYNC])?;
+  let left = time_left_to_term(vm.timers.lock().unwrap().time_left(ref_id));
+  if options.async_reply {
+    let reply = tuple3(proc.get_heap_mut(), gen_atoms::READ_TIMER, tref, left)?;
+    proc.deliver_message(reply)?;
+    return Ok(gen_atoms::OK);
+  }
+  Ok(left)
+}
@@ -1,7 +1,9 @@
 use crate::{
+  defs::{Reductions, SizeBytes},
   emulator::{atom, heap::THeapOwner, process::Process},
   fail::{self, RtResult},
-  term::{cons, term_builder::BinaryBuilder, Term},
+  rt_util::ext_term_format,
+  term::{boxed, cons, term_builder::BinaryBuilder, Term},
 };
 
 // Converts an atom to Erlang string.
@@ -38,40 +40,144 @@ pub fn integer_to_list_1(curr_p: &mut Process, val: Term) -> RtResult<Term> {
   unsafe { cons::integer_to_list(val, curr_p.get_heap_mut()) }
 }
 
-// Returns list `list` reversed with `tail` appended (any term).
+// Encodes a term to the external term format.
 define_nativefun!(_vm, proc, args,
-  name: "erlang:list_to_binary/1", struct_name: NfErlangL2b1, arity: 1,
-  invoke: { unsafe { list_to_binary_1(proc, list) } },
-  args: list(list),
+  name: "erlang:term_to_binary/1", struct_name: NfErlangT2b1, arity: 1,
+  invoke: { term_to_binary_1(proc, val) },
+  args: term(val),
 );
 
 #[inline]
-unsafe fn list_to_binary_1(proc: &mut Process, list: Term) -> RtResult<Term> {
-  let size = cons::get_iolist_size(list);
-  if size.bytes() == 0 {
-    Ok(Term::empty_binary())
-  } else {
-    let mut bb = BinaryBuilder::with_size(size, proc.get_heap_mut())?;
-    list_to_binary_1_recursive(&mut bb, list)?;
-    Ok(bb.make_term())
-  }
+fn term_to_binary_1(proc: &mut Process, val: Term) -> RtResult<Term> {
+  let data = ext_term_format::encode(val).or_else(|_| fail::create::badarg())?;
+  let bin_p = unsafe { boxed::Binary::create_with_data(&data, proc.get_heap_mut())? };
+  proc.context.consume_native_work(data.len());
+  Ok(unsafe { (*bin_p).make_term() })
 }
 
-unsafe fn list_to_binary_1_recursive(
-  bb: &mut BinaryBuilder,
+// Converts an iolist to a binary. Long lists are converted over several
+// timeslices: first the size is counted, then the bytes are written.
+define_nativefun!(_vm, proc, args,
+  name: "erlang:list_to_binary/1", struct_name: NfErlangL2b1, arity: 1,
+  invoke: { unsafe { list_to_binary_1(proc, args, list) } },
+  args: list(list),
+);
+
+unsafe fn list_to_binary_1(
+  proc: &mut Process,
+  args: &[Term],
   list: Term,
 ) -> RtResult<Term> {
-  cons::for_each(list, |elem| {
-    if elem.is_small() {
-      // Any small integer even larger than 256 counts as 1 byte
-      bb.write_byte(elem.get_small_unsigned() as u8);
-    } else if elem.is_binary() {
-      unimplemented!("l2b: appending a binary");
-    // bb.write_binary(elem.get_box_ptr());
-    } else if elem.is_cons() {
-      list_to_binary_1_recursive(bb, elem)?;
+  // Saved state: the binary being written or NIL while the size is counted,
+  // the byte count or write position, and the stack of the iolist walk
+  let (mut bin, mut count, mut stack) =
+    match proc.get_trap_state("erlang:list_to_binary/1", args) {
+      Some(state) => (state[0], state[1].get_small_unsigned(), state[2..].to_vec()),
+      None => (Term::nil(), 0, vec![list]),
+    };
+  let budget = proc.context.get_native_work_budget();
+  let mut work = 0;
+
+  if bin == Term::nil() {
+    let done = walk_iolist(&mut stack, budget, &mut work, &mut |item| {
+      count += if item.is_small() {
+        1
+      } else {
+        item.binary_byte_size().bytes()
+      };
+    })?;
+    if !done {
+      proc.context.consume_native_work(work);
+      return trap_list_to_binary(proc, args, bin, count, &stack);
+    }
+    if count == 0 {
+      proc.context.consume_native_work(work);
+      return Ok(Term::empty_binary());
+    }
+    let bb = BinaryBuilder::with_size(SizeBytes::new(count), proc.get_heap_mut())?;
+    bin = bb.make_term();
+    count = 0;
+    stack = vec![list];
+  }
+
+  let mut bb = BinaryBuilder::continue_from(bin, count);
+  let done = walk_iolist(&mut stack, budget, &mut work, &mut |item| {
+    if item.is_small() {
+      bb.write_byte(item.get_small_unsigned() as u8);
+    } else {
+      bb.write_bytes((*boxed::Binary::get_trait_from_term(item)).get_data());
     }
-    Ok(())
   })?;
-  Ok(list)
+  count = bb.get_pos();
+  proc.context.consume_native_work(work);
+  if !done {
+    return trap_list_to_binary(proc, args, bin, count, &stack);
+  }
+  Ok(bin)
+}
+
+fn trap_list_to_binary(
+  proc: &mut Process,
+  args: &[Term],
+  bin: Term,
+  count: usize,
+  stack: &[Term],
+) -> RtResult<Term> {
+  let mut state = vec![bin, Term::make_small_unsigned(count)];
+  state.extend_from_slice(stack);
+  proc.trap("erlang:list_to_binary/1", args, &state)
+}
+
+/// Walks an iolist depth first until it ends or `work` reaches `budget`,
+/// `stack` holds the unvisited rest of every nested list. Bytes and non-empty
+/// binaries are given to `emit`. Returns whether the walk has finished.
+unsafe fn walk_iolist(
+  stack: &mut Vec<Term>,
+  budget: usize,
+  work: &mut usize,
+  emit: &mut dyn FnMut(Term),
+) -> RtResult<bool> {
+  while *work < budget {
+    let item = match stack.last_mut() {
+      None => return Ok(true),
+      Some(top) => {
+        if top.is_cons() {
+          let cell = top.get_cons_ptr();
+          *top = (*cell).tl();
+          (*cell).hd()
+        } else {
+          // Tail of the list, NIL or a binary
+          let tail = *top;
+          stack.pop();
+          if tail != Term::nil() && !tail.is_binary() {
+            return fail::create::badarg();
+          }
+          tail
+        }
+      }
+    };
+    *work += 1;
+    if item.is_cons() {
+      stack.push(item);
+    } else if item.is_small() {
+      if item.get_small_signed() < 0 || item.get_small_signed() > 255 {
+        return fail::create::badarg();
+      }
+      emit(item);
+    } else if item.is_binary() {
+      if item == Term::empty_binary() {
+        continue;
+      }
+      let binp = boxed::Binary::get_trait_from_term(item);
+      let size = (*binp).get_bit_size();
+      if !size.bits.is_multiple_of(8) {
+        return fail::create::badarg();
+      }
+      *work += size.bits / 8 / Reductions::NATIVE_WORK_PER_REDUCTION;
+      emit(item);
+    } else if item != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  Ok(stack.is_empty())
 }
@@ -1,8 +1,8 @@
 //! Implements misc and general purpose list operations.
 use crate::{
   emulator::{heap::THeapOwner, process::Process},
-  fail::RtResult,
-  term::{compare, term_builder::ListBuilder, *},
+  fail::{self, RtResult},
+  term::{compare, *},
 };
 use core::cmp::Ordering;
 
@@ -27,21 +27,34 @@ fn member_2(sample: Term, list: Term) -> RtResult<Term> {
   Ok(Term::make_bool(result))
 }
 
-// Returns list `list` reversed with `tail` appended (any term).
+// Returns list `list` reversed with `tail` appended (any term). Long lists
+// are reversed over several timeslices.
 define_nativefun!(_vm, proc, args,
   name: "lists:reverse/2", struct_name: NfListsReverse2, arity: 2,
-  invoke: { unsafe { reverse_2(proc, list, tail) } },
+  invoke: { unsafe { reverse_2(proc, args, list, tail) } },
   args: list(list), term(tail),
 );
 
-#[inline]
-unsafe fn reverse_2(proc: &mut Process, list: Term, tail: Term) -> RtResult<Term> {
-  let mut lb = ListBuilder::new()?;
-  let hp = proc.get_heap_mut();
-
-  // Going forward the list, prepend values to the result
-  cons::for_each(list, |elem| lb.prepend(elem, hp))?;
-
-  // Last element's tail in the new list is set to `tail` argument
-  Ok(lb.make_term_with_tail(tail))
+unsafe fn reverse_2(
+  proc: &mut Process,
+  args: &[Term],
+  list: Term,
+  tail: Term,
+) -> RtResult<Term> {
+  // Saved state: the rest of the list and the result so far, which starts
+  // with `tail`. Cells are only prepended, see `cons::reverse_onto`.
+  let (rest, acc) = match proc.get_trap_state("lists:reverse/2", args) {
+    Some(state) => (state[0], state[1]),
+    None => (list, tail),
+  };
+  let budget = proc.context.get_native_work_budget();
+  let (rest, acc, work) = cons::reverse_onto(rest, acc, budget, proc.get_heap_mut())?;
+  proc.context.consume_native_work(work);
+  if rest.is_cons() {
+    return proc.trap("lists:reverse/2", args, &[rest, acc]);
+  }
+  if rest != Term::nil() {
+    return fail::create::badarg();
+  }
+  Ok(acc)
 }
@@ -35,7 +35,7 @@ macro_rules! define_nativefun {
     pub struct $struct_name {}
     impl $struct_name {
       pub fn _f(
-        $vmvar: &mut crate::emulator::vm::VM,
+        $vmvar: &crate::emulator::vm::VM,
         $procvar: &mut crate::emulator::process::Process,
         $argsvar: &[Term],
       ) -> crate::fail::RtResult<Term> {
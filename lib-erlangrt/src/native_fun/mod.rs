@@ -22,7 +22,7 @@ pub mod lists;
 /// In case of error the `NON_VALUE` should be returned and the process is
 /// informed about error situation (error reason and type are set etc).
 pub type NativeFn =
-  fn(vm: &mut VM, cur_proc: &mut Process, args: &[Term]) -> RtResult<Term>;
+  fn(vm: &VM, cur_proc: &mut Process, args: &[Term]) -> RtResult<Term>;
 
 #[inline]
 pub fn assert_arity(fn_name: &str, have_arity: usize, args: &[Term]) {
@@ -1,7 +1,7 @@
 use super::bin_reader::BinaryReader;
 use crate::{
-  defs::{SWord, Word},
-  emulator::{atom, heap::THeap},
+  defs::{SWord, TDataReader, Word},
+  emulator::{atom, gen_atoms, heap::THeap},
   fail::{RtErr, RtResult},
   term::{
     boxed::{self, bignum::sign::Sign},
@@ -16,8 +16,10 @@ enum Tag {
   /// Always goes first in an external term format blob
   ExtTermFormatPrefix = 131,
   NewFloat = 70,
+  NewPid = 88,
   BitBinary = 77,
   AtomCacheRef_ = 82,
+  NewerReference = 90,
   SmallInteger = 97,
   Integer = 98,
   Float = 99,
@@ -73,12 +75,24 @@ pub fn decode_naked(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term>
 
     x if x == Tag::AtomDeprecated as u8 => decode_atom_latin1(r, hp),
 
+    x if x == Tag::AtomUtf8 as u8 => {
+      let size = r.read_u16be() as Word;
+      decode_atom_utf8(r, size)
+    }
+
+    x if x == Tag::SmallAtomUtf8 as u8 => {
+      let size = r.read_u8() as Word;
+      decode_atom_utf8(r, size)
+    }
+
     x if x == Tag::SmallInteger as u8 => decode_u8(r, hp),
 
     x if x == Tag::Integer as u8 => decode_s32(r, hp),
 
     x if x == Tag::Nil as u8 => Ok(Term::nil()),
 
+    x if x == Tag::NewFloat as u8 => decode_float(r, hp),
+
     x if x == Tag::LargeTuple as u8 => {
       let size = r.read_u32be() as Word;
       decode_tuple(r, size, hp)
@@ -101,6 +115,12 @@ pub fn decode_naked(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term>
 
     x if x == Tag::Binary as u8 => decode_binary(r, hp),
 
+    x if x == Tag::NewReference as u8 => decode_reference(r, false, hp),
+
+    x if x == Tag::NewerReference as u8 => decode_reference(r, true, hp),
+
+    x if x == Tag::NewPid as u8 => decode_pid(r, hp),
+
     x if x == Tag::Map as u8 => {
       let size = r.read_u32be() as Word;
       decode_map(r, size, hp)
@@ -159,6 +179,13 @@ fn decode_map(r: &mut BinaryReader, size: usize, hp: &mut dyn THeap) -> RtResult
   Ok(Term::make_boxed(map_ptr))
 }
 
+/// A float stored as 8 bytes big-endian IEEE 754.
+fn decode_float(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term> {
+  let hi = r.read_u32be() as u64;
+  let lo = r.read_u32be() as u64;
+  Term::make_float(hp, f64::from_bits((hi << 32) | lo))
+}
+
 fn decode_u8(r: &mut BinaryReader, _hp: &mut dyn THeap) -> RtResult<Term> {
   let val = r.read_u8();
   Ok(Term::make_small_signed(val as SWord))
@@ -171,8 +198,54 @@ fn decode_s32(r: &mut BinaryReader, _hp: &mut dyn THeap) -> RtResult<Term> {
 
 fn decode_atom_latin1(r: &mut BinaryReader, _hp: &mut dyn THeap) -> RtResult<Term> {
   let sz = r.read_u16be();
-  let val = r.read_str_latin1(sz as Word).unwrap();
-  Ok(atom::from_str(&val))
+  match r.read_str_latin1(sz as Word) {
+    Ok(val) => Ok(atom::from_str(&val)),
+    Err(e) => fail(format!("{}Bad latin-1 atom: {:?}", module(), e)),
+  }
+}
+
+fn decode_atom_utf8(r: &mut BinaryReader, size: Word) -> RtResult<Term> {
+  match r.read_str_utf8(size) {
+    Ok(val) => Ok(atom::from_str(&val)),
+    Err(e) => fail(format!("{}Bad UTF-8 atom: {:?}", module(), e)),
+  }
+}
+
+/// Decode a reference. The newer format has a 32-bit creation. Only local
+/// references (node `nonode@nohost`) are supported.
+fn decode_reference(
+  r: &mut BinaryReader,
+  newer: bool,
+  hp: &mut dyn THeap,
+) -> RtResult<Term> {
+  let n_words = r.read_u16be();
+  let node = decode_naked(r, hp)?;
+  if newer {
+    r.read_u32be();
+  } else {
+    r.read_u8();
+  }
+  let words: Vec<u32> = (0..n_words).map(|_| r.read_u32be()).collect();
+  if node != gen_atoms::NONODE_AT_NOHOST {
+    let msg = format!("{}External reference for node {} is not supported", module(), node);
+    return fail(msg);
+  }
+  boxed::LocalRef::create_into(hp, boxed::LocalRef::id_from_words(&words))
+}
+
+/// Decode a pid in the new format (32-bit creation). Only local pids are
+/// supported.
+fn decode_pid(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term> {
+  let node = decode_naked(r, hp)?;
+  let id = r.read_u32be();
+  // Serial and creation
+  r.read_u32be();
+  r.read_u32be();
+  if node != gen_atoms::NONODE_AT_NOHOST {
+    let msg = format!("{}External pid for node {} is not supported", module(), node);
+    return fail(msg);
+  }
+  Ok(Term::make_local_pid(id as Word))
 }
 
 fn decode_list(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term> {
@@ -214,3 +287,238 @@ fn decode_string(r: &mut BinaryReader, hp: &mut dyn THeap) -> RtResult<Term> {
 
   Ok(lb.make_term())
 }
+
+/// Encode a term to the external term format, beginning with the ETF tag.
+/// Only local pids and references are supported, with node `nonode@nohost`.
+pub fn encode(t: Term) -> RtResult<Vec<u8>> {
+  let mut out = vec![Tag::ExtTermFormatPrefix as u8];
+  encode_naked(t, &mut out)?;
+  Ok(out)
+}
+
+/// Encode a term without the ETF tag (131u8) and append it to `out`.
+pub fn encode_naked(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  if t.is_small() {
+    encode_small(t.get_small_signed(), out);
+  } else if t.is_atom() {
+    encode_atom(t, out)?;
+  } else if t == Term::nil() {
+    out.push(Tag::Nil as u8);
+  } else if t.is_cons() {
+    unsafe { encode_list(t, out)? };
+  } else if t.is_tuple() {
+    unsafe { encode_tuple(t, out)? };
+  } else if t.is_binary() {
+    unsafe { encode_binary(t, out) };
+  } else if t.is_float() {
+    out.push(Tag::NewFloat as u8);
+    out.extend_from_slice(&unsafe { t.get_float_unchecked() }.to_be_bytes());
+  } else if t.is_big_int() {
+    let big_p = t.get_box_ptr::<boxed::Bignum>();
+    unsafe { encode_big((*big_p).is_negative(), (*big_p).get_digits(), out) };
+  } else if t.is_map() {
+    unsafe { encode_map(t, out)? };
+  } else if t.is_local_ref() {
+    encode_reference(boxed::LocalRef::get_id(t), out)?;
+  } else if t.is_local_pid() {
+    out.push(Tag::NewPid as u8);
+    encode_atom(gen_atoms::NONODE_AT_NOHOST, out)?;
+    out.extend_from_slice(&(t.get_term_val_without_tag() as u32).to_be_bytes());
+    // Serial and creation
+    out.extend_from_slice(&[0; 8]);
+  } else {
+    let msg = format!("{}Encoding {} is not supported", module(), t);
+    return fail(msg);
+  }
+  Ok(())
+}
+
+fn encode_small(val: SWord, out: &mut Vec<u8>) {
+  if (0..256).contains(&val) {
+    out.push(Tag::SmallInteger as u8);
+    out.push(val as u8);
+  } else if val >= i32::MIN as SWord && val <= i32::MAX as SWord {
+    out.push(Tag::Integer as u8);
+    out.extend_from_slice(&(val as i32).to_be_bytes());
+  } else {
+    encode_big(val < 0, &[val.unsigned_abs()], out);
+  }
+}
+
+/// Big integer digits are stored as little-endian bytes.
+fn encode_big(negative: bool, digits: &[Word], out: &mut Vec<u8>) {
+  let mut bytes: Vec<u8> = digits.iter().flat_map(|d| d.to_le_bytes()).collect();
+  while bytes.last() == Some(&0) {
+    bytes.pop();
+  }
+  if bytes.len() < 256 {
+    out.push(Tag::SmallBig as u8);
+    out.push(bytes.len() as u8);
+  } else {
+    out.push(Tag::LargeBig as u8);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+  }
+  out.push(negative as u8);
+  out.extend_from_slice(&bytes);
+}
+
+fn encode_atom(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  let name = atom::to_str(t)?;
+  if name.len() < 256 {
+    out.push(Tag::SmallAtomUtf8 as u8);
+    out.push(name.len() as u8);
+  } else {
+    out.push(Tag::AtomUtf8 as u8);
+    out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+  }
+  out.extend_from_slice(name.as_bytes());
+  Ok(())
+}
+
+unsafe fn encode_list(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  out.push(Tag::List as u8);
+  let count_pos = out.len();
+  out.extend_from_slice(&[0; 4]);
+  let mut count = 0u32;
+  let mut cell = t;
+  while cell.is_cons() {
+    let cons_p = cell.get_cons_ptr();
+    encode_naked((*cons_p).hd(), out)?;
+    count += 1;
+    cell = (*cons_p).tl();
+  }
+  // The tail, NIL for proper lists
+  encode_naked(cell, out)?;
+  out[count_pos..count_pos + 4].copy_from_slice(&count.to_be_bytes());
+  Ok(())
+}
+
+unsafe fn encode_tuple(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  if t == Term::empty_tuple() {
+    out.extend_from_slice(&[Tag::SmallTuple as u8, 0]);
+    return Ok(());
+  }
+  let tuple_p = t.get_tuple_ptr();
+  let arity = (*tuple_p).get_arity();
+  if arity < 256 {
+    out.push(Tag::SmallTuple as u8);
+    out.push(arity as u8);
+  } else {
+    out.push(Tag::LargeTuple as u8);
+    out.extend_from_slice(&(arity as u32).to_be_bytes());
+  }
+  for i in 0..arity {
+    encode_naked((*tuple_p).get_element(i), out)?;
+  }
+  Ok(())
+}
+
+unsafe fn encode_map(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  let map_p = t.get_box_ptr_mut::<boxed::Map>();
+  let pairs = boxed::Map::get_pairs_mut(map_p);
+  out.push(Tag::Map as u8);
+  out.extend_from_slice(&((pairs.len() / 2) as u32).to_be_bytes());
+  for kv in pairs.iter() {
+    encode_naked(*kv, out)?;
+  }
+  Ok(())
+}
+
+/// Binaries with a bit size not divisible by 8 are encoded with the count of
+/// bits used in the last byte.
+unsafe fn encode_binary(t: Term, out: &mut Vec<u8>) {
+  if t == Term::empty_binary() {
+    out.push(Tag::Binary as u8);
+    out.extend_from_slice(&[0; 4]);
+    return;
+  }
+  let bin_p = boxed::Binary::get_trait_from_term(t);
+  let size = (*bin_p).get_bit_size();
+  let n_bytes = size.get_byte_size_rounded_up().bytes();
+  let tail_bits = size.bits % 8;
+  out.push(if tail_bits == 0 {
+    Tag::Binary as u8
+  } else {
+    Tag::BitBinary as u8
+  });
+  out.extend_from_slice(&(n_bytes as u32).to_be_bytes());
+  if tail_bits != 0 {
+    out.push(tail_bits as u8);
+  }
+  match (*bin_p).get_byte_reader() {
+    Some(reader) => out.extend((0..n_bytes).map(|i| reader.read(i))),
+    None => {
+      let reader = (*bin_p).get_bit_reader();
+      out.extend((0..n_bytes).map(|i| reader.read(i)))
+    }
+  }
+}
+
+/// Encode a local reference in the newer format (32-bit creation).
+fn encode_reference(id: Word, out: &mut Vec<u8>) -> RtResult<()> {
+  let words = boxed::LocalRef::id_to_words(id);
+  out.push(Tag::NewerReference as u8);
+  out.extend_from_slice(&(words.len() as u16).to_be_bytes());
+  encode_atom(gen_atoms::NONODE_AT_NOHOST, out)?;
+  // Creation
+  out.extend_from_slice(&[0; 4]);
+  for w in words.iter() {
+    out.extend_from_slice(&w.to_be_bytes());
+  }
+  Ok(())
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::{compare, term_builder::tuple_builder::tuple3},
+  };
+  use core::cmp::Ordering;
+
+  fn roundtrip(t: Term, hp: &mut Heap) -> Term {
+    let data = encode(t).unwrap();
+    decode(&mut BinaryReader::from_bytes(data), hp).unwrap()
+  }
+
+  #[test]
+  fn test_etf_encode_reference() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let id = 0x4_0000_0005;
+    let r = boxed::LocalRef::create_into(&mut hp, id).unwrap();
+    let data = encode(r).unwrap();
+    let mut expected = vec![131, 90, 0, 3, 119, 13];
+    expected.extend_from_slice(b"nonode@nohost");
+    expected.extend_from_slice(&[0, 0, 0, 0]);
+    expected.extend_from_slice(&[0, 0, 0, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(data, expected);
+
+    let r2 = roundtrip(r, &mut hp);
+    assert!(r2.is_local_ref());
+    assert_eq!(boxed::LocalRef::get_id(r2), id);
+  }
+
+  #[test]
+  fn test_etf_roundtrip() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut lb = ListBuilder::new().unwrap();
+    for v in [0, 255, 256, -1, 1 << 40].iter() {
+      unsafe { lb.append(Term::make_small_signed(*v), &mut hp).unwrap() };
+    }
+    let list = lb.make_term();
+    let bin_p = unsafe { boxed::Binary::create_with_data(b"abc", &mut hp).unwrap() };
+    let bin = unsafe { (*bin_p).make_term() };
+    let float = Term::make_float(&mut hp, -2.5).unwrap();
+    let inner = tuple3(&mut hp, gen_atoms::UNDEFINED, Term::nil(), float).unwrap();
+    let t = tuple3(&mut hp, list, bin, inner).unwrap();
+
+    let t2 = roundtrip(t, &mut hp);
+    assert_eq!(compare::cmp_terms(t, t2, true).unwrap(), Ordering::Equal);
+    // Same terms encode the same
+    assert_eq!(encode(t).unwrap(), encode(t2).unwrap());
+  }
+}
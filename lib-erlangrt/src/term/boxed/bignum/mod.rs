@@ -28,6 +28,7 @@ pub const BIG_DIGIT_SIZE: usize = defs::WORD_BYTES;
 pub type Digit = usize;
 
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Bignum {
   header: BoxHeader,
 
@@ -48,9 +49,11 @@ impl TBoxed for Bignum {
 }
 
 impl Bignum {
-  const fn storage_size() -> SizeWords {
-    // This impl stores bignum in dynamic heap with the num library
-    SizeBytes::new(size_of::<Bignum>()).get_words_rounded_up()
+  /// Size of a bignum with `n_limbs` digits in memory with the header word.
+  /// The first digit is already included in the struct.
+  const fn storage_size(n_limbs: usize) -> SizeWords {
+    let self_size = SizeBytes::new(size_of::<Bignum>()).get_words_rounded_up();
+    SizeWords::new(self_size.words + n_limbs.saturating_sub(1))
   }
 
   /// Create bignum for one isize
@@ -84,7 +87,7 @@ impl Bignum {
     sign: Sign,
     limbs: &[Digit],
   ) -> RtResult<*mut Self> {
-    let n_words = Self::storage_size();
+    let n_words = Self::storage_size(limbs.len());
     let this = hp.alloc(n_words, AllocInit::Uninitialized)? as *mut Self;
 
     this.write(Self {
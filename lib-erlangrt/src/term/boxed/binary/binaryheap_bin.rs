@@ -1,5 +1,8 @@
+//! Binary heap: large binaries are stored outside of process heaps, each in
+//! its own memory block, and are shared by reference (see `ReferenceToBinary`).
+//! The block is freed when the last reference is destroyed.
 use crate::{
-  defs::{BitReader, BitSize, ByteReader, SizeBytes, SizeWords},
+  defs::{BitReader, BitSize, ByteReader, SizeBytes, SizeWords, Word},
   fail::{RtErr, RtResult},
   term::{
     boxed::{
@@ -9,23 +12,64 @@ use crate::{
     Term,
   },
 };
-use core::ptr;
+use core::{
+  ptr,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+use std::alloc;
 
 /// Defines operations with a binary on the binary heap
 /// Pointer to this can be directly casted from pointer to boxed::Binary
+#[repr(C)]
 pub struct BinaryHeapBinary {
   pub bin_header: Binary,
   pub size: BitSize,
+  /// How many `ReferenceToBinary` objects point to this binary.
+  refc: AtomicUsize,
   pub data: usize, // first 8 (or 4) bytes of data begin here
 }
 
 impl BinaryHeapBinary {
-  #[allow(dead_code)]
   pub fn storage_size(size: BitSize) -> SizeWords {
     let header_size = SizeBytes::new(std::mem::size_of::<Self>());
     // The size is `BinaryHeapBinary` in words rounded up + storage bytes rounded up
     header_size.get_words_rounded_up() + size.get_words_rounded_up()
   }
+
+  fn layout(storage_sz: SizeWords) -> alloc::Layout {
+    alloc::Layout::array::<Word>(storage_sz.words).unwrap()
+  }
+
+  /// Allocate a new binary on the binary heap. The refcount starts at 0, it
+  /// is increased by every reference created.
+  pub unsafe fn create(size: BitSize) -> *mut Self {
+    let storage_sz = Self::storage_size(size);
+    let layout = Self::layout(storage_sz);
+    let this = alloc::alloc(layout) as *mut Self;
+    if this.is_null() {
+      alloc::handle_alloc_error(layout);
+    }
+    this.write(Self {
+      bin_header: Binary::new(BinaryType::BinaryHeap, storage_sz),
+      size,
+      refc: AtomicUsize::new(0),
+      data: 0,
+    });
+    this
+  }
+
+  #[inline]
+  pub unsafe fn add_ref(this: *mut Self) {
+    (*this).refc.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Decrease the refcount, free the memory if this was the last reference.
+  pub unsafe fn release(this: *mut Self) {
+    if (*this).refc.fetch_sub(1, Ordering::AcqRel) == 1 {
+      let layout = Self::layout(Self::storage_size((*this).size));
+      alloc::dealloc(this as *mut u8, layout);
+    }
+  }
 }
 
 impl TBinary for BinaryHeapBinary {
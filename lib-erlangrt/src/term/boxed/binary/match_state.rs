@@ -15,7 +15,6 @@ use crate::{
 
 /// Binary match buffer is a part of `BinaryMatchState`
 struct MatchBuffer {
-  // TODO: Make sure this is detected when garbage collected
   pub orig: *const dyn TBinary,
   /// The window begins at bit offset 0 always, and `start_at` will advance
   /// forward as we are reading from the binary.
@@ -37,6 +36,7 @@ impl MatchBuffer {
 /// Matchstate is stored on heap as a heap object. Followed by 1 or more save
 /// offset `Term`s.
 /// TODO: Merge match_buffer with this struct, because reasons?
+#[repr(C)]
 pub struct BinaryMatchState {
   pub header: boxed::BoxHeader,
   match_buffer: MatchBuffer,
@@ -92,6 +92,12 @@ impl BinaryMatchState {
     self.match_buffer.orig
   }
 
+  /// Update the source binary pointer, used by the GC when the binary moves.
+  #[inline]
+  pub fn set_src_binary(&mut self, bin_ptr: *const dyn TBinary) {
+    self.match_buffer.orig = bin_ptr;
+  }
+
   #[inline]
   pub fn get_bits_remaining(&self) -> BitSize {
     let stop_at = self.match_buffer.stop_at.bits;
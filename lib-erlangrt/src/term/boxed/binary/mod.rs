@@ -2,10 +2,7 @@ use core::fmt;
 
 use crate::{
   defs::{self, data_reader::TDataReader, BitSize, SizeBytes, SizeWords},
-  emulator::{
-    heap::{THeap, THeapOwner},
-    vm::VM,
-  },
+  emulator::heap::{THeap, THeapOwner},
   fail::{RtErr, RtResult},
   term::{
     boxed::{
@@ -50,6 +47,7 @@ pub enum BinaryType {
 
 /// Binary which stores everything in its allocated memory on process heap.
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Binary {
   header: BoxHeader,
   /// Based on the bin_type, the pointer should be converted to one of binary
@@ -70,20 +68,17 @@ impl TBoxed for Binary {
 }
 
 impl Binary {
-  /// For binary of given size ensure that the heap has enough space on it,
-  /// and if a large binary is to be created, also check the binary heap capacity.
-  #[allow(dead_code)]
+  /// For binary of given size ensure that the heap has enough space on it.
+  /// Large binaries only need space for the reference to the binary heap.
   pub fn ensure_memory_for_binary(
-    _vm: &mut VM,
     proc_source: &mut dyn THeapOwner,
-    bin_source: &mut dyn THeapOwner,
     size: BitSize,
     extra_memory: SizeWords,
   ) -> RtResult<()> {
     if size.get_byte_size_rounded_up().bytes() <= ProcessHeapBinary::ONHEAP_THRESHOLD {
       proc_source.ensure_heap(ProcessHeapBinary::storage_size(size) + extra_memory)
     } else {
-      bin_source.ensure_heap(ReferenceToBinary::storage_size() + extra_memory)
+      proc_source.ensure_heap(ReferenceToBinary::storage_size() + extra_memory)
     }
   }
 
@@ -115,8 +110,12 @@ impl Binary {
     let b_type = Self::get_binary_type_for_creation(size);
     match b_type {
       BinaryType::ProcessHeap => ProcessHeapBinary::create_into(size, hp),
-      BinaryType::BinaryHeap => unimplemented!("create binary on the binary heap"),
-      BinaryType::RefToBinaryHeap => unimplemented!("create ref to binary heap"),
+      BinaryType::BinaryHeap => {
+        // Binary goes to the binary heap, and the process gets a reference
+        let refbin = ReferenceToBinary::create_new(size, hp)?;
+        Ok(refbin as *mut dyn TBinary)
+      }
+      BinaryType::RefToBinaryHeap => panic!("Can't create ref to binary heap here"),
       BinaryType::Slice => panic!("Can't create slice here"),
     }
   }
@@ -15,6 +15,7 @@ use core::ptr;
 
 /// Defines operations with a binary on process heap.
 /// Pointer to this can be directly casted from pointer to boxed::Binary
+#[repr(C)]
 pub struct ProcessHeapBinary {
   pub bin_header: boxed::binary::Binary,
   pub size: BitSize,
@@ -1,6 +1,7 @@
 use crate::{
-  defs::{BitReader, BitSize, ByteReader, SizeBytes, Word, SizeWords},
-  fail::{RtErr, RtResult},
+  defs::{BitReader, BitSize, ByteReader, SizeBytes, SizeWords},
+  emulator::heap::{AllocInit, THeap},
+  fail::RtResult,
   term::{
     boxed::{
       binary::{binaryheap_bin::BinaryHeapBinary, trait_interface::TBinary, BinaryType},
@@ -12,10 +13,12 @@ use crate::{
 
 /// Defines operations with reference to binary.
 /// Pointer to this can be directly casted from pointer to boxed::Binary
+/// Every reference holds one refcount of the binary it points to, the heap
+/// where the reference lives releases it when the reference is garbage.
+#[repr(C)]
 pub struct ReferenceToBinary {
   pub bin_header: Binary,
   pub size: BitSize,
-  refc: Word,
   pub pointer: *mut BinaryHeapBinary,
 }
 
@@ -25,11 +28,38 @@ impl ReferenceToBinary {
     header_size.get_words_rounded_up()
   }
 
-  #[allow(dead_code)]
+  /// Create a new binary of `size` on the binary heap, and a reference to it
+  /// on `hp`.
+  pub unsafe fn create_new(size: BitSize, hp: &mut dyn THeap) -> RtResult<*mut Self> {
+    let this = hp.alloc(Self::storage_size(), AllocInit::Uninitialized)? as *mut Self;
+    Self::init(this, BinaryHeapBinary::create(size), hp);
+    Ok(this)
+  }
+
+  /// Create another reference to the binary `bin` on `hp`, used when the
+  /// binary is copied to another process.
+  pub unsafe fn create_into(
+    bin: *mut BinaryHeapBinary,
+    hp: &mut dyn THeap,
+  ) -> RtResult<*mut Self> {
+    let this = hp.alloc(Self::storage_size(), AllocInit::Uninitialized)? as *mut Self;
+    Self::init(this, bin, hp);
+    Ok(this)
+  }
+
+  unsafe fn init(this: *mut Self, bin: *mut BinaryHeapBinary, hp: &mut dyn THeap) {
+    this.write(Self {
+      bin_header: Binary::new(BinaryType::RefToBinaryHeap, Self::storage_size()),
+      size: (*bin).size,
+      pointer: bin,
+    });
+    BinaryHeapBinary::add_ref(bin);
+    hp.register_refc_binary(this);
+  }
+
+  /// The reference is garbage, release the binary it points to.
   pub unsafe fn on_destroy(this: *mut ReferenceToBinary) {
-    if (*this).refc > 0 {
-      (*this).refc -= 1;
-    }
+    BinaryHeapBinary::release((*this).pointer)
   }
 }
 
@@ -47,24 +77,23 @@ impl TBinary for ReferenceToBinary {
   }
 
   fn get_byte_reader(&self) -> Option<ByteReader> {
-    unimplemented!()
+    unsafe { (*self.pointer).get_byte_reader() }
   }
 
   unsafe fn get_data_mut(&mut self) -> &mut [u8] {
-    unimplemented!()
+    (*self.pointer).get_data_mut()
   }
 
   unsafe fn get_data(&self) -> &[u8] {
-    unimplemented!()
+    (*self.pointer).get_data()
   }
 
   fn get_bit_reader(&self) -> BitReader {
-    unimplemented!()
+    unsafe { (*self.pointer).get_bit_reader() }
   }
 
-  fn store(&mut self, _data: &[u8]) -> RtResult<()> {
-    // TODO: Maybe should be possible? Assist with resolution into BinaryHeapBinary
-    Err(RtErr::CannotCopyIntoRefbin)
+  fn store(&mut self, data: &[u8]) -> RtResult<()> {
+    unsafe { (*self.pointer).store(data) }
   }
 
   fn make_term(&self) -> Term {
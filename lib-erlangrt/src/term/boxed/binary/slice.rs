@@ -13,11 +13,11 @@ use crate::{
 use core::ptr::NonNull;
 
 /// Another type of binary. Refers to a slice in another binary.
+#[repr(C)]
 pub struct BinarySlice {
   pub bin_header: Binary,
   pub offset: BitSize,
   pub size: BitSize,
-  // TODO: Make sure this is recognized as a term during the GC
   pub orig: *const dyn TBinary,
 }
 
@@ -26,6 +26,7 @@ const fn module() -> &'static str {
 
 /// Boxed `Closure` is placed on heap and referred via Term::p
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Closure {
   pub header: BoxHeader,
 
@@ -63,7 +64,7 @@ impl Closure {
   }
 
   fn new(mfa: ModFunArity, nfrozen: usize) -> Self {
-    let storage_size = Self::storage_size(nfrozen) - SizeWords::one();
+    let storage_size = Self::storage_size(nfrozen);
     Self {
       header: BoxHeader::new::<Self>(storage_size),
       mfa,
@@ -119,7 +120,7 @@ impl Closure {
 
   /// Given a closure, find new value for the code pointer and update the
   /// closure. Return: the pointer.
-  pub unsafe fn update_location(&mut self, c_srv: &mut CodeServer) -> RtResult<CodePtr> {
+  pub unsafe fn update_location(&mut self, c_srv: &CodeServer) -> RtResult<CodePtr> {
     let new_dst = c_srv.lookup_beam_code_versioned(&self.mfa)?;
     let ptr = new_dst.ptr;
     self.dst = Some(new_dst);
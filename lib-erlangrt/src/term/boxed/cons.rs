@@ -3,6 +3,7 @@ use crate::term::Term;
 /// A cons is 2 values stored together on heap forming a singly-linked list node.
 /// Each is a fully tagged term so anyone who is parsing the heap will see this
 /// as two independent values.
+#[repr(C)]
 pub struct Cons {
   value: [Term; 2],
 }
@@ -20,6 +20,7 @@ use crate::{
 use core::mem::size_of;
 
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Import {
   header: BoxHeader,
   pub mfarity: ModFunArity,
@@ -86,7 +87,7 @@ impl Import {
 
   /// Lookup a function, referred by this object and possibly attempt code
   /// loading if the module was missing. Return a code pointer.
-  pub fn resolve(&self, code_server: &mut CodeServer) -> RtResult<CodePtr> {
+  pub fn resolve(&self, code_server: &CodeServer) -> RtResult<CodePtr> {
     code_server.lookup_beam_code_and_load(&self.mfarity)
   }
 
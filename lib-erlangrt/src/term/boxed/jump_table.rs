@@ -15,6 +15,7 @@ use crate::{
 use core::fmt;
 
 /// An array of sorted pairs, which like a tuple stores the array in its memory
+#[repr(C)]
 pub struct JumpTable {
   header: BoxHeader,
   /// First data word is stored here
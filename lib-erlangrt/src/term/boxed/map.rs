@@ -1,7 +1,7 @@
 use core::cmp::Ordering;
 
 use crate::{
-  defs::{Word, SizeWords},
+  defs::{SizeBytes, SizeWords, Word},
   emulator::heap::{AllocInit, THeap},
   fail::RtResult,
   term::{
@@ -32,6 +32,7 @@ pub enum MapGetResult {
 /// Representation of Map on heap, either stored as a list of sorted pairs
 /// or as a hash tree (HAMT).
 /// TODO: implement HAMT, for now only using sorted list of pairs
+#[repr(C)]
 pub struct Map {
   header: BoxHeader,
   map_type: MapType,
@@ -50,14 +51,19 @@ impl TBoxed for Map {
 }
 
 impl Map {
-  /// Size of a tuple in memory with the header word (used for allocations)
+  /// Size of a map in memory with the header word (used for allocations)
   #[inline]
   pub fn storage_size(num_pairs: Word) -> SizeWords {
-    SizeWords::new(2 * num_pairs) + BoxHeader::storage_size()
+    Self::self_storage_size() + SizeWords::new(2 * num_pairs)
   }
 
-  /// Capacity is how many extra words been allocated
-  /// TODO: capacity is not words count on heap, but the count of k/v pairs
+  /// Size of the `Map` struct in words, the pairs follow after it
+  #[inline]
+  fn self_storage_size() -> SizeWords {
+    SizeBytes::new(core::mem::size_of::<Self>()).get_words_rounded_up()
+  }
+
+  /// Capacity is how many k/v pairs can be stored in the allocated memory
   fn new(num_pairs: usize) -> Self {
     let storage_size = Self::storage_size(num_pairs);
     Self {
@@ -67,10 +73,9 @@ impl Map {
     }
   }
 
-  /// Returns allocated size used by this map on heap
+  /// Returns how many k/v pairs fit in the memory allocated for this map
   pub fn get_capacity(&self) -> usize {
-    // Capacity is in terms, word is same size as term
-    self.header.get_storage_size().words
+    (self.header.get_storage_size() - Self::self_storage_size()).words / 2
   }
 
   /// Returns actual element count, less or equal to the capacity
@@ -88,6 +93,12 @@ impl Map {
     Ok(p)
   }
 
+  /// Access the used k/v pairs as a flat slice of terms, key followed by value.
+  pub unsafe fn get_pairs_mut<'a>(this: *mut Map) -> &'a mut [Term] {
+    let p = this.add(1) as *mut Term;
+    core::slice::from_raw_parts_mut(p, (*this).count * 2)
+  }
+
   /// Add a key/value pair to map (unsorted).
   /// Note: the flatmap must be sorted for use
   pub unsafe fn add(this: *mut Map, key: Term, value: Term) -> RtResult<()> {
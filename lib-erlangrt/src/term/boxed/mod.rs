@@ -13,11 +13,12 @@ pub mod import;
 pub mod jump_table;
 pub mod map;
 pub mod pid;
+pub mod reference;
 pub mod trait_interface;
 pub mod tuple;
 
 pub use self::{
   bignum::*, binary::Binary, box_header::*, boxtype::*, closure::Closure, cons::Cons,
   export::Export, float::Float, import::Import, jump_table::*, map::*, pid::ExternalPid,
-  trait_interface::*, tuple::Tuple,
+  reference::LocalRef, trait_interface::*, tuple::Tuple,
 };
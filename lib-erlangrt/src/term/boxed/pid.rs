@@ -14,6 +14,7 @@ use crate::{
 use core::mem::size_of;
 
 /// Represents Pid box on heap.
+#[repr(C)]
 pub struct ExternalPid {
   pub header: BoxHeader,
   pub node: Term,
@@ -36,7 +37,7 @@ impl ExternalPid {
   }
 
   fn new(node: Term, id: Word) -> ExternalPid {
-    let storage_size = ExternalPid::storage_size() - SizeWords::one();
+    let storage_size = ExternalPid::storage_size();
     ExternalPid {
       header: BoxHeader::new::<ExternalPid>(storage_size),
       node,
@@ -0,0 +1,97 @@
+use crate::{
+  defs::{SizeBytes, SizeWords, Word},
+  emulator::heap::{AllocInit, THeap},
+  fail::RtResult,
+  term::{
+    boxed::{
+      boxtype::{self, BoxType},
+      trait_interface::TBoxed,
+      BoxHeader,
+    },
+    classify, Term,
+  },
+};
+use core::mem::size_of;
+
+/// Represents a local reference box on heap. The id is unique per VM.
+#[repr(C)]
+pub struct LocalRef {
+  pub header: BoxHeader,
+  pub id: Word,
+}
+
+impl TBoxed for LocalRef {
+  fn get_class(&self) -> classify::TermClass {
+    classify::CLASS_REF
+  }
+
+  fn get_type(&self) -> BoxType {
+    boxtype::BOXTYPETAG_LOCALREF
+  }
+}
+
+impl LocalRef {
+  pub const fn storage_size() -> SizeWords {
+    SizeBytes::new(size_of::<LocalRef>()).get_words_rounded_up()
+  }
+
+  fn new(id: Word) -> LocalRef {
+    LocalRef {
+      header: BoxHeader::new::<LocalRef>(LocalRef::storage_size()),
+      id,
+    }
+  }
+
+  /// Allocates a reference with the given unique id.
+  pub fn create_into(hp: &mut dyn THeap, id: Word) -> RtResult<Term> {
+    let p = hp.alloc(LocalRef::storage_size(), AllocInit::Uninitialized)? as *mut Self;
+    unsafe { p.write(LocalRef::new(id)) }
+    Ok(Term::make_boxed(p))
+  }
+
+  /// Split the id into the numbers printed as `#Ref<0.c.b.a>`. Lowest part
+  /// goes first and is 18 bits wide, as the ID words in the external term
+  /// format.
+  pub fn id_to_words(id: Word) -> [u32; 3] {
+    let id = id as u64;
+    [
+      (id & 0x3ffff) as u32,
+      ((id >> 18) & 0xffff_ffff) as u32,
+      (id >> 50) as u32,
+    ]
+  }
+
+  /// Reverse of `id_to_words`, missing words are zeroes.
+  pub fn id_from_words(words: &[u32]) -> Word {
+    let get = |i: usize| words.get(i).map_or(0u64, |w| *w as u64);
+    ((get(0) & 0x3ffff) | (get(1) << 18) | (get(2) << 50)) as Word
+  }
+
+  /// For a term which is a local reference, get its id.
+  pub fn get_id(t: Term) -> Word {
+    debug_assert!(t.is_local_ref());
+    let p = t.get_box_ptr::<LocalRef>();
+    unsafe { (*p).id }
+  }
+}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ref_id_words_roundtrip() {
+    let ids: [Word; 7] = [0, 1, 0x3ffff, 0x40000, 1 << 49, 1 << 50, Word::MAX];
+    for id in ids.iter() {
+      let words = LocalRef::id_to_words(*id);
+      assert!(words[0] <= 0x3ffff, "first word is 18 bits wide");
+      assert_eq!(LocalRef::id_from_words(&words), *id);
+    }
+    assert_eq!(LocalRef::id_to_words(0x40001), [1, 1, 0]);
+    // Missing words are zeroes
+    assert_eq!(LocalRef::id_from_words(&[5]), 5);
+  }
+}
@@ -16,6 +16,7 @@ use core::fmt;
 
 /// A fixed-size array which stores everything in its allocated memory on
 /// process heap.
+#[repr(C)]
 pub struct Tuple {
   header: BoxHeader,
   /// First data word is stored here. If a tuple is 0 elements, it cannot be
@@ -50,7 +51,7 @@ impl TBoxed for Tuple {
 impl Tuple {
   /// Size of a tuple in memory with the header word (used for allocations)
   #[inline]
-  const fn storage_size(arity: usize) -> SizeWords {
+  pub const fn storage_size(arity: usize) -> SizeWords {
     // Minus one because data0 in tuple already consumes one word
     let self_size = SizeBytes::new(core::mem::size_of::<Self>()).get_words_rounded_up();
     SizeWords::new(self_size.words + arity - 1)
@@ -5,7 +5,7 @@ use crate::{
   emulator::atom,
   fail::RtResult,
   term::{
-    boxed::{self, binary::TBinary},
+    boxed::{self, binary::TBinary, bignum::Digit},
     classify,
     compare::EqResult::Concluded,
     *,
@@ -21,6 +21,8 @@ enum ContinueCompare {
   AnyType { a: Term, b: Term },
   // Resume comparing Cons cells, we just reenter `eq_terms_cons`.
   Cons { a: Term, b: Term },
+  // Resume comparing two tuples of same arity from element `index`.
+  Tuple { a: Term, b: Term, index: usize },
 }
 
 #[allow(dead_code)]
@@ -47,6 +49,19 @@ pub fn cmp_terms(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   cmp_terms_1(a, b, exact)
 }
 
+/// Exact equality (`=:=`). Immediate terms are equal only if their words are
+/// equal, so `1 =:= 1.0` is false. Boxed terms and lists must have the same
+/// type and value.
+pub fn exact_eq(a: Term, b: Term) -> RtResult<bool> {
+  if a == b {
+    return Ok(true);
+  }
+  if a.is_immediate() || b.is_immediate() || a.get_term_tag() != b.get_term_tag() {
+    return Ok(false);
+  }
+  Ok(cmp_terms_1(a, b, true)? == Ordering::Equal)
+}
+
 #[inline]
 fn cmp_terms_1(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   // Comparison might want to recurse.
@@ -61,6 +76,7 @@ fn cmp_terms_1(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
     let eq_result = match op {
       ContinueCompare::AnyType { a: a1, b: b1 }
       | ContinueCompare::Cons { a: a1, b: b1 } => cmp_terms_any_type(a1, b1, exact)?,
+      ContinueCompare::Tuple { a: a1, b: b1, index } => unsafe { cmp_tuple(a1, b1, index) },
     };
 
     match eq_result {
@@ -116,7 +132,7 @@ fn cmp_terms_any_type(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
 
   // If not exact then allow comparing float to int/bigint
   if !exact && (a_is_float || a_is_small) && (b_is_float || b_is_small) {
-    return Ok(EqResult::Concluded(cmp_numbers_not_exact(a, b)));
+    return Ok(EqResult::Concluded(cmp_numbers(a, b, false)));
   } else if a_is_float && b_is_float {
     return Ok(EqResult::Concluded(cmp_floats(a, b)));
   }
@@ -153,8 +169,88 @@ fn cmp_f64_naive(a: f64, b: f64) -> Ordering {
   Ordering::Equal
 }
 
-fn cmp_numbers_not_exact(_a: Term, _b: Term) -> Ordering {
-  unimplemented!("eq_numbers_not_exact")
+/// A number split for comparison: sign, integer part as little-endian digits
+/// with no leading zeros, and the fraction (nonzero only for floats).
+struct NumberParts {
+  negative: bool,
+  digits: Vec<Digit>,
+  fraction: f64,
+}
+
+impl NumberParts {
+  unsafe fn from_term(t: Term) -> Self {
+    if t.is_small() {
+      let val = t.get_small_signed();
+      return Self::from_integer(val < 0, vec![val.unsigned_abs()]);
+    }
+    if t.is_float() {
+      return Self::from_float(t.get_float_unchecked());
+    }
+    let big_p = t.get_box_ptr::<boxed::Bignum>();
+    Self::from_integer((*big_p).is_negative(), (*big_p).get_digits().to_vec())
+  }
+
+  fn from_integer(negative: bool, mut digits: Vec<Digit>) -> Self {
+    while digits.last() == Some(&0) {
+      digits.pop();
+    }
+    Self {
+      negative: negative && !digits.is_empty(),
+      digits,
+      fraction: 0.0,
+    }
+  }
+
+  fn from_float(val: f64) -> Self {
+    let int_part = val.abs().trunc();
+    let fraction = val.abs() - int_part;
+    let digits = if int_part < 2f64.powi(Digit::BITS as i32) {
+      vec![int_part as Digit]
+    } else {
+      // Integer float is mantissa * 2^shift, where shift is not negative
+      let bits = int_part.to_bits();
+      let mantissa = ((bits & ((1 << 52) - 1)) | (1 << 52)) as Digit;
+      let shift = ((bits >> 52) & 0x7ff) as usize - 1075;
+      let mut digits = vec![0; shift / Digit::BITS as usize];
+      let bit_shift = shift as u32 % Digit::BITS;
+      digits.push(mantissa << bit_shift);
+      if bit_shift > 0 {
+        digits.push(mantissa >> (Digit::BITS - bit_shift));
+      }
+      digits
+    };
+    let mut result = Self::from_integer(val < 0.0, digits);
+    result.negative = val < 0.0 && (!result.digits.is_empty() || fraction > 0.0);
+    result.fraction = fraction;
+    result
+  }
+
+  /// Compare the absolute values.
+  fn cmp_magnitude(&self, other: &Self) -> Ordering {
+    self
+      .digits
+      .len()
+      .cmp(&other.digits.len())
+      .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+      .then_with(|| cmp_f64_naive(self.fraction, other.fraction))
+  }
+}
+
+/// Compare numbers (small, big integers and floats) by value. If the values
+/// are equal, in exact mode integers go before floats.
+fn cmp_numbers(a: Term, b: Term, exact: bool) -> Ordering {
+  let a_parts = unsafe { NumberParts::from_term(a) };
+  let b_parts = unsafe { NumberParts::from_term(b) };
+  let by_value = match (a_parts.negative, b_parts.negative) {
+    (true, false) => Ordering::Less,
+    (false, true) => Ordering::Greater,
+    (false, false) => a_parts.cmp_magnitude(&b_parts),
+    (true, true) => b_parts.cmp_magnitude(&a_parts),
+  };
+  if by_value != Ordering::Equal || !exact {
+    return by_value;
+  }
+  a.is_float().cmp(&b.is_float())
 }
 
 /// Compare two atoms for equality. Returns the ordering result.
@@ -200,9 +296,8 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
   // println!("cmp {} tag={:?} vs {} tag={:?}", a, a_prim_tag, b, b_prim_tag);
 
   if a_prim_tag != b_prim_tag {
-    // different primary types, compare their classes
-    // This can be optimized a little but is there any value in optimization?
-    return Ok(EqResult::Concluded(cmp_type_order(a, b)));
+    // different primary types, such as a small integer and a boxed number
+    return Ok(EqResult::Concluded(cmp_mixed_types(a, b, exact)?));
   }
 
   match a_prim_tag {
@@ -210,12 +305,21 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
       if a.is_cp() || b.is_cp() {
         panic!("eq_terms for CP is unsupported")
       }
-      Ok(Concluded(cmp_terms_immed_box(a, b)?))
+      if a.is_tuple() && b.is_tuple() {
+        // Smaller tuples go first, same size tuples are compared by elements
+        let a_arity = unsafe { (*a.get_tuple_ptr()).get_arity() };
+        let b_arity = unsafe { (*b.get_tuple_ptr()).get_arity() };
+        if a_arity != b_arity {
+          return Ok(Concluded(a_arity.cmp(&b_arity)));
+        }
+        return Ok(unsafe { cmp_tuple(a, b, 0) });
+      }
+      Ok(Concluded(cmp_terms_immed_box(a, b, exact)?))
     }
 
     PrimaryTag::CONS_PTR => {
       if !b.is_cons() {
-        return Ok(EqResult::Concluded(cmp_mixed_types(a, b)?));
+        return Ok(EqResult::Concluded(cmp_mixed_types(a, b, exact)?));
       }
 
       Ok(unsafe { cmp_cons(a, b) })
@@ -229,7 +333,7 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
 }
 
 // TODO: Optimize by doing case on tag bits
-fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
+fn cmp_terms_immed(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   if (a == Term::nil() || a == Term::empty_tuple() || a == Term::empty_binary())
     && (a.raw() == b.raw())
   {
@@ -242,7 +346,7 @@ fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
     } else if b.is_external_port() {
       unimplemented!("cmp local vs ext port")
     } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   }
 
@@ -253,12 +357,12 @@ fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
     } else if b.is_external_pid() {
       unimplemented!("cmp local vs ext pid")
     } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   }
 
   if a.is_boxed() {
-    return cmp_terms_immed_box(a, b);
+    return cmp_terms_immed_box(a, b, exact);
   }
 
   // if both are internal immediates, compare their raw values or their tags
@@ -280,12 +384,12 @@ fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
 
 // TODO: Optimize by doing case on tag bits
 #[inline]
-fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
+fn cmp_terms_immed_box(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   if a.is_tuple() {
     if b.is_tuple() {
-      unimplemented!("cmp tuple vs tuple")
+      unreachable!("tuple vs tuple is compared in cmp_terms_primary")
     } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   } else if a.is_map() {
     if a.is_flat_map() {
@@ -323,31 +427,39 @@ fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
   } else if a.is_float() {
     if !b.is_float() {
       // TODO: If b is integer and we don't do exact comparison?
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     } else {
       let a_float = a.get_float()?;
       let b_float = b.get_float()?;
       return Ok(a_float.partial_cmp(&b_float).unwrap());
     }
   } else if a.is_big_int() {
-    if !b.is_big_int() {
-      return cmp_mixed_types(a, b);
-    }
+    // Compared by value with other numbers, by type order with the rest
+    return cmp_mixed_types(a, b, exact);
   } else if a.is_export() {
     if !b.is_export() {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
     // Compare two exports: from utils.c line ~2918
     // cmp atoms a.module and b.module
     // cmp atoms a.fn and b.fn
     // cmp arity
     unimplemented!("compare 2 exports")
+  } else if a.is_local_ref() {
+    if b.is_local_ref() {
+      // Ids grow monotonically, so newer refs compare greater
+      return Ok(boxed::LocalRef::get_id(a).cmp(&boxed::LocalRef::get_id(b)));
+    } else if b.is_external_ref() {
+      unimplemented!("compare local vs ext ref")
+    } else {
+      return cmp_mixed_types(a, b, exact);
+    }
   } else if a.is_boxed() {
     if a.is_binary() && b.is_binary() {
       return unsafe { cmp_binary(a, b) };
     }
     if !a.is_fun() {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
     // Compare 2 function objects: from utils.c line ~2937
     // compare a.module, b.module
@@ -361,7 +473,7 @@ fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
     } else if b.is_external_pid() {
       unimplemented!("compare ext vs ext pid")
     } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   } else if a.is_external_port() {
     if b.is_local_port() {
@@ -369,15 +481,7 @@ fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
     } else if b.is_external_port() {
       unimplemented!("compare ext vs ext port")
     } else {
-      return cmp_mixed_types(a, b);
-    }
-  } else if a.is_local_ref() {
-    if b.is_local_ref() {
-      unimplemented!("compare local vs local ref")
-    } else if b.is_external_ref() {
-      unimplemented!("compare local vs ext ref")
-    } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   } else if a.is_external_ref() {
     if b.is_local_ref() {
@@ -385,13 +489,13 @@ fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
     } else if b.is_external_ref() {
       unimplemented!("compare ext vs ext ref")
     } else {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
   } else {
     // must be a binary
     assert!(a.is_binary());
     if !b.is_binary() {
-      return cmp_mixed_types(a, b);
+      return cmp_mixed_types(a, b, exact);
     }
     unimplemented!("cmp binaries")
   }
@@ -485,9 +589,38 @@ where
   Ok(Ordering::Equal)
 }
 
-/// Deeper comparison of two values with different types
-fn cmp_mixed_types(a: Term, b: Term) -> RtResult<Ordering> {
-  unimplemented!("cmp_mixed_types {} vs {}", a, b)
+/// Deeper comparison of two values with different types. Numbers are compared
+/// by value, other types by their order.
+fn cmp_mixed_types(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
+  if a.is_number() && b.is_number() {
+    return Ok(cmp_numbers(a, b, exact));
+  }
+  Ok(cmp_type_order(a, b))
+}
+
+/// Compare two tuples of same arity element by element, from element `start`.
+/// When elements are not the same, they are compared by the caller and then
+/// the comparison resumes from the next element.
+unsafe fn cmp_tuple(a: Term, b: Term, start: usize) -> EqResult {
+  let a_ptr = a.get_tuple_ptr();
+  let b_ptr = b.get_tuple_ptr();
+  for index in start..(*a_ptr).get_arity() {
+    let a_el = (*a_ptr).get_element(index);
+    let b_el = (*b_ptr).get_element(index);
+    if !Term::is_same(a_el, b_el) {
+      let continue_op = ContinueCompare::Tuple {
+        a,
+        b,
+        index: index + 1,
+      };
+      return EqResult::CompareNested {
+        a: a_el,
+        b: b_el,
+        state: continue_op,
+      };
+    }
+  }
+  EqResult::Concluded(Ordering::Equal)
 }
 
 /// Compare two cons (list) cells.
@@ -549,3 +682,82 @@ unsafe fn cmp_cons(a: Term, b: Term) -> EqResult {
 //  // TODO: see if cmp_terms_immed_box can be useful
 //  unimplemented!("eq_terms_box")
 //}
+
+// Testing section
+//
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::{
+      gen_atoms,
+      heap::{Designation, Heap},
+    },
+    term::{boxed::bignum::sign::Sign, term_builder::tuple_builder::tuple2},
+  };
+
+  fn bignum(hp: &mut Heap, sign: Sign, limbs: &[Digit]) -> Term {
+    Term::make_boxed(unsafe { boxed::Bignum::create_into(hp, sign, limbs) }.unwrap())
+  }
+
+  fn cmp(a: Term, b: Term, exact: bool) -> Ordering {
+    cmp_terms(a, b, exact).unwrap()
+  }
+
+  #[test]
+  fn test_cmp_mixed_numbers() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    // 1 bsl 70 and -(1 bsl 70)
+    let big = bignum(&mut hp, Sign::Positive, &[0, 64]);
+    let neg_big = bignum(&mut hp, Sign::Negative, &[0, 64]);
+    let big_float = Term::make_float(&mut hp, 2f64.powi(70)).unwrap();
+    let larger_float = Term::make_float(&mut hp, 2f64.powi(71)).unwrap();
+    let f2_5 = Term::make_float(&mut hp, 2.5).unwrap();
+    let f_neg = Term::make_float(&mut hp, -0.5).unwrap();
+    let f1 = Term::make_float(&mut hp, 1.0).unwrap();
+    let one = Term::make_small_signed(1);
+    let three = Term::make_small_signed(3);
+
+    assert_eq!(cmp(f2_5, big, true), Ordering::Less);
+    assert_eq!(cmp(big, f2_5, true), Ordering::Greater);
+    assert_eq!(cmp(neg_big, f_neg, true), Ordering::Less);
+    assert_eq!(cmp(three, big, true), Ordering::Less);
+    assert_eq!(cmp(neg_big, three, true), Ordering::Less);
+    assert_eq!(cmp(big, larger_float, true), Ordering::Less);
+    assert_eq!(cmp(f2_5, three, true), Ordering::Less);
+    assert_eq!(cmp(f_neg, one, false), Ordering::Less);
+
+    // Same value: equal, but in exact mode integers go first
+    assert_eq!(cmp(big, big_float, false), Ordering::Equal);
+    assert_eq!(cmp(big, big_float, true), Ordering::Less);
+    assert_eq!(cmp(big_float, big, true), Ordering::Greater);
+    assert_eq!(cmp(one, f1, false), Ordering::Equal);
+    assert_eq!(cmp(one, f1, true), Ordering::Less);
+
+    // Bignums of different sizes and signs
+    let bigger = bignum(&mut hp, Sign::Positive, &[0, 0, 1]);
+    assert_eq!(cmp(big, bigger, true), Ordering::Less);
+    assert_eq!(cmp(neg_big, big, true), Ordering::Less);
+    let big2 = bignum(&mut hp, Sign::Positive, &[0, 64]);
+    assert_eq!(cmp(big, big2, true), Ordering::Equal);
+  }
+
+  #[test]
+  fn test_cmp_bignum_other_types() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let big = bignum(&mut hp, Sign::Positive, &[0, 64]);
+    let tuple = tuple2(&mut hp, gen_atoms::OK, gen_atoms::OK).unwrap();
+    let bin_p = unsafe { boxed::Binary::create_with_data(b"abc", &mut hp).unwrap() };
+    let bin = unsafe { (*bin_p).make_term() };
+
+    // Numbers go before tuples and binaries
+    for exact in [false, true] {
+      assert_eq!(cmp(big, tuple, exact), Ordering::Less);
+      assert_eq!(cmp(tuple, big, exact), Ordering::Greater);
+      assert_eq!(cmp(big, bin, exact), Ordering::Less);
+      assert_eq!(cmp(bin, big, exact), Ordering::Greater);
+      assert_eq!(cmp(big, Term::empty_binary(), exact), Ordering::Less);
+    }
+  }
+}
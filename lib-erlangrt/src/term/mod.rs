@@ -15,5 +15,3 @@ pub mod term_builder; /* implements ITermBuilder for RT VM // term in memory (dy
 
 mod value; // Value stored in one machine word
 pub use self::value::*;
-
-pub mod heap_walker;
\ No newline at end of file
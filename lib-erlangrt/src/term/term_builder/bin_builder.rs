@@ -30,6 +30,25 @@ impl BinaryBuilder {
     })
   }
 
+  /// Continue writing a binary which was created earlier (e.g. by a native
+  /// function which has trapped), starting at byte `pos`.
+  pub unsafe fn continue_from(bin: Term, pos: usize) -> Self {
+    let p = boxed::Binary::get_trait_mut_from_term(bin);
+    let write_slice = (*p).get_data_mut();
+    let start = write_slice.as_mut_ptr();
+    Self {
+      p,
+      write_pos: start.add(pos),
+      limit: start.add(write_slice.len()),
+      size: SizeBytes::new(write_slice.len()),
+    }
+  }
+
+  /// Bytes written so far.
+  pub fn get_pos(&self) -> usize {
+    self.size.bytes() - (self.limit as usize - self.write_pos as usize)
+  }
+
   pub unsafe fn write_byte(&mut self, b: u8) {
     debug_assert!(
       self.write_pos < self.limit,
@@ -40,6 +59,16 @@ impl BinaryBuilder {
     self.write_pos = self.write_pos.add(1);
   }
 
+  pub unsafe fn write_bytes(&mut self, data: &[u8]) {
+    debug_assert!(
+      self.write_pos.add(data.len()) <= self.limit,
+      "binary_builder: writing beyond {} bytes",
+      self.size
+    );
+    core::ptr::copy_nonoverlapping(data.as_ptr(), self.write_pos, data.len());
+    self.write_pos = self.write_pos.add(data.len());
+  }
+
   pub fn make_term(self) -> Term {
     unsafe { (*self.p).make_term() }
   }
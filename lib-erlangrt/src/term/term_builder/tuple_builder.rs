@@ -42,3 +42,15 @@ pub fn tuple2(hp: &mut dyn THeap, a: Term, b: Term) -> RtResult<Term> {
   }
   Ok(tb.make_term())
 }
+
+/// Create a 3-tuple.
+#[inline]
+pub fn tuple3(hp: &mut dyn THeap, a: Term, b: Term, c: Term) -> RtResult<Term> {
+  let tb = TupleBuilder::with_arity(3, hp)?;
+  unsafe {
+    tb.set_element(0, a);
+    tb.set_element(1, b);
+    tb.set_element(2, c);
+  }
+  Ok(tb.make_term())
+}
@@ -1,7 +1,10 @@
 //! Utility functions for handling lists
 use crate::{
-  defs::{exc_type::ExceptionType, sizes::SizeBytes},
-  emulator::{gen_atoms, heap::THeap},
+  defs::exc_type::ExceptionType,
+  emulator::{
+    gen_atoms,
+    heap::{allocate_cons, THeap},
+  },
   fail::{RtErr, RtResult},
   term::{boxed, term_builder::ListBuilder, Term},
 };
@@ -29,37 +32,6 @@ pub fn list_length(val: Term) -> RtResult<usize> {
   }
 }
 
-/// Copies list `src` to heap `hp`.
-/// Arg: `src` list, must not be NIL (check for it before calling this).
-/// Returns: pointer to the list head (first element of the result tuple) and
-/// the pointer to the last cell (second element).
-/// OBSERVE that the tail of the returned copy is uninitialized memory.
-pub unsafe fn copy_list_leave_tail(
-  src: Term,
-  hp: &mut dyn THeap,
-) -> RtResult<(Term, *mut boxed::Cons)> {
-  let mut lb = ListBuilder::new()?;
-
-  // Copy elements one by one
-  if let Ok(Some(tail)) = for_each(src, |elem| {
-    lb.append(elem, hp)?;
-    Ok(())
-  }) {
-    return Ok((lb.make_term_with_tail(tail), lb.tail_p));
-  }
-  Ok((lb.make_term(), lb.tail_p))
-
-  //  loop {
-  //    lb.append((*src_p).hd());
-  //    let src_tl = (*src_p).tl();
-  //    if !src_tl.is_cons() {
-  //      return Ok((lb.make_term(), lb.get_write_p()));
-  //    }
-  //    lb.next()?;
-  //    src_p = src_tl.get_cons_ptr();
-  //  }
-}
-
 /// For each list element run the function. Tail element (usually NIL) is ignored.
 /// Returns: Tail element (NIL for proper list) or `None` for empty list
 pub fn for_each<T>(lst: Term, mut func: T) -> RtResult<Option<Term>>
@@ -144,6 +116,30 @@ where
   }
 }
 
+/// Take up to `limit` elements from the start of `list` and prepend them to
+/// `acc`, this reverses them. Only the new cells are written, older cells are
+/// never changed, so the work can continue in another timeslice after a GC
+/// has moved or promoted the terms.
+/// Returns: the rest of `list`, the new `acc` and the count of elements taken.
+pub unsafe fn reverse_onto(
+  mut list: Term,
+  mut acc: Term,
+  limit: usize,
+  hp: &mut dyn THeap,
+) -> RtResult<(Term, Term, usize)> {
+  let mut count = 0;
+  while list.is_cons() && count < limit {
+    let cell = list.get_cons_ptr();
+    let new_cell = allocate_cons(hp)?;
+    (*new_cell).set_hd((*cell).hd());
+    (*new_cell).set_tl(acc);
+    acc = Term::make_cons(new_cell);
+    list = (*cell).tl();
+    count += 1;
+  }
+  Ok((list, acc, count))
+}
+
 /// Given Rust `String`, create list of characters on heap
 // TODO: Optimize by adding new string type which is not a list?
 pub unsafe fn rust_str_to_list(s: &str, hp: &mut dyn THeap) -> RtResult<Term> {
@@ -193,21 +189,3 @@ pub unsafe fn integer_to_list(val: Term, hp: &mut dyn THeap) -> RtResult<Term> {
 
   Ok(lb.make_term())
 }
-
-pub fn get_iolist_size(list: Term) -> SizeBytes {
-  let mut result = SizeBytes::new(0);
-  for_each(list, |elem| {
-    if elem.is_small() {
-      // Any small integer even larger than 256 counts as 1 byte
-      result.add(1);
-    } else if elem.is_binary() {
-      result.add_bytesize(unsafe { elem.binary_byte_size() });
-    } else if elem.is_cons() {
-      result.add_bytesize(get_iolist_size(elem));
-    }
-    Ok(())
-  })
-  .unwrap();
-  // unwrap above may panic if you feed a non-iolist or OOM or something
-  result
-}
@@ -103,6 +103,11 @@ unsafe fn format_box_contents(
     boxtype::BOXTYPETAG_EXTERNALPID => write!(f, "ExtPid<>"),
     boxtype::BOXTYPETAG_EXTERNALPORT => write!(f, "ExtPort<>"),
     boxtype::BOXTYPETAG_EXTERNALREF => write!(f, "ExtRef<>"),
+    boxtype::BOXTYPETAG_LOCALREF => {
+      let rptr = trait_ptr as *const boxed::LocalRef;
+      let w = boxed::LocalRef::id_to_words((*rptr).id);
+      write!(f, "#Ref<0.{}.{}.{}>", w[2], w[1], w[0])
+    }
     boxtype::BOXTYPETAG_IMPORT => {
       let iptr = trait_ptr as *const boxed::Import;
       write!(f, "#Import<{}>", (*iptr).mfarity)